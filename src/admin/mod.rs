@@ -0,0 +1,421 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use std::time::{Duration, UNIX_EPOCH};
+use tokio::sync::RwLock;
+use hyper::{Method, Request, Response, StatusCode};
+use http_body_util::Full;
+use bytes::Bytes;
+use serde::Serialize;
+use log::info;
+use crate::core::balancer::LoadBalancer;
+use crate::core::health::HealthCheckRegistry;
+use crate::core::state::StateStore;
+use crate::networking::capture::{self, CaptureStore};
+use crate::networking::proxy::{self, BackendDial};
+
+const REPLAY_TIMEOUT: Duration = Duration::from_secs(5);
+const REPLAY_MAX_RESPONSE: usize = 64 * 1024;
+const PREVIEW_BYTES: usize = 256;
+
+// Shared state behind the admin API (served on the metrics port under /api/)
+#[derive(Default)]
+pub struct AdminState {
+    // Rule Name -> LoadBalancer (kept in sync by hot reload)
+    pub load_balancers: RwLock<HashMap<String, Arc<LoadBalancer>>>,
+    // Rule Name -> debug capture ring
+    pub captures: RwLock<HashMap<String, Arc<CaptureStore>>>,
+    // Rule Name -> listen address (fixed for the life of the rule)
+    pub listen_addrs: RwLock<HashMap<String, String>>,
+    // Rule Name -> how the rule connects to its backends, for capture replays (fixed for the life of the rule)
+    pub backend_dials: RwLock<HashMap<String, Arc<BackendDial>>>,
+    // Running health checkers, for on-demand rechecks
+    pub health_checks: Arc<HealthCheckRegistry>,
+    // `state_file`, when configured (fixed for the life of the process)
+    pub state_store: Option<Arc<StateStore>>,
+}
+
+impl AdminState {
+    // Rules that currently have no backend able to take traffic
+    pub async fn rules_without_backends(&self) -> Vec<String> {
+        let mut rules: Vec<String> = self.load_balancers.read().await.iter()
+            .filter(|(_, lb)| !lb.has_healthy_backend())
+            .map(|(name, _)| name.clone())
+            .collect();
+        rules.sort();
+        rules
+    }
+}
+
+#[derive(Serialize)]
+struct RuleSummary {
+    name: String,
+    listen: String,
+    backends: Vec<BackendSummary>,
+}
+
+#[derive(Serialize)]
+struct BackendSummary {
+    addr: String,
+    healthy: bool,
+    drain: bool,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    admin_drain: bool, // Drained via the admin API (persisted with `state_file`)
+    active_connections: usize,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    removed: bool, // Removed from the config, within its `removal_grace_ms`
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    backup: bool, // In the rule's `backup_backends` pool
+    #[serde(skip_serializing_if = "Option::is_none")]
+    effective_weight: Option<f64>, // Configured weight as scaled by `adaptive_weights`
+}
+
+#[derive(Serialize)]
+struct RecheckResult {
+    rule: String,
+    backend: String,
+    healthy: bool,
+    ready: bool,
+    checks: Vec<CheckOutcome>,
+}
+
+#[derive(Serialize)]
+struct DrainResult {
+    rule: String,
+    backend: String,
+    admin_drain: bool,
+}
+
+#[derive(Serialize)]
+struct CheckOutcome {
+    protocol: String,
+    passed: bool,
+}
+
+#[derive(Serialize)]
+struct ConnectionSummary {
+    id: u64,
+    rule: String,
+    client_addr: String,
+    backend_addr: String,
+    started_at: u64, // Unix seconds
+    duration_ms: u64,
+    client_to_backend_bytes: u64,
+    backend_to_client_bytes: u64,
+}
+
+#[derive(Serialize)]
+struct CaptureSummary {
+    rule: String,
+    id: u64,
+    client_addr: String,
+    backend_addr: String,
+    captured_at: u64, // Unix seconds
+    len: usize,
+    preview: String, // Lossy UTF-8 of the first bytes
+}
+
+pub async fn admin_handler(req: Request<hyper::body::Incoming>, state: Arc<AdminState>) -> Result<Response<Full<Bytes>>, hyper::Error> {
+    Ok(handle(&state, req.method(), req.uri().path(), req.uri().query()).await)
+}
+
+async fn handle(state: &AdminState, method: &Method, path: &str, query: Option<&str>) -> Response<Full<Bytes>> {
+    let segments: Vec<&str> = path.trim_start_matches("/api/").split('/').filter(|s| !s.is_empty()).collect();
+
+    match (method, segments.as_slice()) {
+        (&Method::GET, ["rules"]) => list_rules(state).await,
+        (&Method::POST, ["backends", rule, backend, "check"]) => recheck_backend(state, rule, backend).await,
+        (&Method::POST, ["backends", rule, backend, "drain"]) => set_drain(state, rule, backend, true).await,
+        (&Method::DELETE, ["backends", rule, backend, "drain"]) => set_drain(state, rule, backend, false).await,
+        (&Method::GET, ["connections"]) => list_connections(query_param(query, "rule").as_deref()),
+        (&Method::GET, ["captures"]) => list_captures(state).await,
+        (&Method::GET, ["captures", rule, id]) => get_capture(state, rule, id).await,
+        (&Method::POST, ["captures", rule, id, "replay"]) => {
+            match query_param(query, "backend") {
+                Some(backend) => replay_capture(state, rule, id, &backend).await,
+                None => text(StatusCode::BAD_REQUEST, "missing ?backend=<addr>".to_string()),
+            }
+        }
+        _ => match allowed_methods(&segments) {
+            Some(allow) => Response::builder()
+                .status(StatusCode::METHOD_NOT_ALLOWED)
+                .header("Allow", allow)
+                .header("Content-Type", "text/plain")
+                .body(Full::new(Bytes::from("Method Not Allowed")))
+                .unwrap(),
+            None => text(StatusCode::NOT_FOUND, "Not Found".to_string()),
+        },
+    }
+}
+
+// `Allow` header for a known route, so a wrong method gets 405 rather than 404
+fn allowed_methods(segments: &[&str]) -> Option<&'static str> {
+    match segments {
+        ["rules"] | ["connections"] | ["captures"] | ["captures", _, _] => Some("GET"),
+        ["backends", _, _, "check"] | ["captures", _, _, "replay"] => Some("POST"),
+        ["backends", _, _, "drain"] => Some("POST, DELETE"),
+        _ => None,
+    }
+}
+
+// Point-in-time view of every rule's backends, read from the balancers' current snapshots
+async fn list_rules(state: &AdminState) -> Response<Full<Bytes>> {
+    let load_balancers = state.load_balancers.read().await;
+    let listen_addrs = state.listen_addrs.read().await;
+    let mut rules: Vec<RuleSummary> = load_balancers.iter()
+        .map(|(name, lb)| RuleSummary {
+            name: name.clone(),
+            listen: listen_addrs.get(name).cloned().unwrap_or_default(),
+            backends: lb.backends.load().iter().chain(lb.backup_backends.load().iter()).map(|b| (b.clone(), false))
+                .chain(lb.retiring_backends().into_iter().map(|b| (b, true)))
+                .map(|(b, removed)| BackendSummary {
+                    addr: b.addr.clone(),
+                    healthy: b.healthy.load(Ordering::Relaxed),
+                    drain: removed || b.draining(),
+                    admin_drain: b.admin_drain.load(Ordering::Relaxed),
+                    active_connections: b.active_connections.load(Ordering::Relaxed),
+                    removed,
+                    backup: b.backup,
+                    effective_weight: lb.adaptive_weights_enabled().then(|| b.effective_weight() as f64 / 100.0),
+                })
+                .collect(),
+        })
+        .collect();
+    rules.sort_by(|a, b| a.name.cmp(&b.name));
+    json(StatusCode::OK, &rules)
+}
+
+// Runs the backend's health checks now instead of waiting for the next interval
+async fn recheck_backend(state: &AdminState, rule: &str, backend: &str) -> Response<Full<Bytes>> {
+    let Some(lb) = state.load_balancers.read().await.get(rule).cloned() else {
+        return text(StatusCode::NOT_FOUND, format!("no rule '{}'", rule));
+    };
+    let Some(results) = state.health_checks.check_now(rule, backend, &lb).await else {
+        return text(StatusCode::NOT_FOUND, format!("no health checks for backend {} of rule '{}'", backend, rule));
+    };
+
+    let Some(b) = lb.backend(backend) else {
+        return text(StatusCode::NOT_FOUND, format!("backend {} removed from rule '{}'", backend, rule));
+    };
+    json(StatusCode::OK, &RecheckResult {
+        rule: rule.to_string(),
+        backend: backend.to_string(),
+        healthy: b.healthy.load(Ordering::Relaxed),
+        ready: b.ready.load(Ordering::Relaxed),
+        checks: results.into_iter().map(|(protocol, passed)| CheckOutcome { protocol, passed }).collect(),
+    })
+}
+
+// Open TCP connections with their bytes so far, oldest first
+fn list_connections(rule: Option<&str>) -> Response<Full<Bytes>> {
+    let summaries: Vec<ConnectionSummary> = crate::networking::connections::active(rule).iter()
+        .map(|conn| ConnectionSummary {
+            id: conn.id,
+            rule: conn.rule_name.clone(),
+            client_addr: conn.client_addr.to_string(),
+            backend_addr: conn.backend_addr.clone(),
+            started_at: conn.started_at.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0),
+            duration_ms: conn.started.elapsed().as_millis() as u64,
+            client_to_backend_bytes: conn.bytes.client_to_backend.load(Ordering::Relaxed),
+            backend_to_client_bytes: conn.bytes.backend_to_client.load(Ordering::Relaxed),
+        })
+        .collect();
+    json(StatusCode::OK, &summaries)
+}
+
+// Operational drain on top of the config's `drain`; survives reloads (and
+// restarts with `state_file`). Connections already open are left alone.
+async fn set_drain(state: &AdminState, rule: &str, backend: &str, drained: bool) -> Response<Full<Bytes>> {
+    let Some(lb) = state.load_balancers.read().await.get(rule).cloned() else {
+        return text(StatusCode::NOT_FOUND, format!("no rule '{}'", rule));
+    };
+    if !lb.set_admin_drain(backend, drained) {
+        return text(StatusCode::NOT_FOUND, format!("no backend {} in rule '{}'", backend, rule));
+    }
+    json(StatusCode::OK, &DrainResult { rule: rule.to_string(), backend: backend.to_string(), admin_drain: drained })
+}
+
+async fn list_captures(state: &AdminState) -> Response<Full<Bytes>> {
+    let captures = state.captures.read().await;
+    let mut summaries = Vec::new();
+    for (rule, store) in captures.iter() {
+        for sample in store.list() {
+            summaries.push(CaptureSummary {
+                rule: rule.clone(),
+                id: sample.id,
+                client_addr: sample.client_addr.to_string(),
+                backend_addr: sample.backend_addr.clone(),
+                captured_at: sample.captured_at.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0),
+                len: sample.data.len(),
+                preview: String::from_utf8_lossy(&sample.data[..sample.data.len().min(PREVIEW_BYTES)]).into_owned(),
+            });
+        }
+    }
+    json(StatusCode::OK, &summaries)
+}
+
+async fn find_capture(state: &AdminState, rule: &str, id: &str) -> Result<Arc<capture::CapturedSample>, Response<Full<Bytes>>> {
+    let id: u64 = id.parse().map_err(|_| text(StatusCode::BAD_REQUEST, format!("invalid capture id '{}'", id)))?;
+    let captures = state.captures.read().await;
+    captures.get(rule)
+        .and_then(|store| store.get(id))
+        .ok_or_else(|| text(StatusCode::NOT_FOUND, format!("no capture {} for rule '{}'", id, rule)))
+}
+
+async fn get_capture(state: &AdminState, rule: &str, id: &str) -> Response<Full<Bytes>> {
+    match find_capture(state, rule, id).await {
+        Ok(sample) => bytes(StatusCode::OK, sample.data.clone()),
+        Err(resp) => resp,
+    }
+}
+
+async fn replay_capture(state: &AdminState, rule: &str, id: &str, backend: &str) -> Response<Full<Bytes>> {
+    let sample = match find_capture(state, rule, id).await {
+        Ok(sample) => sample,
+        Err(resp) => return resp,
+    };
+
+    // Only the rule's own backends: the admin port must not relay captured
+    // client bytes to arbitrary addresses
+    let Some(lb) = state.load_balancers.read().await.get(rule).cloned() else {
+        return text(StatusCode::NOT_FOUND, format!("no rule '{}'", rule));
+    };
+    if lb.backend(backend).is_none() {
+        return text(StatusCode::BAD_REQUEST, format!("{} is not a backend of rule '{}'", backend, rule));
+    }
+    let Some(dial) = state.backend_dials.read().await.get(rule).cloned() else {
+        return text(StatusCode::NOT_FOUND, format!("no rule '{}'", rule));
+    };
+
+    info!("[{}] Replaying capture {} ({} bytes) against {}", rule, sample.id, sample.data.len(), backend);
+    let open = proxy::open_backend(&dial, &lb, backend, sample.client_addr, sample.local_addr, rule);
+    let res = match tokio::time::timeout(REPLAY_TIMEOUT, open).await {
+        Ok(Ok(stream)) => capture::replay(stream, &sample.data, backend, REPLAY_TIMEOUT, REPLAY_MAX_RESPONSE).await,
+        Ok(Err(e)) => Err(e),
+        Err(_) => Err(anyhow::anyhow!("connect timed out after {:?}", REPLAY_TIMEOUT)),
+    };
+    match res {
+        Ok(response) => bytes(StatusCode::OK, response),
+        Err(e) => text(StatusCode::BAD_GATEWAY, format!("replay to {} failed: {}", backend, e)),
+    }
+}
+
+// Percent-decoded, so `?backend=%5B::1%5D:80` names an IPv6 backend
+fn query_param(query: Option<&str>, name: &str) -> Option<String> {
+    form_urlencoded::parse(query?.as_bytes())
+        .find(|(k, _)| k == name)
+        .map(|(_, v)| v.into_owned())
+}
+
+fn json<T: Serialize>(status: StatusCode, value: &T) -> Response<Full<Bytes>> {
+    match serde_json::to_vec(value) {
+        Ok(body) => Response::builder()
+            .status(status)
+            .header("Content-Type", "application/json")
+            .body(Full::new(Bytes::from(body)))
+            .unwrap(),
+        Err(e) => text(StatusCode::INTERNAL_SERVER_ERROR, format!("Error: {}", e)),
+    }
+}
+
+fn bytes(status: StatusCode, body: Vec<u8>) -> Response<Full<Bytes>> {
+    Response::builder()
+        .status(status)
+        .header("Content-Type", "application/octet-stream")
+        .body(Full::new(Bytes::from(body)))
+        .unwrap()
+}
+
+fn text(status: StatusCode, body: String) -> Response<Full<Bytes>> {
+    Response::builder()
+        .status(status)
+        .header("Content-Type", "text/plain")
+        .body(Full::new(Bytes::from(body)))
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{BackendConfig, CaptureConfig, HealthCheckConfig, Strategy};
+
+    async fn state_with_rule(name: &str, backends: &[&str]) -> AdminState {
+        let state = AdminState::default();
+        let lb = LoadBalancer::new(name.to_string(), backends.iter().map(|b| BackendConfig::Simple(b.to_string())).collect(), None, Strategy::RoundRobin);
+        state.load_balancers.write().await.insert(name.to_string(), Arc::new(lb));
+        state
+    }
+
+    #[tokio::test]
+    async fn test_replay_only_to_rule_backends() {
+        let state = state_with_rule("web", &["127.0.0.1:9"]).await;
+        let store = Arc::new(CaptureStore::new(CaptureConfig { enabled: true, sample_rate: 1.0, max_bytes: 16, max_samples: 4 }));
+        let client = "10.0.0.1:5000".parse().unwrap();
+        store.record(client, "10.0.0.100:80".parse().unwrap(), "127.0.0.1:9".to_string(), b"GET /".to_vec());
+        state.captures.write().await.insert("web".to_string(), store);
+
+        let response = handle(&state, &Method::POST, "/api/captures/web/1/replay", Some("backend=169.254.169.254:80")).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let response = handle(&state, &Method::POST, "/api/captures/web/1/replay", None).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let response = handle(&state, &Method::POST, "/api/captures/web/2/replay", Some("backend=127.0.0.1:9")).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_drain_routes() {
+        let state = state_with_rule("web", &["127.0.0.1:8001", "127.0.0.1:8002"]).await;
+        let lb = state.load_balancers.read().await.get("web").cloned().unwrap();
+
+        let response = handle(&state, &Method::POST, "/api/backends/web/127.0.0.1:8001/drain", None).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(lb.backend("127.0.0.1:8001").unwrap().admin_drain.load(Ordering::Relaxed));
+        assert!((0..4).all(|_| lb.next_backend().unwrap().0 == "127.0.0.1:8002"));
+
+        let response = handle(&state, &Method::DELETE, "/api/backends/web/127.0.0.1:8001/drain", None).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(!lb.backend("127.0.0.1:8001").unwrap().admin_drain.load(Ordering::Relaxed));
+        assert!((0..4).any(|_| lb.next_backend().unwrap().0 == "127.0.0.1:8001"));
+
+        let response = handle(&state, &Method::POST, "/api/backends/api/127.0.0.1:8001/drain", None).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let response = handle(&state, &Method::POST, "/api/backends/web/127.0.0.1:9999/drain", None).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let response = handle(&state, &Method::GET, "/api/backends/web/127.0.0.1:8001/drain", None).await;
+        assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(response.headers()["Allow"], "POST, DELETE");
+    }
+
+    #[tokio::test]
+    async fn test_check_route() {
+        let state = state_with_rule("web", &["127.0.0.1:1", "127.0.0.1:2"]).await; // Nothing listens on port 1
+        let lb = state.load_balancers.read().await.get("web").cloned().unwrap();
+        let check: HealthCheckConfig = serde_yaml::from_str("{ enabled: true, interval_ms: 60000, timeout_ms: 100, protocol: tcp }").unwrap();
+        state.health_checks.sync("web", &lb, &["127.0.0.1:1".to_string()], &[check]).await;
+
+        let response = handle(&state, &Method::POST, "/api/backends/web/127.0.0.1:1/check", None).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(!lb.backend("127.0.0.1:1").unwrap().healthy.load(Ordering::Relaxed));
+
+        let response = handle(&state, &Method::POST, "/api/backends/api/127.0.0.1:1/check", None).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        // Unknown backend, and a known one without checks
+        let response = handle(&state, &Method::POST, "/api/backends/web/127.0.0.1:9999/check", None).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let response = handle(&state, &Method::POST, "/api/backends/web/127.0.0.1:2/check", None).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let response = handle(&state, &Method::GET, "/api/backends/web/127.0.0.1:1/check", None).await;
+        assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+
+        let response = handle(&state, &Method::GET, "/api/nothing", None).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn test_query_param_decodes_values() {
+        assert_eq!(query_param(Some("rule=web&backend=%5B::1%5D:80"), "backend").as_deref(), Some("[::1]:80"));
+        assert_eq!(query_param(Some("backend=%5B%3A%3A1%5D%3A80"), "backend").as_deref(), Some("[::1]:80"));
+        assert_eq!(query_param(Some("rule=web"), "backend"), None);
+        assert_eq!(query_param(None, "rule"), None);
+    }
+}
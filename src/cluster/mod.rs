@@ -1,3 +1,5 @@
+pub mod usage;
+
 use foca::{Foca, Config, Identity, BroadcastHandler, BincodeCodec, Invalidates};
 use std::net::SocketAddr;
 use std::sync::Arc;
@@ -8,6 +10,10 @@ use rand::{rngs::StdRng, SeedableRng};
 use std::time::Duration;
 use bytes::Bytes;
 use std::fmt;
+use log::{error, info, warn};
+
+// Seeds are re-announced at this interval while no member is known
+const REJOIN_INTERVAL: Duration = Duration::from_secs(5);
 
 // --- Data Structures ---
 
@@ -61,8 +67,15 @@ impl Identity for NodeIdentity {
 // Commands from Application to Cluster
 #[derive(Debug)]
 pub enum ClusterCommand {
-    #[allow(dead_code)]
-    BroadcastUsage(String, u32),
+    BroadcastUsage(String, u32), // Key, cumulative usage (see usage::UsageSync)
+    Leave(tokio::sync::oneshot::Sender<()>), // Announce departure, then stop; replies once sent
+}
+
+// Events from Cluster to Application
+#[derive(Debug)]
+pub enum ClusterEvent {
+    Usage(u64, String, u32), // Node ID, key, cumulative usage
+    NodeDown(u64), // Left or failed; its usage counts are stale
 }
 
 // ...
@@ -88,7 +101,7 @@ impl std::error::Error for ClusterError {}
 // --- Custom Broadcast Handler ---
 
 struct SimpleBroadcastHandler {
-    tx_state: mpsc::Sender<(u64, String, u32)>, 
+    tx_state: mpsc::Sender<ClusterEvent>, 
 }
 
 impl BroadcastHandler<NodeIdentity> for SimpleBroadcastHandler {
@@ -107,7 +120,7 @@ impl BroadcastHandler<NodeIdentity> for SimpleBroadcastHandler {
         match msg {
             BroadcastMessage::UsageUpdate { node_id, key, usage } => {
                 let bkey = BroadcastKey { node_id, key: key.clone() };
-                let _ = self.tx_state.try_send((node_id, key, usage));
+                let _ = self.tx_state.try_send(ClusterEvent::Usage(node_id, key, usage));
                 Ok(Some(bkey))
             }
         }
@@ -128,14 +141,16 @@ pub struct Cluster {
     socket: Arc<UdpSocket>,
     rx_cmd: mpsc::Receiver<ClusterCommand>,
     identity: NodeIdentity,
+    seeds: Vec<SocketAddr>,
+    tx_state: mpsc::Sender<ClusterEvent>,
 }
 
 impl Cluster {
     pub async fn new(
         bind_addr: SocketAddr, 
-        _peers: Vec<SocketAddr>,
+        seeds: Vec<SocketAddr>,
         rx_cmd: mpsc::Receiver<ClusterCommand>,
-        tx_state: mpsc::Sender<(u64, String, u32)>
+        tx_state: mpsc::Sender<ClusterEvent>
     ) -> Result<Self, anyhow::Error> {
         let socket = UdpSocket::bind(bind_addr).await?;
         let socket = Arc::new(socket);
@@ -171,19 +186,31 @@ impl Cluster {
             socket,
             rx_cmd,
             identity,
+            seeds,
+            tx_state,
         })
     }
 
-    pub async fn run(mut self, _seeds: Vec<SocketAddr>) {
+    pub async fn run(mut self) {
         let mut buf = vec![0u8; 65535];
         let mut timer = tokio::time::interval(Duration::from_millis(100));
+
+        let mut runtime = foca::AccumulatingRuntime::new();
+        self.announce_to_seeds(&mut runtime);
+        self.handle_runtime(runtime).await;
+        let mut last_announce = tokio::time::Instant::now();
         
-        loop {
+        let left = loop {
             // We use AccumulatingRuntime to capture actions from Foca
             let mut runtime = foca::AccumulatingRuntime::new();
             
             tokio::select! {
                 _ = timer.tick() => {
+                     // Seeds that were down at startup: keep trying until someone answers
+                     if self.foca.num_members() == 0 && last_announce.elapsed() >= REJOIN_INTERVAL {
+                         self.announce_to_seeds(&mut runtime);
+                         last_announce = tokio::time::Instant::now();
+                     }
                      // Periodic Gossip triggering
                      if let Err(e) = self.foca.gossip(&mut runtime) {
                          eprintln!("Foca gossip error: {:?}", e);
@@ -216,14 +243,40 @@ impl Cluster {
                                  }
                              }
                          }
+                         ClusterCommand::Leave(done) => break done,
                      }
                 }
             }
             
             self.handle_runtime(runtime).await;
+        };
+
+        // Peers mark us down right away instead of waiting out failure detection
+        let mut runtime = foca::AccumulatingRuntime::new();
+        if let Err(e) = self.foca.leave_cluster(&mut runtime) {
+            error!("Foca leave error: {:?}", e);
         }
+        while let Some((dst, data)) = runtime.to_send() {
+            let _ = self.socket.send_to(&data, dst.addr).await;
+        }
+        info!("Left cluster");
+        let _ = left.send(());
     }
     
+    // The seed's real identity (random id) is learned from its reply, so the
+    // announce goes to a placeholder identity at the seed's address.
+    fn announce_to_seeds(&mut self, runtime: &mut foca::AccumulatingRuntime<NodeIdentity>) {
+        for seed in &self.seeds {
+            if *seed == self.identity.addr {
+                continue;
+            }
+            let join_identity = NodeIdentity { addr: *seed, id: 0 };
+            if let Err(e) = self.foca.announce(join_identity, &mut *runtime) {
+                warn!("Failed to announce to seed {}: {:?}", seed, e);
+            }
+        }
+    }
+
     async fn handle_runtime(&mut self, mut runtime: foca::AccumulatingRuntime<NodeIdentity>) {
         // Drain to_send
         while let Some((dst, data)) = runtime.to_send() {
@@ -233,8 +286,16 @@ impl Cluster {
         // Drain notifications
         while let Some(notification) = runtime.to_notify() {
             match notification {
-                foca::OwnedNotification::MemberUp(m) => println!("Cluster: Member UP {:?}", m),
-                foca::OwnedNotification::MemberDown(m) => println!("Cluster: Member DOWN {:?}", m),
+                foca::OwnedNotification::MemberUp(m) => {
+                    if self.seeds.contains(&m.addr) {
+                        info!("Cluster: joined via seed {} (node {})", m.addr, m.id);
+                    }
+                    println!("Cluster: Member UP {:?}", m)
+                }
+                foca::OwnedNotification::MemberDown(m) => {
+                    println!("Cluster: Member DOWN {:?}", m);
+                    let _ = self.tx_state.send(ClusterEvent::NodeDown(m.id)).await;
+                }
                  foca::OwnedNotification::Active => println!("Cluster: Active"),
                  foca::OwnedNotification::Idle => println!("Cluster: Idle"),
                  foca::OwnedNotification::Defunct => println!("Cluster: Defunct"),
@@ -0,0 +1,151 @@
+use std::time::{Duration, Instant};
+use dashmap::DashMap;
+use crate::traffic::limiter::RateLimiter;
+
+/// How often locally consumed rate limit tokens are gossiped to the cluster
+pub const DEFAULT_USAGE_SYNC_INTERVAL_MS: u64 = 100;
+
+/// How often keys idle for longer than their limiter's `idle_ttl_ms` are forgotten
+pub const USAGE_PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Cluster-wide view of rate limit usage.
+///
+/// Every node runs the full `requests_per_second` bucket for a key, and every
+/// admission anywhere in the cluster drains it: local ones when they happen,
+/// remote ones when their usage is gossiped. The buckets still refill at the
+/// configured rate, so N nodes together admit ~R per second, not N×R (plus
+/// whatever arrives within one sync interval of gossip lag).
+///
+/// Usage is gossiped as a cumulative count per key ("<rule>/<limiter key>"), so
+/// a newer update can supersede an older one still queued for dissemination
+/// without losing any admissions. A count lower than the last one seen means the
+/// sender pruned the key and started over, so it counts in full.
+#[derive(Default)]
+pub struct UsageSync {
+    local: DashMap<String, LocalUsage>,
+    remote: DashMap<(u64, String), (u32, Instant)>, // Last cumulative count seen per (node, key), and when
+    limiters: DashMap<String, RateLimiter>, // Rule name -> its limiter
+}
+
+struct LocalUsage {
+    total: u32, // Wrapping; receivers read a wrap like a restart
+    dirty: bool, // Changed since the last broadcast
+    last_used: Instant,
+}
+
+impl UsageSync {
+    pub fn register(&self, rule_name: &str, limiter: RateLimiter) {
+        self.limiters.insert(rule_name.to_string(), limiter);
+    }
+
+    pub fn unregister(&self, rule_name: &str) {
+        self.limiters.remove(rule_name);
+    }
+
+    // One token consumed locally for `key`
+    pub fn record(&self, rule_name: &str, key: &str) {
+        let mut usage = self.local.entry(format!("{}/{}", rule_name, key))
+            .or_insert_with(|| LocalUsage { total: 0, dirty: false, last_used: Instant::now() });
+        usage.total = usage.total.wrapping_add(1);
+        usage.dirty = true;
+        usage.last_used = Instant::now();
+    }
+
+    /// Cumulative counts of the keys used since the last call, to broadcast
+    pub fn take_dirty(&self) -> Vec<(String, u32)> {
+        self.local.iter_mut()
+            .filter_map(|mut usage| {
+                std::mem::take(&mut usage.dirty).then(|| (usage.key().clone(), usage.total))
+            })
+            .collect()
+    }
+
+    /// Folds a remote node's cumulative count into the local buckets
+    pub fn apply_remote(&self, node_id: u64, key: &str, total: u32) {
+        let previous = self.remote.insert((node_id, key.to_string()), (total, Instant::now())).map(|(count, _)| count);
+        let delta = match previous {
+            Some(previous) if previous <= total => total - previous,
+            _ => total,
+        };
+        if delta == 0 {
+            return;
+        }
+        let Some((rule_name, limiter_key)) = key.rsplit_once('/') else {
+            return;
+        };
+        if let Some(limiter) = self.limiters.get(rule_name) {
+            limiter.consume_remote(limiter_key, delta);
+        }
+    }
+
+    /// Forgets keys idle for longer than their rule's limiter `idle_ttl_ms`, like the
+    /// limiters themselves; those of removed rules go right away. Local keys still
+    /// waiting to be broadcast are kept.
+    pub fn prune(&self) {
+        let idle = |key: &str, since: Instant| {
+            let ttl = key.rsplit_once('/').and_then(|(rule_name, _)| self.limiters.get(rule_name).map(|l| l.idle_ttl()));
+            ttl.is_none_or(|ttl| since.elapsed() >= ttl)
+        };
+        self.local.retain(|key, usage| usage.dirty || !idle(key, usage.last_used));
+        self.remote.retain(|(_, key), (_, seen)| !idle(key, *seen));
+    }
+
+    /// Forgets the counts of a node that left or failed; it rejoins under a new id
+    pub fn remove_node(&self, node_id: u64) {
+        self.remote.retain(|(node, _), _| *node != node_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{RateLimitConfig, RateLimitScope};
+    use std::net::IpAddr;
+
+    #[test]
+    fn test_remote_usage_drains_local_bucket() {
+        let sync = std::sync::Arc::new(UsageSync::default());
+        let config = RateLimitConfig { enabled: true, requests_per_second: 1, burst: 4, scope: RateLimitScope::Global, ..Default::default() };
+        let limiter = RateLimiter::new(config).with_cluster("web".to_string(), sync.clone());
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+
+        assert!(limiter.check(ip));
+        assert_eq!(sync.take_dirty(), vec![("web/global".to_string(), 1)]);
+        assert!(sync.take_dirty().is_empty());
+
+        // Another node admitted 2: cumulative counts, so a repeat changes nothing
+        sync.apply_remote(7, "web/global", 2);
+        sync.apply_remote(7, "web/global", 2);
+        assert!(limiter.check(ip));
+        assert!(!limiter.check(ip)); // 1 local + 2 remote + 1 local = burst of 4
+
+        // Unknown rules are ignored
+        sync.apply_remote(7, "other/global", 5);
+    }
+
+    #[test]
+    fn test_idle_and_departed_usage_is_forgotten() {
+        let sync = std::sync::Arc::new(UsageSync::default());
+        let config = RateLimitConfig { enabled: true, requests_per_second: 1, burst: 4, idle_ttl_ms: Some(30), ..Default::default() };
+        let limiter = RateLimiter::new(config).with_cluster("web".to_string(), sync.clone());
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+
+        assert!(limiter.check(ip));
+        sync.apply_remote(7, "web/10.0.0.1", 1);
+        sync.apply_remote(8, "web/10.0.0.1", 1);
+        sync.prune();
+        assert_eq!(sync.local.len(), 1); // Not broadcast yet
+
+        sync.take_dirty();
+        sync.remove_node(8);
+        assert_eq!(sync.remote.len(), 1);
+        std::thread::sleep(Duration::from_millis(50));
+        sync.prune();
+        assert!(sync.local.is_empty() && sync.remote.is_empty());
+
+        // A lower count means node 7 pruned the key and started over
+        sync.apply_remote(7, "web/10.0.0.1", 5);
+        sync.apply_remote(7, "web/10.0.0.1", 2);
+        assert_eq!(sync.remote.get(&(7, "web/10.0.0.1".to_string())).unwrap().0, 2);
+    }
+}
@@ -10,6 +10,8 @@ pub enum LbError {
     Anyhow(#[from] anyhow::Error),
     #[error("TLS Error: {0}")]
     Tls(String),
+    #[error("PROXY protocol Error: {0}")]
+    ProxyProtocol(String),
 }
 
 pub type Result<T> = std::result::Result<T, LbError>;
@@ -0,0 +1,302 @@
+use std::fmt;
+use std::io::ErrorKind;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf};
+
+/// Time of the last byte moved on a connection, shared by both of its streams
+pub struct Activity {
+    start: Instant,
+    last_ms: AtomicU64, // Since `start`
+}
+
+impl Activity {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self { start: Instant::now(), last_ms: AtomicU64::new(0) })
+    }
+
+    pub fn touch(&self) {
+        self.last_ms.store(self.start.elapsed().as_millis() as u64, Ordering::Relaxed);
+    }
+
+    pub fn idle_for(&self) -> Duration {
+        self.start.elapsed().saturating_sub(Duration::from_millis(self.last_ms.load(Ordering::Relaxed)))
+    }
+
+    /// Resolves once nothing was read or written for `timeout`
+    pub async fn idle_expired(&self, timeout: Duration) {
+        loop {
+            let idle = self.idle_for();
+            if idle >= timeout {
+                return;
+            }
+            tokio::time::sleep(timeout - idle).await;
+        }
+    }
+}
+
+/// Stream wrapper that records every successful read/write in an `Activity`
+pub struct ActivityStream<S> {
+    inner: S,
+    activity: Arc<Activity>,
+}
+
+impl<S> ActivityStream<S> {
+    pub fn new(inner: S, activity: Arc<Activity>) -> Self {
+        Self { inner, activity }
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for ActivityStream<S> {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<std::io::Result<()>> {
+        let this = self.get_mut();
+        let before = buf.filled().len();
+        let res = Pin::new(&mut this.inner).poll_read(cx, buf);
+        if buf.filled().len() > before {
+            this.activity.touch();
+        }
+        res
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for ActivityStream<S> {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<std::io::Result<usize>> {
+        let this = self.get_mut();
+        let res = Pin::new(&mut this.inner).poll_write(cx, buf);
+        if matches!(res, Poll::Ready(Ok(n)) if n > 0) {
+            this.activity.touch();
+        }
+        res
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+/// Bytes relayed so far on one connection, readable while it is open
+#[derive(Default)]
+pub struct ByteCounters {
+    pub client_to_backend: AtomicU64,
+    pub backend_to_client: AtomicU64,
+}
+
+/// Client side of a relay that adds what it reads (bound for the backend) and
+/// writes (from the backend) to `ByteCounters`
+pub struct CountingStream<S> {
+    inner: S,
+    counters: Arc<ByteCounters>,
+}
+
+impl<S> CountingStream<S> {
+    pub fn new(inner: S, counters: Arc<ByteCounters>) -> Self {
+        Self { inner, counters }
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for CountingStream<S> {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<std::io::Result<()>> {
+        let this = self.get_mut();
+        let before = buf.filled().len();
+        let res = Pin::new(&mut this.inner).poll_read(cx, buf);
+        this.counters.client_to_backend.fetch_add((buf.filled().len() - before) as u64, Ordering::Relaxed);
+        res
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for CountingStream<S> {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<std::io::Result<usize>> {
+        let this = self.get_mut();
+        let res = Pin::new(&mut this.inner).poll_write(cx, buf);
+        if let Poll::Ready(Ok(n)) = res {
+            this.counters.backend_to_client.fetch_add(n as u64, Ordering::Relaxed);
+        }
+        res
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+/// Inner error of an I/O error caused by the backend dropping its side of a
+/// relay mid-stream (reset, broken pipe, a write that takes no bytes), as
+/// opposed to a fault on the client's side or ours
+#[derive(Debug)]
+pub struct BackendClosed(std::io::Error);
+
+impl fmt::Display for BackendClosed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "backend closed mid-stream: {}", self.0)
+    }
+}
+
+impl std::error::Error for BackendClosed {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.0)
+    }
+}
+
+/// Tags an error from the backend's socket as `BackendClosed` when it means
+/// the backend went away; other errors pass through unchanged
+pub fn from_backend(e: std::io::Error) -> std::io::Error {
+    match e.kind() {
+        ErrorKind::ConnectionReset | ErrorKind::ConnectionAborted | ErrorKind::BrokenPipe
+        | ErrorKind::UnexpectedEof | ErrorKind::WriteZero => std::io::Error::new(e.kind(), BackendClosed(e)),
+        _ => e,
+    }
+}
+
+pub fn is_backend_closed(e: &std::io::Error) -> bool {
+    e.get_ref().is_some_and(|inner| inner.is::<BackendClosed>())
+}
+
+/// Backend side of a relay: its errors go through `from_backend`, and a write
+/// that accepts no bytes is reported as such rather than left to the copy loop
+pub struct BackendStream<S> {
+    inner: S,
+}
+
+impl<S> BackendStream<S> {
+    pub fn new(inner: S) -> Self {
+        Self { inner }
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for BackendStream<S> {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_read(cx, buf).map_err(from_backend)
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for BackendStream<S> {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<std::io::Result<usize>> {
+        match Pin::new(&mut self.get_mut().inner).poll_write(cx, buf) {
+            Poll::Ready(Ok(0)) if !buf.is_empty() => Poll::Ready(Err(from_backend(ErrorKind::WriteZero.into()))),
+            res => res.map_err(from_backend),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx).map_err(from_backend)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx).map_err(from_backend)
+    }
+}
+
+/// Relays between two streams until both directions have hit EOF, returning
+/// the bytes moved (a -> b, b -> a). Each direction runs on its own: an EOF
+/// from one side only shuts down the other side's write half, so a peer that
+/// half-closes can still be sent to (and keep sending) until it closes fully.
+pub async fn copy_bidirectional<A, B>(a: A, b: B, buffer_size: usize) -> std::io::Result<(u64, u64)>
+where
+    A: AsyncRead + AsyncWrite + Unpin,
+    B: AsyncRead + AsyncWrite + Unpin,
+{
+    let (mut a_read, mut a_write) = tokio::io::split(a);
+    let (mut b_read, mut b_write) = tokio::io::split(b);
+    tokio::try_join!(
+        copy_one_way(&mut a_read, &mut b_write, buffer_size),
+        copy_one_way(&mut b_read, &mut a_write, buffer_size),
+    )
+}
+
+async fn copy_one_way<R, W>(from: &mut R, to: &mut W, buffer_size: usize) -> std::io::Result<u64>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut buf = vec![0u8; buffer_size.max(1)];
+    let mut total = 0u64;
+    loop {
+        let n = from.read(&mut buf).await?;
+        if n == 0 {
+            // EOF: pass the half-close on. The peer may already be gone, which
+            // must not fail the direction still in flight.
+            let _ = to.shutdown().await;
+            return Ok(total);
+        }
+        to.write_all(&buf[..n]).await?;
+        // Buffering writers (TLS) must not sit on data while we wait for more
+        to.flush().await?;
+        total += n as u64;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_activity_resets_idle_timer() {
+        let (a, mut b) = tokio::io::duplex(64);
+        let activity = Activity::new();
+        let mut stream = ActivityStream::new(a, activity.clone());
+
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert!(activity.idle_for() >= Duration::from_millis(60));
+        b.write_all(b"ping").await.unwrap();
+        let mut buf = [0u8; 4];
+        stream.read_exact(&mut buf).await.unwrap();
+        assert!(activity.idle_for() < Duration::from_millis(60));
+
+        let start = Instant::now();
+        activity.idle_expired(Duration::from_millis(50)).await;
+        assert!(start.elapsed() >= Duration::from_millis(45));
+    }
+
+    #[tokio::test]
+    async fn test_backend_closed_is_tagged() {
+        // Backend gone: the relay's write to it fails as BackendClosed
+        let (a, b) = tokio::io::duplex(64);
+        drop(b);
+        let mut backend = BackendStream::new(a);
+        let err = backend.write_all(b"request").await.unwrap_err();
+        assert!(is_backend_closed(&err), "{:?}", err);
+
+        // Other failures are left alone
+        assert!(!is_backend_closed(&from_backend(ErrorKind::TimedOut.into())));
+        assert!(!is_backend_closed(&std::io::Error::from(ErrorKind::BrokenPipe)));
+    }
+
+    #[tokio::test]
+    async fn test_copy_bidirectional_half_close() {
+        let (mut client, client_side) = tokio::io::duplex(64);
+        let (backend_side, mut backend) = tokio::io::duplex(64);
+        let relay = tokio::spawn(copy_bidirectional(client_side, backend_side, 16));
+
+        // Backend sends its greeting and half-closes (SMTP QUIT-style), but keeps reading
+        backend.write_all(b"220 bye").await.unwrap();
+        backend.shutdown().await.unwrap();
+        let mut greeting = Vec::new();
+        client.read_to_end(&mut greeting).await.unwrap();
+        assert_eq!(greeting, b"220 bye");
+
+        // The client keeps sending after seeing EOF; none of it may be cut off
+        let payload: Vec<u8> = (0..1000u32).map(|i| i as u8).collect();
+        let expected = payload.clone();
+        let writer = tokio::spawn(async move {
+            client.write_all(&payload).await.unwrap();
+            client.shutdown().await.unwrap();
+        });
+        let mut received = Vec::new();
+        backend.read_to_end(&mut received).await.unwrap();
+        assert_eq!(received, expected);
+        writer.await.unwrap();
+
+        assert_eq!(relay.await.unwrap().unwrap(), (1000, 7));
+    }
+}
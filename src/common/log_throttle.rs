@@ -0,0 +1,172 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use dashmap::DashMap;
+use lazy_static::lazy_static;
+use crate::traffic::limiter::SimpleLimiter;
+
+// Max identical error lines per key (rule/backend) per second before suppression kicks in.
+const ERROR_LOGS_PER_SEC: u32 = 5;
+// How often suppressed counts are reported for keys that have gone quiet
+const SUMMARY_INTERVAL: Duration = Duration::from_secs(10);
+// Keys quiet for this long are forgotten, so one-off keys (e.g. removed backends) don't pile up
+const IDLE_TTL: Duration = Duration::from_secs(300);
+
+lazy_static! {
+    pub static ref ERROR_LOG_THROTTLE: LogThrottle = LogThrottle::new(ERROR_LOGS_PER_SEC);
+}
+
+struct ThrottleEntry {
+    limiter: SimpleLimiter,
+    suppressed: AtomicU64,
+}
+
+/// Rate gate for hot log paths. Each key (e.g. "rule/backend") may log `per_sec`
+/// lines per second; anything above that is counted and reported as a summary
+/// the next time the key is allowed to log again, or by the periodic summary
+/// task if the errors stop.
+pub struct LogThrottle {
+    per_sec: u32,
+    entries: DashMap<String, Arc<ThrottleEntry>>,
+}
+
+impl LogThrottle {
+    pub fn new(per_sec: u32) -> Self {
+        LogThrottle {
+            per_sec: per_sec.max(1),
+            entries: DashMap::new(),
+        }
+    }
+
+    /// Returns `Some(suppressed)` if a line for `key` may be logged now, where
+    /// `suppressed` is the number of lines dropped since the last one logged.
+    /// Returns `None` if the line should be dropped.
+    pub fn check(&self, key: &str) -> Option<u64> {
+        let entry = match self.entries.get(key) {
+            Some(e) => e.clone(),
+            None => self.entries.entry(key.to_string()).or_insert_with(|| {
+                Arc::new(ThrottleEntry {
+                    limiter: SimpleLimiter::new(self.per_sec, self.per_sec),
+                    suppressed: AtomicU64::new(0),
+                })
+            }).value().clone(),
+        };
+
+        if entry.limiter.check_n(1).is_ok() {
+            Some(entry.suppressed.swap(0, Ordering::Relaxed))
+        } else {
+            entry.suppressed.fetch_add(1, Ordering::Relaxed);
+            None
+        }
+    }
+
+    /// Takes the suppressed counts of every key that dropped lines since they
+    /// were last reported
+    pub fn take_suppressed(&self) -> Vec<(String, u64)> {
+        self.entries.iter()
+            .filter_map(|entry| {
+                let suppressed = entry.suppressed.swap(0, Ordering::Relaxed);
+                (suppressed > 0).then(|| (entry.key().clone(), suppressed))
+            })
+            .collect()
+    }
+
+    /// Drops keys that have not logged for `ttl` and have no suppressed count
+    /// left to report
+    pub fn prune_idle(&self, ttl: Duration) {
+        self.entries.retain(|_, entry| {
+            Arc::strong_count(entry) > 1 || entry.suppressed.load(Ordering::Relaxed) > 0 || entry.limiter.idle_for() < ttl
+        });
+    }
+}
+
+/// Reports `ERROR_LOG_THROTTLE` suppression every `SUMMARY_INTERVAL`, so the
+/// count of a burst that stopped is not lost waiting for the key's next line
+pub fn spawn_summary_task() {
+    tokio::spawn(async {
+        let mut ticker = tokio::time::interval(SUMMARY_INTERVAL);
+        ticker.tick().await;
+        loop {
+            ticker.tick().await;
+            for (key, suppressed) in ERROR_LOG_THROTTLE.take_suppressed() {
+                log::error!("[{}] suppressed {} similar messages", key, suppressed);
+            }
+            ERROR_LOG_THROTTLE.prune_idle(IDLE_TTL);
+        }
+    });
+}
+
+/// `error!` gated by `ERROR_LOG_THROTTLE` on the given key. When lines were dropped
+/// since the last emitted one, a "suppressed N messages" summary is logged first.
+#[macro_export]
+macro_rules! throttled_error {
+    ($key:expr, $($arg:tt)+) => {
+        if let Some(suppressed) = $crate::common::log_throttle::ERROR_LOG_THROTTLE.check(&$key) {
+            if suppressed > 0 {
+                log::error!("[{}] suppressed {} similar messages", $key, suppressed);
+            }
+            log::error!($($arg)+);
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_throttle_suppresses_and_reports() {
+        let throttle = LogThrottle::new(2);
+
+        // Burst of 2 allowed, nothing suppressed yet
+        assert_eq!(throttle.check("App/10.0.0.1:80"), Some(0));
+        assert_eq!(throttle.check("App/10.0.0.1:80"), Some(0));
+
+        // Over the limit -> dropped
+        assert_eq!(throttle.check("App/10.0.0.1:80"), None);
+        assert_eq!(throttle.check("App/10.0.0.1:80"), None);
+
+        // Other keys are gated independently
+        assert_eq!(throttle.check("App/10.0.0.2:80"), Some(0));
+
+        // Once tokens refill, the suppressed count is reported and reset
+        std::thread::sleep(std::time::Duration::from_millis(600));
+        assert_eq!(throttle.check("App/10.0.0.1:80"), Some(2));
+        assert_eq!(throttle.check("App/10.0.0.1:80"), None);
+    }
+
+    #[test]
+    fn test_take_suppressed_reports_quiet_keys() {
+        let throttle = LogThrottle::new(1);
+        assert_eq!(throttle.check("App/10.0.0.1:80"), Some(0));
+        assert_eq!(throttle.check("App/10.0.0.1:80"), None);
+        assert_eq!(throttle.check("App/10.0.0.1:80"), None);
+        assert_eq!(throttle.check("App/10.0.0.2:80"), Some(0));
+
+        // Only keys with drops are reported, and each count only once
+        assert_eq!(throttle.take_suppressed(), vec![("App/10.0.0.1:80".to_string(), 2)]);
+        assert!(throttle.take_suppressed().is_empty());
+    }
+
+    #[test]
+    fn test_prune_idle_keeps_unreported_counts() {
+        let throttle = LogThrottle::new(1);
+        assert_eq!(throttle.check("App/10.0.0.1:80"), Some(0));
+        assert_eq!(throttle.check("App/10.0.0.2:80"), Some(0));
+        assert_eq!(throttle.check("App/10.0.0.2:80"), None);
+
+        // Recently used keys survive
+        throttle.prune_idle(Duration::from_secs(60));
+        assert_eq!(throttle.entries.len(), 2);
+
+        // Idle keys go, except one still holding a suppressed count
+        throttle.prune_idle(Duration::ZERO);
+        assert_eq!(throttle.entries.len(), 1);
+        assert!(throttle.entries.contains_key("App/10.0.0.2:80"));
+
+        // Once reported it can go too
+        assert_eq!(throttle.take_suppressed(), vec![("App/10.0.0.2:80".to_string(), 1)]);
+        throttle.prune_idle(Duration::ZERO);
+        assert!(throttle.entries.is_empty());
+    }
+}
@@ -1,3 +1,4 @@
 pub mod error;
 pub mod io;
-
+pub mod log_throttle;
+pub mod rule_log;
@@ -0,0 +1,113 @@
+use std::collections::HashMap;
+use std::sync::OnceLock;
+use arc_swap::ArcSwap;
+use lazy_static::lazy_static;
+use log::{LevelFilter, Log, Metadata, Record, SetLoggerError};
+
+#[derive(Default)]
+struct RuleLevels {
+    levels: HashMap<String, LevelFilter>,
+    max: LevelFilter,
+}
+
+lazy_static! {
+    static ref RULE_LEVELS: ArcSwap<RuleLevels> = ArcSwap::from_pointee(RuleLevels::default());
+}
+
+// Level of the global filter (`log.level` / RUST_LOG), set once by `init`
+static GLOBAL_LEVEL: OnceLock<LevelFilter> = OnceLock::new();
+
+/// env_logger with per-rule overrides. Lines of this crate tagged with a rule
+/// ("[<rule>] ...") pass if either the global filter or that rule's
+/// `log_level` allows them, so one rule can log at debug on its own.
+struct RuleLogger {
+    global: env_logger::Logger,
+    unfiltered: env_logger::Logger, // Same output, used for lines admitted by a rule override
+}
+
+impl Log for RuleLogger {
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        self.global.enabled(metadata) || metadata.level() <= RULE_LEVELS.load().max
+    }
+
+    fn log(&self, record: &Record<'_>) {
+        if self.global.matches(record) {
+            self.global.log(record);
+            return;
+        }
+        if rule_admits(&RULE_LEVELS.load(), record) {
+            self.unfiltered.log(record);
+        }
+    }
+
+    fn flush(&self) {
+        self.global.flush();
+    }
+}
+
+/// Installs the logger. `level` (from `log.level`) wins; without it RUST_LOG
+/// applies, and "info" if neither is set.
+pub fn init(level: Option<LevelFilter>) -> Result<(), SetLoggerError> {
+    let global = match level {
+        Some(level) => env_logger::Builder::new().filter_level(level).build(),
+        None => env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).build(),
+    };
+    let unfiltered = env_logger::Builder::new().filter_level(LevelFilter::Trace).build();
+    let _ = GLOBAL_LEVEL.set(global.filter());
+    log::set_boxed_logger(Box::new(RuleLogger { global, unfiltered }))?;
+    log::set_max_level(global_level());
+    Ok(())
+}
+
+/// Replaces the per-rule overrides (rule name -> level), e.g. after a config reload
+pub fn set_rule_levels(levels: HashMap<String, LevelFilter>) {
+    let max = levels.values().copied().max().unwrap_or(LevelFilter::Off);
+    RULE_LEVELS.store(std::sync::Arc::new(RuleLevels { levels, max }));
+    log::set_max_level(global_level().max(max));
+}
+
+// Whether a line the global filter rejected passes its rule's `log_level`
+fn rule_admits(rules: &RuleLevels, record: &Record<'_>) -> bool {
+    if record.level() > rules.max || !record.target().starts_with(env!("CARGO_CRATE_NAME")) {
+        return false;
+    }
+    // Only formatted when some rule asked for more than the global level
+    let message = record.args().to_string();
+    rule_of(&message).and_then(|rule| rules.levels.get(rule)).is_some_and(|level| record.level() <= *level)
+}
+
+fn global_level() -> LevelFilter {
+    GLOBAL_LEVEL.get().copied().unwrap_or(LevelFilter::Info)
+}
+
+// "[web] Proxy error ..." -> "web"
+fn rule_of(message: &str) -> Option<&str> {
+    message.strip_prefix('[')?.split_once(']').map(|(rule, _)| rule)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rule_of() {
+        assert_eq!(rule_of("[web] Proxy error (backend 10.0.0.1:80): reset"), Some("web"));
+        assert_eq!(rule_of("[] empty"), Some(""));
+        assert_eq!(rule_of("Backend 10.0.0.1:80 marked HEALTHY"), None);
+        assert_eq!(rule_of("[unterminated"), None);
+    }
+
+    #[test]
+    fn test_rule_level_gates_health_lines() {
+        let levels = HashMap::from([("web".to_string(), LevelFilter::Debug), ("api".to_string(), LevelFilter::Warn)]);
+        let rules = RuleLevels { levels, max: LevelFilter::Debug };
+        let admits = |rule: &str| rule_admits(&rules, &Record::builder()
+            .args(format_args!("[{}] TCP check failed for 10.0.0.1:80: connection refused", rule))
+            .level(log::Level::Debug)
+            .target(concat!(env!("CARGO_CRATE_NAME"), "::core::health"))
+            .build());
+        assert!(admits("web"));
+        assert!(!admits("api"));
+        assert!(!admits("other"));
+    }
+}
@@ -1,5 +1,8 @@
 use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::Path;
 use thiserror::Error;
+use crate::networking::socket::{MIN_SOCKET_BUFFER, MAX_SOCKET_BUFFER};
 
 #[derive(Debug, Error)]
 pub enum ConfigError {
@@ -7,6 +10,26 @@ pub enum ConfigError {
     MissingField(String),
     #[error("Invalid configuration: {0}")]
     InvalidValue(String),
+    #[error("Failed to parse {0:?} configuration: {1}")]
+    Parse(ConfigFormat, String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigFormat {
+    Yaml,
+    Json,
+    Toml,
+}
+
+impl ConfigFormat {
+    // By file extension; anything unrecognised is treated as YAML
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()).map(str::to_ascii_lowercase).as_deref() {
+            Some("json") => ConfigFormat::Json,
+            Some("toml") => ConfigFormat::Toml,
+            _ => ConfigFormat::Yaml,
+        }
+    }
 }
 
 #[derive(Debug, Deserialize, Clone, PartialEq)]
@@ -19,11 +42,123 @@ pub struct Config {
     
     // Logging Configuration (Optional)
     pub log: Option<LogConfig>,
+
+    // Metrics / Admin HTTP server (Optional, defaults to 0.0.0.0:9091)
+    pub metrics: Option<MetricsConfig>,
+
+    // Process-wide health check settings (Optional)
+    pub health_checks: Option<HealthChecksConfig>,
+
+    // New TCP connections accepted per second by the whole process, across all rules (Optional)
+    pub accept_rate_per_sec: Option<u32>,
+
+    // JSON file keeping admin-API drains and outlier ejections across restarts (Optional, read at startup)
+    pub state_file: Option<String>,
+}
+
+impl Config {
+    pub fn parse(content: &str, format: ConfigFormat) -> Result<Self, ConfigError> {
+        let content = expand_env(content, format != ConfigFormat::Json, |name| std::env::var(name).ok())?;
+        let parsed = match format {
+            ConfigFormat::Yaml => serde_yaml::from_str(&content).map_err(|e| e.to_string()),
+            ConfigFormat::Json => serde_json::from_str(&content).map_err(|e| e.to_string()),
+            ConfigFormat::Toml => toml::from_str(&content).map_err(|e| e.to_string()),
+        };
+        parsed.map_err(|e| ConfigError::Parse(format, e))
+    }
+
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let content = std::fs::read_to_string(path)?;
+        Ok(Self::parse(&content, ConfigFormat::from_path(path))?)
+    }
+}
+
+// Expands `${VAR}` and `${VAR:-default}` in the raw config text; `$${` is a literal `${`.
+// With `comments` (YAML, TOML), `#` comments are left as they are, so a commented-out
+// line may still name a variable that is no longer set.
+fn expand_env(content: &str, comments: bool, lookup: impl Fn(&str) -> Option<String>) -> Result<String, ConfigError> {
+    let mut out = String::with_capacity(content.len());
+    for line in content.split_inclusive('\n') {
+        let (text, comment) = line.split_at(if comments { comment_start(line) } else { line.len() });
+        expand_line(text, &lookup, &mut out)?;
+        out.push_str(comment);
+    }
+    Ok(out)
+}
+
+fn expand_line(line: &str, lookup: &impl Fn(&str) -> Option<String>, out: &mut String) -> Result<(), ConfigError> {
+    let mut rest = line;
+    while let Some(start) = rest.find('$') {
+        out.push_str(&rest[..start]);
+        let tail = &rest[start..];
+        if let Some(after) = tail.strip_prefix("$${") {
+            out.push_str("${");
+            rest = after;
+            continue;
+        }
+        if !tail.starts_with("${") {
+            out.push('$');
+            rest = &tail[1..];
+            continue;
+        }
+        let end = tail.find('}').ok_or_else(|| {
+            ConfigError::InvalidValue(format!("Unterminated '${{' in config: {}", tail.lines().next().unwrap_or(tail)))
+        })?;
+        let (name, default) = match tail[2..end].split_once(":-") {
+            Some((name, default)) => (name, Some(default)),
+            None => (&tail[2..end], None),
+        };
+        match lookup(name).or_else(|| default.map(String::from)) {
+            Some(value) => out.push_str(&value),
+            None => return Err(ConfigError::MissingField(format!("environment variable '{}' is not set and has no default", name))),
+        }
+        rest = &tail[end + 1..];
+    }
+    out.push_str(rest);
+    Ok(())
+}
+
+// Byte offset where a `#` comment starts in `line` (its length if none): a `#` at the
+// start of the line or after whitespace, outside quoted strings
+fn comment_start(line: &str) -> usize {
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+    let mut prev = ' ';
+    for (i, c) in line.char_indices() {
+        match quote {
+            Some('"') if escaped => escaped = false,
+            Some('"') if c == '\\' => escaped = true,
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if c == '"' || c == '\'' => quote = Some(c),
+            None if c == '#' && prev.is_whitespace() => return i,
+            None => {}
+        }
+        prev = c;
+    }
+    line.len()
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct MetricsConfig {
+    pub listen: String, // e.g., "0.0.0.0:9100"
+    pub connection_duration_buckets: Option<Vec<f64>>, // Upper bounds in seconds; read at startup only
 }
 
 #[derive(Debug, Deserialize, Clone, PartialEq)]
 pub struct LogConfig {
-    pub level: String,
+    pub level: String, // off, error, warn, info, debug or trace; overrides RUST_LOG
+}
+
+impl LogConfig {
+    pub fn level_filter(&self) -> Option<log::LevelFilter> {
+        self.level.parse().ok()
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct HealthChecksConfig {
+    pub max_concurrent_probes: Option<usize>, // Cap on periodic probes in flight across all rules
 }
 
 #[derive(Debug, Deserialize, Clone, PartialEq)]
@@ -31,6 +166,7 @@ pub struct ClusterConfig {
     pub enabled: bool,
     pub bind_addr: String, // e.g., "0.0.0.0:9090"
     pub peers: Vec<String>, // Seed peers e.g. ["10.0.0.2:9090"]
+    pub sync_interval_ms: Option<u64>, // Rate limit usage gossip interval, default 100
 }
 
 #[derive(Debug, Deserialize, Clone, PartialEq)]
@@ -41,33 +177,285 @@ pub enum BackendConfig {
         addr: String,
         #[serde(default = "default_drain")]
         drain: bool,
+        #[serde(default = "default_weight")]
+        weight: u32, // Relative share of connections; 0 = excluded from selection
+        #[serde(default)]
+        dns: bool, // Re-resolve `addr` every `dns_refresh_ms`, one backend per returned address
+        proxy_protocol: Option<bool>, // Overrides the rule's `proxy_protocol` for this backend
+        nodelay: Option<bool>, // Overrides the rule's `nodelay` for connections to this backend
+    }
+}
+
+/// Most ports one `listen` range may cover
+pub const MAX_LISTEN_PORTS: usize = 1024;
+
+// "ip:port", or "ip:first-last" for every port of an inclusive range
+fn parse_listen(listen: &str) -> Option<Vec<std::net::SocketAddr>> {
+    if let Ok(addr) = listen.parse::<std::net::SocketAddr>() {
+        return Some(vec![addr]);
+    }
+    let (host, ports) = listen.rsplit_once(':')?;
+    let (first, last) = ports.split_once('-')?;
+    let (first, last) = (first.parse::<u16>().ok()?, last.parse::<u16>().ok()?);
+    if first == 0 || first > last {
+        return None;
+    }
+    let ip = format!("{}:0", host).parse::<std::net::SocketAddr>().ok()?.ip();
+    Some((first..=last).map(|port| std::net::SocketAddr::new(ip, port)).collect())
+}
+
+// "ip:port", "[ipv6]:port" or "hostname:port"; hostnames are resolved at connect time
+fn is_host_port(addr: &str) -> bool {
+    if addr.parse::<std::net::SocketAddr>().is_ok() {
+        return true;
     }
+    let Some((host, port)) = addr.rsplit_once(':') else {
+        return false;
+    };
+    let valid_port = port.parse::<u16>().is_ok_and(|p| p != 0);
+    let valid_host = !host.is_empty()
+        && !host.starts_with('[') // Bracketed but not a valid IPv6 address
+        && host.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.' || c == '_');
+    valid_port && valid_host
+}
+
+fn default_reuse_port() -> bool {
+    true
 }
 
 fn default_drain() -> bool {
     false
 }
 
+fn default_weight() -> u32 {
+    1
+}
+
+impl BackendConfig {
+    pub fn addr(&self) -> &str {
+        match self {
+            BackendConfig::Simple(addr) => addr,
+            BackendConfig::Detailed { addr, .. } => addr,
+        }
+    }
+
+    pub fn drain(&self) -> bool {
+        match self {
+            BackendConfig::Simple(_) => false,
+            BackendConfig::Detailed { drain, .. } => *drain,
+        }
+    }
+
+    pub fn weight(&self) -> u32 {
+        match self {
+            BackendConfig::Simple(_) => default_weight(),
+            BackendConfig::Detailed { weight, .. } => *weight,
+        }
+    }
+
+    pub fn dns(&self) -> bool {
+        match self {
+            BackendConfig::Simple(_) => false,
+            BackendConfig::Detailed { dns, .. } => *dns,
+        }
+    }
+
+    pub fn proxy_protocol(&self) -> Option<bool> {
+        match self {
+            BackendConfig::Simple(_) => None,
+            BackendConfig::Detailed { proxy_protocol, .. } => *proxy_protocol,
+        }
+    }
+
+    pub fn nodelay(&self) -> Option<bool> {
+        match self {
+            BackendConfig::Simple(_) => None,
+            BackendConfig::Detailed { nodelay, .. } => *nodelay,
+        }
+    }
+}
+
 #[derive(Debug, Deserialize, Clone, PartialEq)]
 pub struct LBRule {
     pub name: String,
     pub listen: String, // e.g., "0.0.0.0:8080"
     pub backends: Vec<BackendConfig>,
-    pub protocol: Option<String>, // Default TCP
+    #[serde(default)]
+    pub backup_backends: Vec<BackendConfig>, // Used only while no primary backend is available
+    pub dns_refresh_ms: Option<u64>, // Re-resolution interval of `dns: true` backends, default 30s
+    pub removal_grace_ms: Option<u64>, // Backends removed by a reload drain this long, then their connections are closed
+    pub protocol: Option<String>, // "tcp" (default) or "udp"
+    pub log_level: Option<String>, // Per-rule override of log.level, e.g. "debug" for one noisy rule
+    pub udp_idle_timeout_ms: Option<u64>, // UDP session expiry, default 30s
+    pub udp_max_sessions: Option<usize>, // UDP sessions per listen port before new clients are dropped, default 10000
+    #[serde(default)]
+    pub strategy: Strategy,
+    pub sticky: Option<StickyConfig>,
     
     // Per-rule configurations
     #[serde(default)]
     pub proxy_protocol: bool, // Enable Proxy Protocol V2
+    #[serde(default)]
+    pub accept_proxy_protocol: bool, // Require a v1/v2 PROXY header from the upstream proxy
+    #[serde(default)]
+    pub protocol_sniff: bool, // Label connections by their first bytes (tls, http, ssh...) in metrics (TCP)
+    pub nodelay: Option<bool>, // TCP_NODELAY on client and backend sockets, default true; backends may override it
 
     pub tls: Option<TlsConfig>,
     pub backend_tls: Option<BackendTlsConfig>,
     pub rate_limit: Option<RateLimitConfig>,
     pub bandwidth_limit: Option<BandwidthLimitConfig>,
     pub backend_connection_limit: Option<usize>,
-    pub health_check: Option<HealthCheckConfig>,
+    #[serde(default)]
+    pub overflow: Overflow, // Once every backend is at backend_connection_limit
+    pub max_connections: Option<usize>, // Concurrent client connections for the whole rule (TCP)
+
+    // TCP timeouts (none by default)
+    pub connect_timeout_ms: Option<u64>, // Backend connect
+    pub idle_timeout_ms: Option<u64>, // No bytes in either direction; closes client and backend
+    pub max_retries: Option<u32>, // Other backends to try when a backend connect fails (default 0)
+    pub backend_bind_addr: Option<String>, // Source IP of backend connections (TCP), e.g. to pick the egress interface
+    pub backend_pool: Option<BackendPoolConfig>, // Pre-opened backend connections for new clients (TCP)
 
+    // Socket buffer sizes (SO_RCVBUF/SO_SNDBUF) for listener, client and backend sockets
+    pub recv_buffer_bytes: Option<usize>,
+    pub send_buffer_bytes: Option<usize>,
+    pub listen_backlog: Option<u32>, // Pending connection queue per listening socket, default 1024
+    pub acceptors: Option<usize>, // Accept tasks per listen address, default NUM_ACCEPTORS or the CPU count
+    pub accept_error_backoff_max_ms: Option<u64>, // Longest pause after repeated accept errors (e.g. EMFILE), default 1000
+    #[serde(default = "default_reuse_port")]
+    pub reuse_port: bool, // One SO_REUSEPORT socket per acceptor; false shares a single socket
+    pub copy_buffer_size: Option<usize>, // Proxy relay buffer per direction, default 64KB
+    pub health_check: Option<HealthCheckSetting>,
+    pub passive_health: Option<PassiveHealthConfig>,
+    pub outlier_detection: Option<OutlierDetectionConfig>,
+    pub adaptive_weights: Option<AdaptiveWeightsConfig>,
+
+    pub acl: Option<AclConfig>,
+    // Legacy top-level lists, merged into `acl`
     pub allow_list: Option<Vec<String>>,
     pub deny_list: Option<Vec<String>>,
+
+    pub capture: Option<CaptureConfig>,
+}
+
+impl LBRule {
+    pub fn is_udp(&self) -> bool {
+        self.protocol.as_deref().is_some_and(|p| p.eq_ignore_ascii_case("udp"))
+    }
+
+    pub fn log_level_filter(&self) -> Option<log::LevelFilter> {
+        self.log_level.as_deref()?.parse().ok()
+    }
+
+    /// Addresses to bind: the listen address, or one per port of a range.
+    /// None if `listen` is malformed.
+    pub fn listen_addrs(&self) -> Option<Vec<std::net::SocketAddr>> {
+        parse_listen(&self.listen)
+    }
+}
+
+// Source IP filtering: CIDRs or plain IPs. Deny wins; a non-empty allow list denies everything else.
+#[derive(Debug, Deserialize, Clone, PartialEq, Default)]
+pub struct AclConfig {
+    #[serde(default)]
+    pub allow: Vec<String>,
+    #[serde(default)]
+    pub deny: Vec<String>,
+}
+
+// Spare backend connections opened ahead of time; each is used by one client only
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct BackendPoolConfig {
+    #[serde(default = "default_pool_max_idle")]
+    pub max_idle: usize, // Per backend
+    #[serde(default = "default_pool_idle_timeout_ms")]
+    pub idle_timeout_ms: u64, // Unused spares older than this are closed
+}
+
+fn default_pool_max_idle() -> usize {
+    crate::networking::pool::DEFAULT_POOL_MAX_IDLE
+}
+
+fn default_pool_idle_timeout_ms() -> u64 {
+    crate::networking::pool::DEFAULT_POOL_IDLE_TIMEOUT_MS
+}
+
+// Debug capture of the first bytes of sampled connections (replayable via admin API)
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct CaptureConfig {
+    pub enabled: bool,
+    #[serde(default = "default_capture_sample_rate")]
+    pub sample_rate: f64, // 0.0 - 1.0
+    #[serde(default = "default_capture_max_bytes")]
+    pub max_bytes: usize, // Per sample
+    #[serde(default = "default_capture_max_samples")]
+    pub max_samples: usize, // Ring size
+}
+
+fn default_capture_sample_rate() -> f64 {
+    0.01
+}
+
+fn default_capture_max_bytes() -> usize {
+    4096
+}
+
+fn default_capture_max_samples() -> usize {
+    32
+}
+
+// Backend selection strategy
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Strategy {
+    #[default]
+    RoundRobin, // Smooth weighted round-robin (plain round-robin when all weights are equal)
+    LeastConnections, // Fewest active connections, ties broken round-robin
+    WeightedLeastConnections, // Fewest active connections per unit of weight
+    ConsistentHash, // Hash ring keyed on client IP (affinity, stable across backend changes)
+    Maglev, // Maglev lookup table keyed on client IP: like consistent_hash, but evenly spread and weighted
+    #[serde(rename = "p2c")]
+    P2C, // Power of two choices: less loaded of two random backends
+    LeastResponseTime, // Lowest EWMA of recent connection durations, scaled by active connections
+    Random, // Uniformly random among available backends, in proportion to weight
+    // hash(client IP) % available backends: affinity without a ring or table, but
+    // unlike consistent_hash/maglev most clients move whenever that count changes
+    IpHash,
+}
+
+// New connections once every eligible backend is at `backend_connection_limit`
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Overflow {
+    #[default]
+    Reject, // The client is dropped
+    LeastLoaded, // Soft limit: go over it on the backend with the fewest active connections
+}
+
+// Source-IP session affinity on top of the strategy
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct StickyConfig {
+    pub ttl_ms: u64, // Idle time after which a client may be re-balanced
+}
+
+// A single check, or a list of checks (e.g. liveness + readiness) per backend
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum HealthCheckSetting {
+    Single(HealthCheckConfig),
+    Multiple(Vec<HealthCheckConfig>),
+}
+
+impl HealthCheckSetting {
+    // Enabled checks only
+    pub fn checks(&self) -> Vec<HealthCheckConfig> {
+        let all = match self {
+            HealthCheckSetting::Single(c) => vec![c.clone()],
+            HealthCheckSetting::Multiple(list) => list.clone(),
+        };
+        all.into_iter().filter(|c| c.enabled).collect()
+    }
 }
 
 #[derive(Debug, Deserialize, Clone, PartialEq)]
@@ -75,8 +463,127 @@ pub struct HealthCheckConfig {
     pub enabled: bool,
     pub interval_ms: u64,
     pub timeout_ms: u64,
-    pub protocol: String, // "tcp" or "http"
+    pub protocol: String, // "tcp", "http", "grpc" or "udp"
     pub path: Option<String>, // for http
+    pub method: Option<String>, // for http, default GET (e.g. HEAD for endpoints without a body)
+    pub host: Option<String>, // for http, Host header; default the probed addr
+    pub headers: Option<BTreeMap<String, String>>, // for http, extra request headers
+    #[serde(default)]
+    pub tls: bool, // for http: probe over HTTPS
+    #[serde(default)]
+    pub ignore_verify: bool, // with tls: accept any backend certificate
+    pub grpc_service: Option<String>, // for grpc, default "" (whole server)
+    pub expected_statuses: Option<Vec<u16>>, // for http, default [200]
+    pub send: Option<String>, // tcp: bytes written after connecting; udp: probe datagram (default empty)
+    pub expect: Option<String>, // tcp/udp: response must contain it; http: body must contain it
+    #[serde(default = "default_threshold")]
+    pub rise: u32, // Consecutive passes before a failed check counts as passing again
+    #[serde(default = "default_threshold")]
+    pub fall: u32, // Consecutive failures before a passing check counts as failed
+    pub port: Option<u16>, // Probe this port instead of the backend's traffic port
+    pub jitter_ms: Option<u64>, // Max random delay before the first probe, default interval_ms
+    #[serde(default)]
+    pub on_fail: HealthCheckAction,
+}
+
+fn default_threshold() -> u32 {
+    1
+}
+
+// Ejects a backend after repeated connect failures seen on live traffic
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct PassiveHealthConfig {
+    #[serde(default = "default_passive_max_failures")]
+    pub max_failures: u32, // Connect failures within fail_timeout_ms that eject the backend
+    #[serde(default = "default_passive_fail_timeout_ms")]
+    pub fail_timeout_ms: u64, // Counting window, and how long the backend stays out
+}
+
+fn default_passive_max_failures() -> u32 {
+    3
+}
+
+fn default_passive_fail_timeout_ms() -> u64 {
+    10000
+}
+
+// Ejects a backend whose connect error rate is too high, for longer each time it reoffends
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct OutlierDetectionConfig {
+    #[serde(default = "default_outlier_interval_ms")]
+    pub interval_ms: u64, // Window over which the error rate is measured
+    #[serde(default = "default_outlier_error_rate_percent")]
+    pub error_rate_percent: u32, // Connect error rate that ejects the backend
+    #[serde(default = "default_outlier_min_requests")]
+    pub min_requests: u32, // Connects in the window before the rate counts
+    #[serde(default = "default_outlier_base_ejection_ms")]
+    pub base_ejection_ms: u64, // First ejection; doubles for every repeat offense
+    #[serde(default = "default_outlier_max_ejection_ms")]
+    pub max_ejection_ms: u64,
+    #[serde(default = "default_outlier_max_ejection_percent")]
+    pub max_ejection_percent: u32, // Never eject more than this share of the backends
+}
+
+fn default_outlier_interval_ms() -> u64 {
+    10000
+}
+
+fn default_outlier_error_rate_percent() -> u32 {
+    50
+}
+
+fn default_outlier_min_requests() -> u32 {
+    10
+}
+
+fn default_outlier_base_ejection_ms() -> u64 {
+    30000
+}
+
+fn default_outlier_max_ejection_ms() -> u64 {
+    300000
+}
+
+fn default_outlier_max_ejection_percent() -> u32 {
+    50
+}
+
+// Scales each backend's weight by its recent connect error rate and response time
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct AdaptiveWeightsConfig {
+    #[serde(default = "default_adaptive_interval_ms")]
+    pub interval_ms: u64, // How often the weights are recomputed
+    #[serde(default = "default_adaptive_step_percent")]
+    pub step_percent: u32, // Share of the gap to the target weight closed per interval
+    #[serde(default = "default_adaptive_min_weight_percent")]
+    pub min_weight_percent: u32, // Lowest effective weight, as a share of the configured weight
+    #[serde(default = "default_adaptive_max_weight_percent")]
+    pub max_weight_percent: u32,
+}
+
+fn default_adaptive_interval_ms() -> u64 {
+    5000
+}
+
+fn default_adaptive_step_percent() -> u32 {
+    20
+}
+
+fn default_adaptive_min_weight_percent() -> u32 {
+    10
+}
+
+fn default_adaptive_max_weight_percent() -> u32 {
+    200
+}
+
+// What a failing check does to the backend
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum HealthCheckAction {
+    #[default]
+    Eject, // Liveness: mark unhealthy
+    Drain, // Readiness: stop new connections, keep existing ones
 }
 
 #[derive(Debug, Deserialize, Clone, PartialEq)]
@@ -84,13 +591,56 @@ pub struct TlsConfig {
     pub enabled: bool,
     pub cert: String,
     pub key: String,
+    pub client_ca: Option<String>, // CA bundle for verifying client certs (mTLS)
+    #[serde(default)]
+    pub client_auth: ClientAuth,
+    pub client_cert_allow: Option<Vec<String>>, // Client cert CN/SANs let through, exact or "*.example.com"; default any
+    pub min_version: Option<String>, // "1.2" (default) or "1.3"
+    pub cipher_suites: Option<Vec<String>>, // rustls suite names, e.g. TLS13_AES_256_GCM_SHA384; default all
+    pub alpn: Option<Vec<String>>, // Protocols advertised via ALPN in preference order, e.g. ["h2", "http/1.1"]
+    pub session_cache_size: Option<usize>, // Sessions kept for resumption, default 1024; 0 disables resumption
 }
 
-#[derive(Debug, Deserialize, Clone, PartialEq)]
+// Whether clients must present a certificate when client_ca is set
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ClientAuth {
+    #[default]
+    Required, // Handshake fails without a valid client cert
+    Optional, // Anonymous clients allowed, presented certs must still verify
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq, Default)]
 pub struct RateLimitConfig {
     pub enabled: bool,
     pub requests_per_second: u32,
     pub burst: u32,
+    #[serde(default)]
+    pub scope: RateLimitScope,
+    // Share one bucket per subnet (e.g. 24 / 64) instead of per exact IP
+    pub key_prefix_v4: Option<u8>,
+    pub key_prefix_v6: Option<u8>,
+    // Buckets unused for this long are dropped (default 5 minutes)
+    pub idle_ttl_ms: Option<u64>,
+    // How rejected TCP connections are ended; unset drops them straight away
+    pub reject_response: Option<RejectResponse>,
+}
+
+// Goodbye for a rate-limited TCP client, so it backs off instead of retrying hard
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RejectResponse {
+    Close, // Orderly close (FIN) rather than a reset
+    Http, // "429 Too Many Requests" when the first bytes look like an HTTP request, else `close`
+}
+
+// What a token bucket is keyed on
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum RateLimitScope {
+    #[default]
+    PerIp, // One bucket per client IP
+    Global, // One bucket shared by every client of the rule
 }
 
 #[derive(Debug, Deserialize, Clone, PartialEq)]
@@ -98,18 +648,30 @@ pub struct BandwidthLimitConfig {
     pub enabled: bool,
     pub client: Option<ClientBandwidthConfig>,
     pub backend: Option<BackendBandwidthConfig>,
+    #[serde(default = "default_chunk_size")]
+    pub chunk_size: usize, // Bytes per limiter check; raise for 10GbE+ flows
+    pub idle_ttl_ms: Option<u64>, // Client/backend limiters unused for this long are dropped
+    // Aggregate caps shared by every connection of the rule, on top of the per-IP ones
+    pub rule_upload_per_sec: Option<u32>,
+    pub rule_download_per_sec: Option<u32>,
+}
+
+fn default_chunk_size() -> usize {
+    crate::traffic::bandwidth::DEFAULT_CHUNK_SIZE
 }
 
 #[derive(Debug, Deserialize, Clone, PartialEq)]
 pub struct ClientBandwidthConfig {
     pub upload_per_sec: u32,
     pub download_per_sec: u32,
+    pub burst_bytes: Option<u32>, // Bucket size, default 64KB; raised to chunk_size if smaller
 }
 
 #[derive(Debug, Deserialize, Clone, PartialEq)]
 pub struct BackendBandwidthConfig {
     pub upload_per_sec: u32,
     pub download_per_sec: u32,
+    pub burst_bytes: Option<u32>, // Bucket size, default 64KB; raised to chunk_size if smaller
 }
 
 #[derive(Debug, Deserialize, Clone, PartialEq)]
@@ -117,6 +679,7 @@ pub struct BackendTlsConfig {
     pub enabled: bool,
     #[serde(default)]
     pub ignore_verify: bool,
+    pub server_name: Option<String>, // SNI + verification name; defaults to the backend's host
 }
 
 impl Config {
@@ -124,14 +687,462 @@ impl Config {
         if self.rules.is_empty() {
              return Err(ConfigError::MissingField("rules are empty".to_string()));
         }
+        if let Some(metrics) = &self.metrics
+            && metrics.listen.parse::<std::net::SocketAddr>().is_err() {
+                return Err(ConfigError::InvalidValue(format!("metrics.listen '{}' is not a valid socket address", metrics.listen)));
+        }
+        if let Some(buckets) = self.metrics.as_ref().and_then(|m| m.connection_duration_buckets.as_ref())
+            && (buckets.is_empty()
+                || buckets.iter().any(|b| !b.is_finite() || *b <= 0.0)
+                || buckets.windows(2).any(|w| w[0] >= w[1])) {
+                return Err(ConfigError::InvalidValue(format!("metrics.connection_duration_buckets {:?} must be positive and strictly increasing", buckets)));
+        }
+        if let Some(log) = &self.log
+            && log.level_filter().is_none() {
+                return Err(ConfigError::InvalidValue(format!("log.level '{}' is not one of off, error, warn, info, debug, trace", log.level)));
+        }
+        if self.accept_rate_per_sec == Some(0) {
+            return Err(ConfigError::InvalidValue("accept_rate_per_sec must be greater than 0".to_string()));
+        }
+        if self.health_checks.as_ref().is_some_and(|hc| hc.max_concurrent_probes == Some(0)) {
+            return Err(ConfigError::InvalidValue("health_checks.max_concurrent_probes must be greater than 0".to_string()));
+        }
+        if self.state_file.as_deref().is_some_and(|path| path.trim().is_empty()) {
+            return Err(ConfigError::InvalidValue("state_file must not be empty".to_string()));
+        }
+        if self.cluster.as_ref().is_some_and(|c| c.sync_interval_ms == Some(0)) {
+            return Err(ConfigError::InvalidValue("cluster.sync_interval_ms must be greater than 0".to_string()));
+        }
         for (i, rule) in self.rules.iter().enumerate() {
+            // Rules are keyed by name at runtime, and each (listen address, protocol) binds one socket
+            let listen_addrs = rule.listen_addrs().unwrap_or_default();
+            for (j, other) in self.rules[..i].iter().enumerate() {
+                if other.name == rule.name {
+                    return Err(ConfigError::InvalidValue(format!("Rules at index {} and {} share the same name '{}'", j, i, rule.name)));
+                }
+                if other.is_udp() == rule.is_udp()
+                    && let Some(addr) = other.listen_addrs().unwrap_or_default().into_iter().find(|addr| listen_addrs.contains(addr)) {
+                        return Err(ConfigError::InvalidValue(format!("Rules '{}' and '{}' both listen on {} ({})",
+                            other.name, rule.name, addr, if rule.is_udp() { "udp" } else { "tcp" })));
+                }
+            }
             if rule.backends.is_empty() {
                 return Err(ConfigError::InvalidValue(format!("Rule '{}' (index {}) has no backends", rule.name, i)));
             }
             if rule.listen.is_empty() {
                  return Err(ConfigError::InvalidValue(format!("Rule '{}' has no listen address", rule.name)));
             }
+            if listen_addrs.is_empty() {
+                return Err(ConfigError::InvalidValue(format!("Rule '{}' listen '{}' is not a valid socket address (ip:port or ip:first-last)", rule.name, rule.listen)));
+            }
+            if listen_addrs.len() > MAX_LISTEN_PORTS {
+                return Err(ConfigError::InvalidValue(format!("Rule '{}' listen '{}' covers {} ports, more than {}", rule.name, rule.listen, listen_addrs.len(), MAX_LISTEN_PORTS)));
+            }
+            if let Some(backend) = rule.backends.iter().find(|b| !is_host_port(b.addr())) {
+                return Err(ConfigError::InvalidValue(format!("Rule '{}' backend '{}' is not a valid host:port address", rule.name, backend.addr())));
+            }
+            for backup in &rule.backup_backends {
+                if !is_host_port(backup.addr()) {
+                    return Err(ConfigError::InvalidValue(format!("Rule '{}' backup backend '{}' is not a valid host:port address", rule.name, backup.addr())));
+                }
+                if backup.dns() {
+                    return Err(ConfigError::InvalidValue(format!("Rule '{}' backup backend '{}': dns is not supported for backup backends", rule.name, backup.addr())));
+                }
+                if rule.backends.iter().any(|b| b.addr() == backup.addr()) {
+                    return Err(ConfigError::InvalidValue(format!("Rule '{}' lists '{}' as both a backend and a backup backend", rule.name, backup.addr())));
+                }
+            }
+            if rule.overflow != Overflow::Reject && rule.backend_connection_limit.is_none() {
+                return Err(ConfigError::InvalidValue(format!("Rule '{}' sets overflow but no backend_connection_limit", rule.name)));
+            }
+            if rule.dns_refresh_ms == Some(0) {
+                return Err(ConfigError::InvalidValue(format!("Rule '{}' dns_refresh_ms must be greater than 0", rule.name)));
+            }
+            match rule.protocol.as_deref().map(str::to_ascii_lowercase).as_deref() {
+                None | Some("tcp") => {}
+                Some("udp") => {
+                    if rule.accept_proxy_protocol {
+                        return Err(ConfigError::InvalidValue(format!("Rule '{}' uses protocol udp, which does not support accept_proxy_protocol", rule.name)));
+                    }
+                    if rule.protocol_sniff {
+                        return Err(ConfigError::InvalidValue(format!("Rule '{}' uses protocol udp, which does not support protocol_sniff", rule.name)));
+                    }
+                    if rule.tls.as_ref().is_some_and(|t| t.enabled) || rule.backend_tls.as_ref().is_some_and(|t| t.enabled) {
+                        return Err(ConfigError::InvalidValue(format!("Rule '{}' uses protocol udp, which does not support TLS", rule.name)));
+                    }
+                    if rule.udp_idle_timeout_ms == Some(0) || rule.udp_max_sessions == Some(0) {
+                        return Err(ConfigError::InvalidValue(format!("Rule '{}' udp_idle_timeout_ms and udp_max_sessions must be greater than 0", rule.name)));
+                    }
+                }
+                Some(other) => {
+                    return Err(ConfigError::InvalidValue(format!("Rule '{}' has unknown protocol '{}' (expected tcp or udp)", rule.name, other)));
+                }
+            }
+            if let Some(tls) = &rule.tls {
+                if tls.min_version.as_deref().is_some_and(|v| !matches!(v, "1.2" | "1.3")) {
+                    return Err(ConfigError::InvalidValue(format!("Rule '{}' tls.min_version must be \"1.2\" or \"1.3\"", rule.name)));
+                }
+                if tls.cipher_suites.as_ref().is_some_and(|s| s.is_empty()) {
+                    return Err(ConfigError::InvalidValue(format!("Rule '{}' tls.cipher_suites must not be empty", rule.name)));
+                }
+                if tls.alpn.as_ref().is_some_and(|protocols| protocols.iter().any(|p| p.is_empty() || p.len() > 255)) {
+                    return Err(ConfigError::InvalidValue(format!("Rule '{}' tls.alpn protocols must be 1 to 255 bytes", rule.name)));
+                }
+                if let Some(allow) = &tls.client_cert_allow {
+                    if tls.client_ca.is_none() {
+                        return Err(ConfigError::InvalidValue(format!("Rule '{}' tls.client_cert_allow needs tls.client_ca", rule.name)));
+                    }
+                    let invalid = |entry: &&String| {
+                        let name = entry.strip_prefix("*.").unwrap_or(entry);
+                        name.is_empty() || name.contains('*')
+                    };
+                    if let Some(entry) = allow.iter().find(invalid) {
+                        return Err(ConfigError::InvalidValue(format!("Rule '{}' tls.client_cert_allow entry '{}' must be a name, or \"*.\" followed by a name", rule.name, entry)));
+                    }
+                }
+            }
+            for (field, value) in [("recv_buffer_bytes", rule.recv_buffer_bytes), ("send_buffer_bytes", rule.send_buffer_bytes)] {
+                if let Some(size) = value
+                    && !(MIN_SOCKET_BUFFER..=MAX_SOCKET_BUFFER).contains(&size) {
+                        return Err(ConfigError::InvalidValue(format!("Rule '{}' {} must be between {} and {}", rule.name, field, MIN_SOCKET_BUFFER, MAX_SOCKET_BUFFER)));
+                }
+            }
+            if rule.listen_backlog.is_some_and(|backlog| backlog == 0 || backlog > i32::MAX as u32) {
+                return Err(ConfigError::InvalidValue(format!("Rule '{}' listen_backlog must be between 1 and {}", rule.name, i32::MAX)));
+            }
+            if let Some(level) = &rule.log_level
+                && level.parse::<log::LevelFilter>().is_err() {
+                    return Err(ConfigError::InvalidValue(format!("Rule '{}' log_level '{}' is not one of off, error, warn, info, debug, trace", rule.name, level)));
+            }
+            if rule.acceptors == Some(0) {
+                return Err(ConfigError::InvalidValue(format!("Rule '{}' acceptors must be greater than 0", rule.name)));
+            }
+            if rule.copy_buffer_size == Some(0) {
+                return Err(ConfigError::InvalidValue(format!("Rule '{}' copy_buffer_size must be greater than 0", rule.name)));
+            }
+            for (field, value) in [("connect_timeout_ms", rule.connect_timeout_ms), ("idle_timeout_ms", rule.idle_timeout_ms), ("removal_grace_ms", rule.removal_grace_ms), ("accept_error_backoff_max_ms", rule.accept_error_backoff_max_ms)] {
+                if value == Some(0) {
+                    return Err(ConfigError::InvalidValue(format!("Rule '{}' {} must be greater than 0", rule.name, field)));
+                }
+            }
+            if let Some(addr) = &rule.backend_bind_addr
+                && addr.parse::<std::net::IpAddr>().is_err() {
+                    return Err(ConfigError::InvalidValue(format!("Rule '{}' backend_bind_addr '{}' is not a valid IP address", rule.name, addr)));
+            }
+            if let Some(pool) = &rule.backend_pool
+                && (pool.max_idle == 0 || pool.idle_timeout_ms == 0) {
+                    return Err(ConfigError::InvalidValue(format!("Rule '{}' backend_pool.max_idle and backend_pool.idle_timeout_ms must be greater than 0", rule.name)));
+            }
+            if rule.bandwidth_limit.as_ref().is_some_and(|bw| bw.chunk_size == 0) {
+                return Err(ConfigError::InvalidValue(format!("Rule '{}' bandwidth_limit.chunk_size must be greater than 0", rule.name)));
+            }
+            if let Some(bw) = &rule.bandwidth_limit {
+                for (field, burst) in [
+                    ("client.burst_bytes", bw.client.as_ref().and_then(|c| c.burst_bytes)),
+                    ("backend.burst_bytes", bw.backend.as_ref().and_then(|b| b.burst_bytes)),
+                ] {
+                    if burst == Some(0) {
+                        return Err(ConfigError::InvalidValue(format!("Rule '{}' bandwidth_limit.{} must be at least 1", rule.name, field)));
+                    }
+                }
+            }
+            for (field, ttl) in [
+                ("rate_limit.idle_ttl_ms", rule.rate_limit.as_ref().and_then(|rl| rl.idle_ttl_ms)),
+                ("bandwidth_limit.idle_ttl_ms", rule.bandwidth_limit.as_ref().and_then(|bw| bw.idle_ttl_ms)),
+            ] {
+                if ttl == Some(0) {
+                    return Err(ConfigError::InvalidValue(format!("Rule '{}' {} must be greater than 0", rule.name, field)));
+                }
+            }
+            if let Some(rl) = &rule.rate_limit {
+                if rl.key_prefix_v4.is_some_and(|p| p > 32) {
+                    return Err(ConfigError::InvalidValue(format!("Rule '{}' rate_limit.key_prefix_v4 must be at most 32", rule.name)));
+                }
+                if rl.key_prefix_v6.is_some_and(|p| p > 128) {
+                    return Err(ConfigError::InvalidValue(format!("Rule '{}' rate_limit.key_prefix_v6 must be at most 128", rule.name)));
+                }
+            }
+            let checks = rule.health_check.as_ref().map(|hc| hc.checks()).unwrap_or_default();
+            if checks.iter().any(|c| c.rise == 0 || c.fall == 0) {
+                return Err(ConfigError::InvalidValue(format!("Rule '{}' health_check rise/fall must be at least 1", rule.name)));
+            }
+            for check in &checks {
+                // Written verbatim into the probe request
+                let is_token = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_graphic() && !b":()<>@,;\\\"/[]?={}".contains(&b));
+                let is_value = |s: &str| !s.bytes().any(|b| b == b'\r' || b == b'\n');
+                if check.method.as_deref().is_some_and(|m| !is_token(m))
+                    || check.host.as_deref().is_some_and(|h| h.is_empty() || !is_value(h))
+                    || check.headers.iter().flatten().any(|(name, value)| !is_token(name) || !is_value(value)) {
+                    return Err(ConfigError::InvalidValue(format!("Rule '{}' health_check method, host or headers contain invalid characters", rule.name)));
+                }
+                if check.tls && check.protocol != "http" {
+                    return Err(ConfigError::InvalidValue(format!("Rule '{}' health_check tls is only supported with protocol http", rule.name)));
+                }
+            }
+            if rule.passive_health.as_ref().is_some_and(|p| p.max_failures == 0 || p.fail_timeout_ms == 0) {
+                return Err(ConfigError::InvalidValue(format!("Rule '{}' passive_health max_failures and fail_timeout_ms must be greater than 0", rule.name)));
+            }
+            if let Some(od) = &rule.outlier_detection {
+                if od.interval_ms == 0 || od.base_ejection_ms == 0 || od.min_requests == 0 {
+                    return Err(ConfigError::InvalidValue(format!("Rule '{}' outlier_detection interval_ms, base_ejection_ms and min_requests must be greater than 0", rule.name)));
+                }
+                if od.max_ejection_ms < od.base_ejection_ms {
+                    return Err(ConfigError::InvalidValue(format!("Rule '{}' outlier_detection max_ejection_ms must be at least base_ejection_ms", rule.name)));
+                }
+                if !(1..=100).contains(&od.error_rate_percent) || od.max_ejection_percent > 100 {
+                    return Err(ConfigError::InvalidValue(format!("Rule '{}' outlier_detection error_rate_percent must be 1-100 and max_ejection_percent at most 100", rule.name)));
+                }
+            }
+            if let Some(aw) = &rule.adaptive_weights {
+                if aw.interval_ms == 0 || !(1..=100).contains(&aw.step_percent) {
+                    return Err(ConfigError::InvalidValue(format!("Rule '{}' adaptive_weights interval_ms must be greater than 0 and step_percent 1-100", rule.name)));
+                }
+                if !(1..=100).contains(&aw.min_weight_percent) || !(100..=1000).contains(&aw.max_weight_percent) {
+                    return Err(ConfigError::InvalidValue(format!("Rule '{}' adaptive_weights min_weight_percent must be 1-100 and max_weight_percent 100-1000", rule.name)));
+                }
+            }
+            if let Some(capture) = &rule.capture {
+                if !(0.0..=1.0).contains(&capture.sample_rate) {
+                    return Err(ConfigError::InvalidValue(format!("Rule '{}' capture.sample_rate must be between 0 and 1", rule.name)));
+                }
+                if capture.max_bytes == 0 || capture.max_bytes > crate::networking::capture::MAX_CAPTURE_BYTES {
+                    return Err(ConfigError::InvalidValue(format!("Rule '{}' capture.max_bytes must be between 1 and {}", rule.name, crate::networking::capture::MAX_CAPTURE_BYTES)));
+                }
+            }
         }
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_from_path() {
+        assert_eq!(ConfigFormat::from_path(Path::new("lb.yaml")), ConfigFormat::Yaml);
+        assert_eq!(ConfigFormat::from_path(Path::new("lb.yml")), ConfigFormat::Yaml);
+        assert_eq!(ConfigFormat::from_path(Path::new("/etc/lb/lb.JSON")), ConfigFormat::Json);
+        assert_eq!(ConfigFormat::from_path(Path::new("lb.toml")), ConfigFormat::Toml);
+        assert_eq!(ConfigFormat::from_path(Path::new("lb.conf")), ConfigFormat::Yaml);
+        assert_eq!(ConfigFormat::from_path(Path::new("lb")), ConfigFormat::Yaml);
+    }
+
+    #[test]
+    fn test_same_config_in_every_format() {
+        let yaml = r#"
+rules:
+  - name: "web"
+    listen: "0.0.0.0:8080"
+    backends:
+      - "127.0.0.1:9001"
+      - addr: "127.0.0.1:9002"
+        weight: 3
+    rate_limit:
+      enabled: true
+      requests_per_second: 100
+      burst: 20
+      scope: "global"
+    health_check:
+      enabled: true
+      interval_ms: 1000
+      timeout_ms: 200
+      protocol: "tcp"
+metrics:
+  listen: "127.0.0.1:9100"
+"#;
+        let json = r#"{
+  "rules": [{
+    "name": "web",
+    "listen": "0.0.0.0:8080",
+    "backends": ["127.0.0.1:9001", {"addr": "127.0.0.1:9002", "weight": 3}],
+    "rate_limit": {"enabled": true, "requests_per_second": 100, "burst": 20, "scope": "global"},
+    "health_check": {"enabled": true, "interval_ms": 1000, "timeout_ms": 200, "protocol": "tcp"}
+  }],
+  "metrics": {"listen": "127.0.0.1:9100"}
+}"#;
+        let toml = r#"
+[metrics]
+listen = "127.0.0.1:9100"
+
+[[rules]]
+name = "web"
+listen = "0.0.0.0:8080"
+backends = ["127.0.0.1:9001", { addr = "127.0.0.1:9002", weight = 3 }]
+
+[rules.rate_limit]
+enabled = true
+requests_per_second = 100
+burst = 20
+scope = "global"
+
+[rules.health_check]
+enabled = true
+interval_ms = 1000
+timeout_ms = 200
+protocol = "tcp"
+"#;
+        let from_yaml = Config::parse(yaml, ConfigFormat::Yaml).unwrap();
+        assert_eq!(from_yaml.rules[0].backends.len(), 2);
+        assert_eq!(from_yaml.rules[0].rate_limit.as_ref().unwrap().scope, RateLimitScope::Global);
+        assert_eq!(Config::parse(json, ConfigFormat::Json).unwrap(), from_yaml);
+        assert_eq!(Config::parse(toml, ConfigFormat::Toml).unwrap(), from_yaml);
+    }
+
+    #[test]
+    fn test_expand_env() {
+        let lookup = |name: &str| match name {
+            "CERT_DIR" => Some("/etc/certs".to_string()),
+            "EMPTY" => Some(String::new()),
+            _ => None,
+        };
+        assert_eq!(expand_env("cert: \"${CERT_DIR}/server.crt\"", true, lookup).unwrap(), "cert: \"/etc/certs/server.crt\"");
+        assert_eq!(expand_env("listen: \"${LISTEN:-0.0.0.0:8080}\"", true, lookup).unwrap(), "listen: \"0.0.0.0:8080\"");
+        assert_eq!(expand_env("a: ${EMPTY:-x}", true, lookup).unwrap(), "a: "); // Set but empty wins over the default
+        assert_eq!(expand_env("cost: $5 and $${LITERAL}", true, lookup).unwrap(), "cost: $5 and ${LITERAL}");
+
+        let err = expand_env("backends: [\"${BACKEND}\"]", true, lookup).unwrap_err();
+        assert!(err.to_string().contains("'BACKEND'"), "{}", err);
+        assert!(expand_env("x: ${CERT_DIR", true, lookup).is_err());
+
+        // Commented-out lines and trailing comments are not expanded, `#` inside quotes is
+        let yaml = "backends:\n  # - \"${OLD_BACKEND}\"\n  - \"${BACKEND:-10.0.0.1:80}\" # was ${OLD}\ncert: \"a #${CERT_DIR}\"\n";
+        assert_eq!(expand_env(yaml, true, lookup).unwrap(),
+            "backends:\n  # - \"${OLD_BACKEND}\"\n  - \"10.0.0.1:80\" # was ${OLD}\ncert: \"a #/etc/certs\"\n");
+        assert!(expand_env("a = 1 # ${OLD}", true, lookup).is_ok());
+        assert!(expand_env("{\"a\": \"# ${OLD}\"}", false, lookup).is_err()); // JSON has no comments
+    }
+
+    fn rules_config(rules: &str) -> Config {
+        Config::parse(&format!("rules: {}", rules), ConfigFormat::Yaml).unwrap()
+    }
+
+    #[test]
+    fn test_health_check_header_injection_rejected() {
+        let check = |extra: &str| rules_config(&format!(r#"[{{ name: "web", listen: "0.0.0.0:8080", backends: ["127.0.0.1:9001"],
+            health_check: {{ enabled: true, interval_ms: 1000, timeout_ms: 200, protocol: "http", {} }} }}]"#, extra)).validate();
+        assert!(check(r#"method: "HEAD", host: "api.example.com", headers: { X-Probe: "1" }"#).is_ok());
+        assert!(check(r#"host: "a\r\nX-Evil: 1""#).is_err());
+        assert!(check(r#"headers: { "Bad Name": "1" }"#).is_err());
+        assert!(check(r#"method: "GET /x""#).is_err());
+    }
+
+    #[test]
+    fn test_health_check_tls_needs_http() {
+        let check = |protocol: &str| rules_config(&format!(r#"[{{ name: "web", listen: "0.0.0.0:8080", backends: ["127.0.0.1:9001"],
+            health_check: {{ enabled: true, interval_ms: 1000, timeout_ms: 200, protocol: "{}", tls: true, ignore_verify: true }} }}]"#, protocol)).validate();
+        assert!(check("http").is_ok());
+        assert!(check("tcp").is_err());
+    }
+
+    #[test]
+    fn test_duplicate_rule_names_rejected() {
+        let config = rules_config(r#"[
+            { name: "web", listen: "0.0.0.0:8080", backends: ["127.0.0.1:9001"] },
+            { name: "web", listen: "0.0.0.0:8081", backends: ["127.0.0.1:9002"] }]"#);
+        let err = config.validate().unwrap_err().to_string();
+        assert!(err.contains("'web'") && err.contains("same name"), "{}", err);
+    }
+
+    #[test]
+    fn test_duplicate_listen_rejected_per_protocol() {
+        let config = rules_config(r#"[
+            { name: "a", listen: "0.0.0.0:53", backends: ["127.0.0.1:9001"] },
+            { name: "b", listen: "0.0.0.0:53", backends: ["127.0.0.1:9002"] }]"#);
+        let err = config.validate().unwrap_err().to_string();
+        assert!(err.contains("'a'") && err.contains("'b'") && err.contains("0.0.0.0:53"), "{}", err);
+
+        // TCP and UDP on the same port don't clash
+        let config = rules_config(r#"[
+            { name: "dns-tcp", listen: "0.0.0.0:53", backends: ["127.0.0.1:9001"] },
+            { name: "dns-udp", listen: "0.0.0.0:53", protocol: "udp", backends: ["127.0.0.1:9002"] }]"#);
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_address_validation() {
+        for (listen, backend) in [("0.0.0.0:99999", "127.0.0.1:9001"), ("localhost:8080", "127.0.0.1:9001"), ("0.0.0.0:8080", "bad addr"), ("0.0.0.0:8080", "10.0.0.1"), ("0.0.0.0:8080", "api.internal:0")] {
+            let config = rules_config(&format!(r#"[{{ name: "web", listen: "{}", backends: ["{}"] }}]"#, listen, backend));
+            let err = config.validate().unwrap_err().to_string();
+            assert!(err.contains("'web'"), "{}", err);
+        }
+
+        for backend in ["127.0.0.1:9001", "[::1]:9001", "api.internal:443", "backend-1.svc.cluster.local:8080"] {
+            let config = rules_config(&format!(r#"[{{ name: "web", listen: "[::]:8080", backends: ["{}"] }}]"#, backend));
+            assert!(config.validate().is_ok(), "{}", backend);
+        }
+    }
+
+    #[test]
+    fn test_backup_backends_validated() {
+        let check = |backups: &str| rules_config(&format!(r#"[{{ name: "web", listen: "0.0.0.0:8080", backends: ["127.0.0.1:9001"], backup_backends: {} }}]"#, backups)).validate();
+        assert!(check(r#"["127.0.0.1:9002", { addr: "10.0.0.9:80", weight: 2 }]"#).is_ok());
+        assert!(check(r#"["127.0.0.1:9001"]"#).unwrap_err().to_string().contains("both"));
+        assert!(check(r#"["bad addr"]"#).unwrap_err().to_string().contains("'web'"));
+        assert!(check(r#"[{ addr: "backup.internal:80", dns: true }]"#).unwrap_err().to_string().contains("dns"));
+    }
+
+    #[test]
+    fn test_parse_error_names_format() {
+        let err = Config::parse("{ not json", ConfigFormat::Json).unwrap_err();
+        assert!(err.to_string().contains("Json"), "{}", err);
+    }
+
+    #[test]
+    fn test_duration_buckets_validated() {
+        let mut config = rules_config(r#"[{ name: "web", listen: "0.0.0.0:8080", backends: ["127.0.0.1:9001"] }]"#);
+        for (buckets, ok) in [(vec![0.0005, 0.001, 3600.0], true), (vec![], false), (vec![1.0, 0.5], false), (vec![1.0, 1.0], false), (vec![0.0, 1.0], false), (vec![f64::INFINITY], false)] {
+            config.metrics = Some(MetricsConfig { listen: "127.0.0.1:9100".to_string(), connection_duration_buckets: Some(buckets.clone()) });
+            assert_eq!(config.validate().is_ok(), ok, "{:?}", buckets);
+        }
+    }
+
+    #[test]
+    fn test_backend_pool_defaults_and_validation() {
+        let config = rules_config(r#"[{ name: "web", listen: "0.0.0.0:8080", backends: ["127.0.0.1:9001"], backend_pool: {} }]"#);
+        assert!(config.validate().is_ok());
+        let pool = config.rules[0].backend_pool.as_ref().unwrap();
+        assert_eq!((pool.max_idle, pool.idle_timeout_ms), (4, 30_000));
+
+        let config = rules_config(r#"[{ name: "web", listen: "0.0.0.0:8080", backends: ["127.0.0.1:9001"], backend_pool: { max_idle: 0 } }]"#);
+        assert!(config.validate().unwrap_err().to_string().contains("backend_pool"));
+    }
+
+    #[test]
+    fn test_listen_port_ranges() {
+        let config = rules_config(r#"[{ name: "rtp", listen: "0.0.0.0:30000-30100", backends: ["127.0.0.1:9001"] }]"#);
+        assert!(config.validate().is_ok());
+        let addrs = config.rules[0].listen_addrs().unwrap();
+        assert_eq!(addrs.len(), 101);
+        assert_eq!((addrs[0].to_string().as_str(), addrs[100].port()), ("0.0.0.0:30000", 30100));
+        assert_eq!(parse_listen("[::1]:5000-5001").unwrap()[1].to_string(), "[::1]:5001");
+
+        for listen in ["0.0.0.0:30100-30000", "0.0.0.0:0-10", "0.0.0.0:1-", "host:1-2", "0.0.0.0:1000-9000"] {
+            let config = rules_config(&format!(r#"[{{ name: "rtp", listen: "{}", backends: ["127.0.0.1:9001"] }}]"#, listen));
+            assert!(config.validate().is_err(), "{}", listen);
+        }
+
+        // Overlapping ranges clash like identical listen addresses
+        let config = rules_config(r#"[
+            { name: "a", listen: "0.0.0.0:30000-30100", backends: ["127.0.0.1:9001"] },
+            { name: "b", listen: "0.0.0.0:30100", backends: ["127.0.0.1:9001"] },
+            { name: "c", listen: "0.0.0.0:30050-30060", backends: ["127.0.0.1:9001"], protocol: udp }
+        ]"#);
+        assert!(config.validate().unwrap_err().to_string().contains("0.0.0.0:30100"));
+    }
+
+    #[test]
+    fn test_log_levels_validated() {
+        let mut config = rules_config(r#"[{ name: "web", listen: "0.0.0.0:8080", backends: ["127.0.0.1:9001"], log_level: "debug" }]"#);
+        config.log = Some(LogConfig { level: "WARN".to_string() });
+        assert!(config.validate().is_ok());
+        assert_eq!(config.rules[0].log_level_filter(), Some(log::LevelFilter::Debug));
+
+        config.log = Some(LogConfig { level: "degub".to_string() });
+        assert!(config.validate().unwrap_err().to_string().contains("log.level"));
+
+        config.log = None;
+        config.rules[0].log_level = Some("loud".to_string());
+        assert!(config.validate().unwrap_err().to_string().contains("'web'"));
+    }
+}
@@ -1,14 +1,158 @@
-use std::sync::atomic::{AtomicUsize, AtomicBool, Ordering};
-use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, AtomicBool, AtomicU32, AtomicI64, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::net::IpAddr;
+use std::time::Duration;
+use tokio::time::Instant; // Follows tokio's paused clock in tests
+use dashmap::DashMap;
+use std::hash::{DefaultHasher, Hash, Hasher};
 use arc_swap::ArcSwap;
+use rand::Rng;
 use log::{warn, info};
+use crate::core::state::{ejection_deadline, SavedRuleState, StateStore};
+pub use crate::config::{Overflow, Strategy};
+
+// Virtual nodes per backend on the consistent hash ring
+const RING_VNODES: usize = 160;
+
+// Maglev lookup table size; prime, and large enough (>= 100x backends) to stay even
+const MAGLEV_TABLE_SIZE: usize = 65537;
+
+// Full sweep of expired sticky entries every N new assignments
+const STICKY_SWEEP_EVERY: usize = 1024;
+
+// Weight of a new connection duration in a backend's response time EWMA
+const RESPONSE_TIME_ALPHA: f64 = 0.2;
+
+// Half-life of a response time EWMA that gets no new samples, so a backend that
+// was avoided for being slow regains its standing and gets tried again
+const RESPONSE_TIME_HALF_LIFE: Duration = Duration::from_secs(30);
+
+// (point, backend) pairs sorted by point
+type HashRing = Vec<(u64, Arc<Backend>)>;
+
+// Slot -> index into `backends`; empty when no backend has a weight
+#[derive(Default)]
+struct MaglevTable {
+    backends: Vec<Arc<Backend>>,
+    slots: Vec<u16>,
+}
 
 #[derive(Clone)]
 pub struct LoadBalancer {
     pub rule_name: String, // Added for metrics
     pub backends: Arc<ArcSwap<Vec<Arc<Backend>>>>, 
+    pub backup_backends: Arc<ArcSwap<Vec<Arc<Backend>>>>, // Only selected while no primary backend is available
+    ring: Arc<ArcSwap<HashRing>>, // ConsistentHash only
+    maglev: Arc<ArcSwap<MaglevTable>>, // Maglev only
     current: Arc<AtomicUsize>,
     connection_limit: Option<usize>,
+    overflow: Overflow,
+    strategy: Strategy,
+    sticky: Option<StickyTable>,
+    passive: Option<PassiveHealth>,
+    outlier: Option<OutlierDetection>,
+    adaptive: Option<AdaptiveWeights>,
+    removal_grace: Option<Duration>,
+    retiring: Arc<Mutex<Vec<Arc<Backend>>>>, // Removed by a reload, draining until their grace period ends
+    state_store: Option<Arc<StateStore>>, // Persists admin drains and outlier ejections across restarts
+}
+
+// Set when a backend's removal grace period is over; its open connections close
+#[derive(Default)]
+struct Retirement {
+    done: AtomicBool,
+    notify: tokio::sync::Notify,
+    generation: AtomicU64, // Bumped per removal; only the latest removal's timer may finish it
+}
+
+// Connect-failure based ejection (nginx max_fails/fail_timeout style)
+#[derive(Clone, Copy)]
+struct PassiveHealth {
+    max_failures: u32,
+    fail_timeout: Duration, // Failure counting window, and how long the backend is then skipped
+}
+
+// Error-rate based ejection with exponential backoff (Envoy outlier detection style)
+#[derive(Clone, Copy)]
+struct OutlierDetection {
+    interval: Duration,
+    error_rate_percent: u32,
+    min_requests: u32,
+    base_ejection: Duration,
+    max_ejection: Duration,
+    max_ejection_percent: u32,
+}
+
+// Feedback controller scaling weights by error rate and response time
+#[derive(Clone, Copy)]
+struct AdaptiveWeights {
+    interval: Duration,
+    step_percent: u32,
+    min_percent: u32,
+    max_percent: u32,
+}
+
+#[derive(Default)]
+struct OutlierState {
+    window_start: Option<Instant>,
+    requests: u32,
+    failures: u32,
+    ejected_until: Option<Instant>,
+    ejections: u32, // Backoff exponent; decays by one per clean window
+}
+
+// EWMA of connection durations as fixed-point microseconds (LeastResponseTime)
+struct ResponseTime {
+    epoch: Instant,
+    ewma_us: AtomicU64,
+    updated_ms: AtomicU64, // Since `epoch`; u64::MAX until the first sample
+}
+
+impl ResponseTime {
+    fn new() -> Self {
+        Self { epoch: Instant::now(), ewma_us: AtomicU64::new(0), updated_ms: AtomicU64::new(u64::MAX) }
+    }
+
+    // Concurrent samples may overwrite each other; one lost sample barely moves an EWMA
+    fn record(&self, duration: Duration) {
+        let sample = duration.as_micros().min(u64::MAX as u128) as f64;
+        let now_ms = self.epoch.elapsed().as_millis() as u64;
+        let next = match self.current_at(now_ms) {
+            None => sample,
+            Some(prev) => prev as f64 * (1.0 - RESPONSE_TIME_ALPHA) + sample * RESPONSE_TIME_ALPHA,
+        };
+        self.ewma_us.store(next as u64, Ordering::Relaxed);
+        self.updated_ms.store(now_ms, Ordering::Relaxed);
+    }
+
+    // Decayed EWMA in microseconds, 0 for a backend without samples
+    fn current_us(&self) -> u64 {
+        self.current_at(self.epoch.elapsed().as_millis() as u64).unwrap_or(0)
+    }
+
+    fn current_at(&self, now_ms: u64) -> Option<u64> {
+        let updated = self.updated_ms.load(Ordering::Relaxed);
+        if updated == u64::MAX {
+            return None;
+        }
+        let half_lives = now_ms.saturating_sub(updated) as f64 / RESPONSE_TIME_HALF_LIFE.as_millis() as f64;
+        Some((self.ewma_us.load(Ordering::Relaxed) as f64 * 0.5f64.powf(half_lives)) as u64)
+    }
+}
+
+#[derive(Default)]
+struct PassiveState {
+    failures: u32,
+    window_start: Option<Instant>,
+    down_until: Option<Instant>,
+}
+
+// Client IP -> (backend addr, last used)
+#[derive(Clone)]
+struct StickyTable {
+    ttl: Duration,
+    entries: Arc<DashMap<IpAddr, (String, Instant)>>,
+    inserts: Arc<AtomicUsize>,
 }
 
 #[derive(Clone)]
@@ -18,70 +162,478 @@ pub struct Backend {
     pub active_connections: Arc<AtomicUsize>,
     pub healthy: Arc<AtomicBool>,
     pub drain: Arc<AtomicBool>, // Configured state (true = draining, false = accept traffic)
+    pub admin_drain: Arc<AtomicBool>, // Drained via the admin API, on top of the configured state
+    pub ready: Arc<AtomicBool>, // Readiness check state (false = no new connections)
+    pub weight: Arc<AtomicU32>, // Configured weight (0 = excluded)
+    pub backup: bool, // Member of the rule's backup pool
+    current_weight: Arc<AtomicI64>, // Smooth WRR running weight
+    adaptive_percent: Arc<AtomicU32>, // Share of `weight` in effect (adaptive_weights), 100 otherwise
+    adaptive_window: Arc<Mutex<(u32, u32)>>, // Connects and connect failures since the last adaptive step
+    passive: Arc<Mutex<PassiveState>>, // Recent connect failures
+    outlier: Arc<Mutex<OutlierState>>, // Connect outcomes in the current window
+    response_time: Arc<ResponseTime>, // Recent connection durations
+    proxy_protocol: Arc<Mutex<Option<bool>>>, // Per-backend override of the rule's PROXY header setting
+    nodelay: Arc<Mutex<Option<bool>>>, // Per-backend override of the rule's TCP_NODELAY setting
+    retirement: Arc<Retirement>,
+}
+
+impl Backend {
+    fn new(rule_name: &str, addr: String, drain: bool, weight: u32, proxy_protocol: Option<bool>, nodelay: Option<bool>, backup: bool) -> Self {
+        // Init Metric
+        crate::metrics::BACKEND_HEALTH_STATUS.with_label_values(&[rule_name, &addr]).set(1.0);
+        crate::metrics::BACKEND_ACTIVE_CONNECTIONS.with_label_values(&[rule_name, &addr]).set(0.0);
+
+        Backend {
+            rule_name: rule_name.to_string(),
+            addr,
+            active_connections: Arc::new(AtomicUsize::new(0)),
+            healthy: Arc::new(AtomicBool::new(true)), // Optimistic init
+            drain: Arc::new(AtomicBool::new(drain)),
+            admin_drain: Arc::new(AtomicBool::new(false)),
+            ready: Arc::new(AtomicBool::new(true)),
+            weight: Arc::new(AtomicU32::new(weight)),
+            backup,
+            current_weight: Arc::new(AtomicI64::new(0)),
+            adaptive_percent: Arc::new(AtomicU32::new(100)),
+            adaptive_window: Arc::new(Mutex::new((0, 0))),
+            passive: Arc::new(Mutex::new(PassiveState::default())),
+            outlier: Arc::new(Mutex::new(OutlierState::default())),
+            response_time: Arc::new(ResponseTime::new()),
+            proxy_protocol: Arc::new(Mutex::new(proxy_protocol)),
+            nodelay: Arc::new(Mutex::new(nodelay)),
+            retirement: Arc::new(Retirement::default()),
+        }
+    }
+
+    pub fn draining(&self) -> bool {
+        self.drain.load(Ordering::Relaxed) || self.admin_drain.load(Ordering::Relaxed)
+    }
+
+    fn passively_down(&self) -> bool {
+        self.passive.lock().unwrap().down_until.is_some_and(|until| Instant::now() < until)
+    }
+
+    fn outlier_ejected(&self) -> bool {
+        self.outlier.lock().unwrap().ejected_until.is_some_and(|until| Instant::now() < until)
+    }
+
+    // Weight read by the weighted strategies, in hundredths of the configured weight
+    pub fn effective_weight(&self) -> u64 {
+        self.weight.load(Ordering::Relaxed) as u64 * self.adaptive_percent.load(Ordering::Relaxed) as u64
+    }
 }
 
 impl LoadBalancer {
-    pub fn new(rule_name: String, backend_configs: Vec<crate::config::BackendConfig>, connection_limit: Option<usize>) -> Self {
+    pub fn new(rule_name: String, backend_configs: Vec<crate::config::BackendConfig>, connection_limit: Option<usize>, strategy: Strategy) -> Self {
         let backends: Vec<Arc<Backend>> = backend_configs.into_iter().map(|config| {
-            let (addr, drain) = match config {
-                crate::config::BackendConfig::Simple(a) => (a, false),
-                crate::config::BackendConfig::Detailed { addr, drain } => (addr, drain),
-            };
-
-            // Init Metric
-            crate::metrics::BACKEND_HEALTH_STATUS.with_label_values(&[&rule_name, &addr]).set(1.0);
-            crate::metrics::BACKEND_ACTIVE_CONNECTIONS.with_label_values(&[&rule_name, &addr]).set(0.0);
-            
-            Arc::new(Backend {
-                rule_name: rule_name.clone(),
-                addr,
-                active_connections: Arc::new(AtomicUsize::new(0)),
-                healthy: Arc::new(AtomicBool::new(true)), // Optimistic init
-                drain: Arc::new(AtomicBool::new(drain)),
-            })
+            Arc::new(Backend::new(&rule_name, config.addr().to_string(), config.drain(), config.weight(), config.proxy_protocol(), config.nodelay(), false))
         }).collect();
 
         LoadBalancer {
             rule_name,
+            ring: Arc::new(ArcSwap::from_pointee(build_ring(&backends))),
+            maglev: Arc::new(ArcSwap::from_pointee(build_maglev_for(strategy, &backends))),
             backends: Arc::new(ArcSwap::from_pointee(backends)),
+            backup_backends: Arc::new(ArcSwap::from_pointee(Vec::new())),
             current: Arc::new(AtomicUsize::new(0)),
             connection_limit,
+            overflow: Overflow::Reject,
+            strategy,
+            sticky: None,
+            passive: None,
+            outlier: None,
+            adaptive: None,
+            removal_grace: None,
+            retiring: Arc::new(Mutex::new(Vec::new())),
+            state_store: None,
+        }
+    }
+
+    // Restores the rule's admin drains and unexpired ejections from `store`,
+    // and saves them there whenever they change from now on
+    pub fn with_state_store(mut self, store: Arc<StateStore>) -> Self {
+        let saved = store.rule(&self.rule_name);
+        for backend in self.backends.load().iter().chain(self.backup_backends.load().iter()) {
+            restore_backend(backend, &saved);
+        }
+        self.state_store = Some(store);
+        self
+    }
+
+    // Drains (or undrains) a backend on top of its configured `drain`, which
+    // config reloads don't touch. False if the rule has no such backend.
+    pub fn set_admin_drain(&self, backend_addr: &str, drained: bool) -> bool {
+        let Some(backend) = self.backend(backend_addr) else { return false };
+        if backend.admin_drain.swap(drained, Ordering::Relaxed) != drained {
+            info!("[{}] Backend {} {} via admin API", self.rule_name, backend_addr, if drained { "drained" } else { "undrained" });
+        }
+        self.save_state();
+        true
+    }
+
+    // Snapshot of what `with_state_store` restores
+    fn save_state(&self) {
+        let Some(store) = &self.state_store else { return };
+        let mut saved = SavedRuleState::default();
+        let now = Instant::now();
+        for backend in self.backends.load().iter().chain(self.backup_backends.load().iter()) {
+            if backend.admin_drain.load(Ordering::Relaxed) {
+                saved.drained.insert(backend.addr.clone());
+            }
+            if let Some(until) = backend.outlier.lock().unwrap().ejected_until.filter(|until| *until > now) {
+                saved.ejected_until.insert(backend.addr.clone(), ejection_deadline(until - now));
+            }
+        }
+        store.update(&self.rule_name, saved);
+    }
+
+    // Backends removed by `update_backends` stop taking new connections but keep
+    // their open ones for `grace`; whatever is still open then is closed.
+    pub fn with_removal_grace(mut self, grace: Duration) -> Self {
+        self.removal_grace = Some(grace);
+        self
+    }
+
+    // With `Overflow::LeastLoaded` the connection limit is soft: once every eligible
+    // backend is at it, the least loaded one takes the connection anyway.
+    pub fn with_overflow(mut self, overflow: Overflow) -> Self {
+        self.overflow = overflow;
+        self
+    }
+
+    // Failover pool: new connections go to these backends only while none of the
+    // primary backends is available, and return to the primaries once one recovers.
+    pub fn with_backup_backends(self, backend_configs: Vec<crate::config::BackendConfig>) -> Self {
+        let backups: Vec<Arc<Backend>> = backend_configs.into_iter().map(|config| {
+            Arc::new(Backend::new(&self.rule_name, config.addr().to_string(), config.drain(), config.weight(), config.proxy_protocol(), config.nodelay(), true))
+        }).collect();
+        self.backup_backends.store(Arc::new(backups));
+        self
+    }
+
+    // Backends removed from the config that are still within their grace period
+    pub fn retiring_backends(&self) -> Vec<Arc<Backend>> {
+        self.retiring.lock().unwrap().clone()
+    }
+
+    fn retire(&self, backend: Arc<Backend>, grace: Duration) {
+        info!("Backend {} removed, draining for {:?} ({} active connections)", backend.addr, grace, backend.active_connections.load(Ordering::Relaxed));
+        let generation = {
+            let mut list = self.retiring.lock().unwrap();
+            list.push(backend.clone());
+            backend.retirement.generation.fetch_add(1, Ordering::Relaxed) + 1
+        };
+        let retiring = self.retiring.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(grace).await;
+            let mut list = retiring.lock().unwrap();
+            // Removed again after a re-add: the later removal's timer owns the grace period
+            if backend.retirement.generation.load(Ordering::Relaxed) != generation {
+                return;
+            }
+            // Re-added within the grace period: it is back in service
+            let Some(pos) = list.iter().position(|b| Arc::ptr_eq(b, &backend)) else { return };
+            list.remove(pos);
+            drop(list);
+            let open = backend.active_connections.load(Ordering::Relaxed);
+            if open > 0 {
+                info!("Backend {} grace period over, closing {} remaining connections", backend.addr, open);
+            }
+            backend.retirement.done.store(true, Ordering::Relaxed);
+            backend.retirement.notify.notify_waiters();
+        });
+    }
+
+    // Enable source-IP sticky sessions: a client reuses its last backend while
+    // that backend stays available and the client returns within `ttl`.
+    pub fn with_sticky(mut self, ttl: Duration) -> Self {
+        self.sticky = Some(StickyTable {
+            ttl,
+            entries: Arc::new(DashMap::new()),
+            inserts: Arc::new(AtomicUsize::new(0)),
+        });
+        self
+    }
+
+    // Enable passive health: `max_failures` connect failures within `fail_timeout`
+    // take a backend out of rotation for `fail_timeout`, between active probes.
+    pub fn with_passive_health(mut self, max_failures: u32, fail_timeout: Duration) -> Self {
+        self.passive = Some(PassiveHealth { max_failures: max_failures.max(1), fail_timeout });
+        self
+    }
+
+    // Enable outlier detection: a backend whose connect error rate over `interval_ms`
+    // reaches the threshold is ejected, for twice as long on every repeat offense.
+    pub fn with_outlier_detection(mut self, config: &crate::config::OutlierDetectionConfig) -> Self {
+        self.outlier = Some(OutlierDetection {
+            interval: Duration::from_millis(config.interval_ms),
+            error_rate_percent: config.error_rate_percent,
+            min_requests: config.min_requests.max(1),
+            base_ejection: Duration::from_millis(config.base_ejection_ms),
+            max_ejection: Duration::from_millis(config.max_ejection_ms),
+            max_ejection_percent: config.max_ejection_percent,
+        });
+        self
+    }
+
+    // Enable adaptive weights: every `interval_ms` each backend's effective weight moves
+    // towards a target lowered by its connect error rate and by a response time above
+    // its pool's average, within min/max_weight_percent of the configured weight.
+    // Runs once `spawn_adaptive_weights` is called.
+    pub fn with_adaptive_weights(mut self, config: &crate::config::AdaptiveWeightsConfig) -> Self {
+        self.adaptive = Some(AdaptiveWeights {
+            interval: Duration::from_millis(config.interval_ms),
+            step_percent: config.step_percent,
+            min_percent: config.min_weight_percent,
+            max_percent: config.max_weight_percent,
+        });
+        self
+    }
+
+    pub fn adaptive_weights_enabled(&self) -> bool {
+        self.adaptive.is_some()
+    }
+
+    // Runs the adaptive weights controller until the load balancer is dropped
+    pub fn spawn_adaptive_weights(self: &Arc<Self>) {
+        let Some(adaptive) = self.adaptive else { return };
+        let lb = Arc::downgrade(self);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(adaptive.interval);
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                let Some(lb) = lb.upgrade() else { break };
+                lb.adapt_weights();
+            }
+        });
+    }
+
+    // One controller step per pool. The target is 100% of the configured weight, times
+    // the share of connects that succeeded and the pool's mean response time over the
+    // backend's; the effective weight closes `step_percent` of the gap to it.
+    // Without connects or samples in the interval that factor stays neutral,
+    // so an idle backend drifts back to its configured weight.
+    fn adapt_weights(&self) {
+        let Some(adaptive) = self.adaptive else { return };
+        let (min, max) = (adaptive.min_percent as f64, adaptive.max_percent as f64);
+        for pool in [&self.backends, &self.backup_backends] {
+            let backends = pool.load();
+            let latencies: Vec<u64> = backends.iter().map(|b| b.response_time.current_us()).filter(|us| *us > 0).collect();
+            let mean_us = if latencies.is_empty() { 0.0 } else { latencies.iter().sum::<u64>() as f64 / latencies.len() as f64 };
+
+            for backend in backends.iter() {
+                let (connects, failures) = std::mem::take(&mut *backend.adaptive_window.lock().unwrap());
+                let mut target = 100.0;
+                if connects > 0 {
+                    target *= 1.0 - failures as f64 / connects as f64;
+                }
+                let latency_us = backend.response_time.current_us();
+                if latency_us > 0 && mean_us > 0.0 {
+                    target *= mean_us / latency_us as f64;
+                }
+                let target = target.clamp(min, max);
+
+                let current = backend.adaptive_percent.load(Ordering::Relaxed) as f64;
+                let mut step = (target - current) * adaptive.step_percent as f64 / 100.0;
+                if step.abs() < 1.0 {
+                    step = target - current; // Settle instead of stalling under one percent away
+                }
+                let next = (current + step).round().clamp(min, max) as u32;
+                if next != current as u32 {
+                    log::debug!("Backend {} adaptive weight {}% -> {}% ({}/{} connects failed, {}us vs pool {:.0}us)",
+                        backend.addr, current, next, failures, connects, latency_us, mean_us);
+                }
+                backend.adaptive_percent.store(next, Ordering::Relaxed);
+                crate::metrics::BACKEND_EFFECTIVE_WEIGHT.with_label_values(&[&backend.rule_name, &backend.addr])
+                    .set(backend.effective_weight() as f64 / 100.0);
+            }
+        }
+    }
+
+    // Connect outcome counted towards the backend's next adaptive weights step
+    fn record_adaptive_outcome(&self, backend: &Backend, failed: bool) {
+        if self.adaptive.is_none() {
+            return;
+        }
+        let mut window = backend.adaptive_window.lock().unwrap();
+        window.0 = window.0.saturating_add(1);
+        if failed {
+            window.1 = window.1.saturating_add(1);
+        }
+    }
+
+    // Connect outcome for outlier detection; ejects at the end of a bad enough sample
+    // True when the outcome ejected the backend
+    fn record_outlier_outcome(&self, backends: &[Arc<Backend>], backend: &Backend, failed: bool) -> bool {
+        let Some(outlier) = self.outlier else { return false };
+        // Counted before locking this backend (ejected_count locks each one)
+        let ejected = ejected_count(backends);
+
+        let now = Instant::now();
+        let mut state = backend.outlier.lock().unwrap();
+        if state.ejected_until.is_some_and(|until| now < until) {
+            return false; // Stragglers from before the ejection
+        }
+        if state.window_start.is_none_or(|start| now.duration_since(start) > outlier.interval) {
+            if state.window_start.is_some() {
+                state.ejections = state.ejections.saturating_sub(1); // A full window without ejection
+            }
+            state.window_start = Some(now);
+            state.requests = 0;
+            state.failures = 0;
+            state.ejected_until = None;
+        }
+        state.requests += 1;
+        if failed {
+            state.failures += 1;
+        }
+        if state.requests < outlier.min_requests
+            || (state.failures as u64) * 100 < (outlier.error_rate_percent as u64) * (state.requests as u64) {
+            return false;
+        }
+
+        if (ejected + 1) * 100 > outlier.max_ejection_percent as usize * backends.len() {
+            log::debug!("[{}] Backend {} is an outlier but max_ejection_percent is reached", backend.rule_name, backend.addr);
+            return false;
+        }
+        let duration = outlier.base_ejection.saturating_mul(1 << state.ejections.min(16)).min(outlier.max_ejection);
+        warn!("[{}] Backend {} EJECTED for {:?}: {}/{} connects failed (outlier detection)", backend.rule_name, backend.addr, duration, state.failures, state.requests);
+        crate::metrics::OUTLIER_EJECTIONS.with_label_values(&[&backend.rule_name, &backend.addr]).inc();
+        state.ejected_until = Some(now + duration);
+        state.ejections += 1;
+        state.window_start = None;
+        true
+    }
+
+    // Whether a PROXY header goes to this backend, when it overrides the rule's setting
+    pub fn backend_proxy_protocol(&self, backend_addr: &str) -> Option<bool> {
+        let backend = self.backend(backend_addr)?;
+        *backend.proxy_protocol.lock().unwrap()
+    }
+
+    // Whether TCP_NODELAY is set towards this backend, when it overrides the rule's setting
+    pub fn backend_nodelay(&self, backend_addr: &str) -> Option<bool> {
+        let backend = self.backend(backend_addr)?;
+        *backend.nodelay.lock().unwrap()
+    }
+
+    // Primary or backup backend by address
+    pub fn backend(&self, backend_addr: &str) -> Option<Arc<Backend>> {
+        [&self.backends, &self.backup_backends].into_iter()
+            .find_map(|pool| pool.load().iter().find(|b| b.addr == backend_addr).cloned())
+    }
+
+    // The pool a backend belongs to; outlier ejection limits apply per pool
+    fn pool_of(&self, backend: &Backend) -> arc_swap::Guard<Arc<Vec<Arc<Backend>>>> {
+        if backend.backup { self.backup_backends.load() } else { self.backends.load() }
+    }
+
+    // Called by the proxy when connecting to a backend fails
+    pub fn report_backend_failure(&self, backend_addr: &str) {
+        let Some(backend) = self.backend(backend_addr) else { return };
+        self.record_adaptive_outcome(&backend, true);
+        if self.record_outlier_outcome(&self.pool_of(&backend), &backend, true) {
+            self.save_state();
+        }
+        let Some(passive) = self.passive else { return };
+
+        let now = Instant::now();
+        let mut state = backend.passive.lock().unwrap();
+        if state.window_start.is_none_or(|start| now.duration_since(start) > passive.fail_timeout) {
+            state.window_start = Some(now);
+            state.failures = 0;
+        }
+        state.failures += 1;
+        if state.failures >= passive.max_failures {
+            warn!("[{}] Backend {} marked UNHEALTHY for {:?} after {} connect failures (passive)", self.rule_name, backend_addr, passive.fail_timeout, state.failures);
+            state.down_until = Some(now + passive.fail_timeout);
+            state.failures = 0;
+            state.window_start = None;
+        }
+    }
+
+    // Called by the proxy after a successful backend connect
+    pub fn report_backend_success(&self, backend_addr: &str) {
+        if self.passive.is_none() && self.outlier.is_none() && self.adaptive.is_none() {
+            return;
+        }
+        if let Some(backend) = self.backend(backend_addr) {
+            self.record_adaptive_outcome(&backend, false);
+            if self.record_outlier_outcome(&self.pool_of(&backend), &backend, false) {
+                self.save_state();
+            }
+            let mut state = backend.passive.lock().unwrap();
+            state.failures = 0;
+            state.window_start = None;
+        }
+    }
+
+    // Called by the proxy when a connection closes; feeds least_response_time and adaptive weights
+    pub fn report_connection_duration(&self, backend_addr: &str, duration: Duration) {
+        if self.strategy != Strategy::LeastResponseTime && self.adaptive.is_none() {
+            return;
+        }
+        if let Some(backend) = self.backend(backend_addr) {
+            backend.response_time.record(duration);
         }
     }
 
     pub async fn update_backends(&self, new_backend_configs: Vec<crate::config::BackendConfig>) {
+        let current_backends = self.backends.load();
+        let new_backends = self.replace_pool(&current_backends, new_backend_configs, false);
+
+        // Existing backends keep their ring points, so only keys owned by
+        // added/removed backends move.
+        self.ring.store(Arc::new(build_ring(&new_backends)));
+        self.maglev.store(Arc::new(build_maglev_for(self.strategy, &new_backends)));
+        self.backends.store(Arc::new(new_backends));
+    }
+
+    pub async fn update_backup_backends(&self, new_backend_configs: Vec<crate::config::BackendConfig>) {
+        let current_backends = self.backup_backends.load();
+        let new_backends = self.replace_pool(&current_backends, new_backend_configs, true);
+        self.backup_backends.store(Arc::new(new_backends));
+    }
+
+    fn replace_pool(&self, current_backends: &[Arc<Backend>], new_backend_configs: Vec<crate::config::BackendConfig>, backup: bool) -> Vec<Arc<Backend>> {
         // Construct new backend list
         // Optimization: preserve active connection counters for existing backends if possible
         // We need to read the current backends to match addresses
-        let current_backends = self.backends.load();
+        let mut retiring = self.retiring.lock().unwrap();
+        // New addresses (e.g. from DNS) may have saved state from before a restart
+        let saved = self.state_store.as_ref().map(|store| store.rule(&self.rule_name));
         
         let new_backends: Vec<Arc<Backend>> = new_backend_configs.into_iter().map(|config| {
-             let (addr, drain_cfg) = match config {
-                crate::config::BackendConfig::Simple(a) => (a, false),
-                crate::config::BackendConfig::Detailed { addr, drain } => (addr, drain),
-            };
+             let (addr, drain_cfg, weight) = (config.addr().to_string(), config.drain(), config.weight());
+
+             // A backend re-added during its grace period picks up where it left off
+             let returning = retiring.iter().position(|b| b.addr == addr && b.backup == backup).map(|pos| retiring.remove(pos));
 
              // Try to find existing backend state
-             if let Some(existing) = current_backends.iter().find(|b| b.addr == addr) {
-                 // Update drain state if changed
+             if let Some(existing) = current_backends.iter().find(|b| b.addr == addr).or(returning.as_ref()) {
+                 // Update drain state / weight / PROXY and nodelay overrides if changed
                  existing.drain.store(drain_cfg, Ordering::Relaxed);
+                 existing.weight.store(weight, Ordering::Relaxed);
+                 *existing.proxy_protocol.lock().unwrap() = config.proxy_protocol();
+                 *existing.nodelay.lock().unwrap() = config.nodelay();
                  existing.clone()
              } else {
-                 // Init Metric for new backend
-                 crate::metrics::BACKEND_HEALTH_STATUS.with_label_values(&[&self.rule_name, &addr]).set(1.0);
-                 crate::metrics::BACKEND_ACTIVE_CONNECTIONS.with_label_values(&[&self.rule_name, &addr]).set(0.0);
-                 
-                 Arc::new(Backend {
-                    rule_name: self.rule_name.clone(),
-                    addr,
-                    active_connections: Arc::new(AtomicUsize::new(0)),
-                    healthy: Arc::new(AtomicBool::new(true)),
-                    drain: Arc::new(AtomicBool::new(drain_cfg)),
-                 })
+                 let backend = Arc::new(Backend::new(&self.rule_name, addr, drain_cfg, weight, config.proxy_protocol(), config.nodelay(), backup));
+                 if let Some(saved) = &saved {
+                     restore_backend(&backend, saved);
+                 }
+                 backend
              }
         }).collect();
 
-        self.backends.store(Arc::new(new_backends));
+        drop(retiring);
+        if let Some(grace) = self.removal_grace {
+            for removed in current_backends.iter().filter(|b| !new_backends.iter().any(|n| Arc::ptr_eq(n, b))) {
+                self.retire(removed.clone(), grace);
+            }
+        }
+        new_backends
     }
     
     // Used by Health Checker
@@ -89,83 +641,577 @@ impl LoadBalancer {
         // Update Metric
         crate::metrics::BACKEND_HEALTH_STATUS.with_label_values(&[&self.rule_name, backend_addr]).set(if healthy { 1.0 } else { 0.0 });
 
-        // We can just iterate the current snapshot. Since backends are Arc, 
+        // We can just look in the current snapshots. Since backends are Arc, 
         // updating atomic bool is visible to everyone.
-        let backends = self.backends.load();
-        if let Some(backend) = backends.iter().find(|b| b.addr == backend_addr) {
+        if let Some(backend) = self.backend(backend_addr) {
             let old = backend.healthy.swap(healthy, Ordering::Relaxed);
             if old != healthy {
                 if healthy {
-                    info!("Backend {} marked HEALTHY", backend_addr);
+                    info!("[{}] Backend {} marked HEALTHY", self.rule_name, backend_addr);
                 } else {
-                    warn!("Backend {} marked UNHEALTHY", backend_addr);
+                    warn!("[{}] Backend {} marked UNHEALTHY", self.rule_name, backend_addr);
                 }
             } else {
-                log::debug!("Health check update for {}: no change (healthy={})", backend_addr, healthy);
+                log::debug!("[{}] Health check update for {}: no change (healthy={})", self.rule_name, backend_addr, healthy);
+            }
+        }
+    }
+
+    // Used by readiness checks (HealthCheckAction::Drain)
+    pub async fn set_backend_ready(&self, backend_addr: &str, ready: bool) {
+        if let Some(backend) = self.backend(backend_addr) {
+            let old = backend.ready.swap(ready, Ordering::Relaxed);
+            if old != ready {
+                if ready {
+                    info!("[{}] Backend {} marked READY", self.rule_name, backend_addr);
+                } else {
+                    warn!("[{}] Backend {} marked NOT READY (draining)", self.rule_name, backend_addr);
+                }
+            }
+        }
+    }
+
+    // Selection for a specific client; hash-based strategies key on the IP,
+    // the others ignore it.
+    pub fn next_backend_for(&self, client_ip: IpAddr) -> Option<(String, ConnectionGuard)> {
+        let Some(sticky) = &self.sticky else {
+            return self.select_for(client_ip);
+        };
+
+        if let Some(backend) = self.sticky_backend(sticky, client_ip) {
+            log::debug!("Sticky session: {} -> {}", client_ip, backend.addr);
+            return Some(self.acquire(&backend));
+        }
+
+        let selected = self.select_for(client_ip)?;
+        sticky.entries.insert(client_ip, (selected.0.clone(), Instant::now()));
+        if sticky.inserts.fetch_add(1, Ordering::Relaxed) % STICKY_SWEEP_EVERY == STICKY_SWEEP_EVERY - 1 {
+            let ttl = sticky.ttl;
+            sticky.entries.retain(|_, (_, last_used)| last_used.elapsed() < ttl);
+        }
+        Some(selected)
+    }
+
+    // Still-valid sticky backend for this client; expired entries are evicted on lookup
+    fn sticky_backend(&self, sticky: &StickyTable, client_ip: IpAddr) -> Option<Arc<Backend>> {
+        let mut entry = sticky.entries.get_mut(&client_ip)?;
+        if entry.1.elapsed() >= sticky.ttl {
+            drop(entry);
+            sticky.entries.remove(&client_ip);
+            return None;
+        }
+
+        // Only primaries: a client pinned to a backup returns to the primaries once one recovers
+        let backends = self.backends.load();
+        let backend = backends.iter().find(|b| b.addr == entry.0 && self.is_available(b))?;
+        entry.1 = Instant::now();
+        Some(backend.clone())
+    }
+
+    fn select_for(&self, client_ip: IpAddr) -> Option<(String, ConnectionGuard)> {
+        match self.strategy {
+            Strategy::ConsistentHash | Strategy::Maglev | Strategy::IpHash => {
+                let selected = match self.strategy {
+                    Strategy::Maglev => self.pick_from_maglev(client_ip, &[]),
+                    Strategy::IpHash => self.pick_ip_hash(&self.backends.load(), client_ip, &[]),
+                    _ => self.pick_from_ring(client_ip, &[]),
+                }
+                .or_else(|| self.pick_backup(Some(client_ip), &[]))
+                .or_else(|| self.pick_overflow(&[]));
+                match selected {
+                    Some(backend) => Some(self.acquire(&backend)),
+                    None => {
+                        warn!("All backends are at capacity, unhealthy, or draining");
+                        None
+                    }
+                }
             }
+            _ => self.next_backend(),
         }
     }
 
+    // First available backend clockwise from the client's hash point
+    fn pick_from_ring(&self, client_ip: IpAddr, excluded: &[String]) -> Option<Arc<Backend>> {
+        let ring = self.ring.load();
+        if ring.is_empty() {
+            log::debug!("No backends configured");
+            return None;
+        }
+
+        let point = hash_of(&client_ip);
+        let start = ring.partition_point(|(h, _)| *h < point);
+        (0..ring.len())
+            .map(|i| &ring[(start + i) % ring.len()].1)
+            .find(|backend| !excluded.contains(&backend.addr) && self.is_available(backend))
+            .cloned()
+    }
+
+    // Client's hash modulo the available backends. Cheap, but any change in that
+    // set (a backend failing, draining, or being added) remaps most clients.
+    fn pick_ip_hash(&self, backends: &[Arc<Backend>], client_ip: IpAddr, excluded: &[String]) -> Option<Arc<Backend>> {
+        let available: Vec<&Arc<Backend>> = backends.iter()
+            .filter(|b| !excluded.contains(&b.addr) && self.is_available(b))
+            .collect();
+        if available.is_empty() {
+            return None;
+        }
+        let idx = (hash_of(&client_ip) % available.len() as u64) as usize;
+        Some(available[idx].clone())
+    }
+
+    // Client's slot in the Maglev table; unavailable backends are skipped by probing
+    // the following slots, so only the clients of that backend move (evenly).
+    fn pick_from_maglev(&self, client_ip: IpAddr, excluded: &[String]) -> Option<Arc<Backend>> {
+        let table = self.maglev.load();
+        if table.slots.is_empty() {
+            log::debug!("No backends configured");
+            return None;
+        }
+
+        let start = (hash_of(&client_ip) % table.slots.len() as u64) as usize;
+        let mut rejected = vec![false; table.backends.len()];
+        let mut remaining = table.backends.len();
+        for i in 0..table.slots.len() {
+            let idx = table.slots[(start + i) % table.slots.len()] as usize;
+            if rejected[idx] {
+                continue;
+            }
+            let backend = &table.backends[idx];
+            if !excluded.contains(&backend.addr) && self.is_available(backend) {
+                return Some(backend.clone());
+            }
+            rejected[idx] = true;
+            remaining -= 1;
+            if remaining == 0 {
+                break;
+            }
+        }
+        None
+    }
+
+    // Selection for a connect retry: same strategy, but never one of the backends
+    // that already failed for this client. Sticky entries are not consulted, the
+    // pinned backend is the one that just failed.
+    pub fn next_backend_excluding(&self, client_ip: IpAddr, excluded: &[String]) -> Option<(String, ConnectionGuard)> {
+        let selected = match self.strategy {
+            // Next backend clockwise on the ring, like a removed backend's clients
+            Strategy::ConsistentHash => self.pick_from_ring(client_ip, excluded),
+            Strategy::Maglev => self.pick_from_maglev(client_ip, excluded),
+            Strategy::IpHash => self.pick_ip_hash(&self.backends.load(), client_ip, excluded),
+            _ => {
+                let candidates: Vec<Arc<Backend>> = self.backends.load().iter()
+                    .filter(|b| !excluded.contains(&b.addr))
+                    .cloned()
+                    .collect();
+                let start_index = self.current.fetch_add(1, Ordering::Relaxed);
+                self.pick_by_strategy(&candidates, start_index).cloned()
+            }
+        }
+        .or_else(|| self.pick_backup(Some(client_ip), excluded))
+        .or_else(|| self.pick_overflow(excluded));
+        selected.map(|backend| self.acquire(&backend))
+    }
+
+    // Backup pool selection, reached only when no primary backend is available.
+    // Hash strategies keep client affinity with a modulo hash over the backups.
+    fn pick_backup(&self, client_ip: Option<IpAddr>, excluded: &[String]) -> Option<Arc<Backend>> {
+        let backups = self.backup_backends.load();
+        if backups.is_empty() {
+            return None;
+        }
+        let selected = match (self.strategy, client_ip) {
+            (Strategy::ConsistentHash | Strategy::Maglev | Strategy::IpHash, Some(client_ip)) => self.pick_ip_hash(&backups, client_ip, excluded),
+            _ => {
+                let candidates: Vec<Arc<Backend>> = backups.iter()
+                    .filter(|b| !excluded.contains(&b.addr))
+                    .cloned()
+                    .collect();
+                let start_index = self.current.fetch_add(1, Ordering::Relaxed);
+                self.pick_by_strategy(&candidates, start_index).cloned()
+            }
+        };
+        if let Some(backend) = &selected {
+            log::debug!("No primary backend available, failing over to backup {}", backend.addr);
+        }
+        selected
+    }
+
     pub fn next_backend(&self) -> Option<(String, ConnectionGuard)> {
         // Wait-free read!
         let backends = self.backends.load();
-        if backends.is_empty() {
+        if backends.is_empty() && self.backup_backends.load().is_empty() {
             log::debug!("No backends configured");
             return None;
         }
 
         let start_index = self.current.fetch_add(1, Ordering::Relaxed);
+        let selected = self.pick_by_strategy(&backends, start_index).cloned()
+            .or_else(|| self.pick_backup(None, &[]))
+            .or_else(|| self.pick_overflow(&[]));
+
+        match selected {
+            Some(backend) => Some(self.acquire(&backend)),
+            None => {
+                warn!("All backends are at capacity, unhealthy, or draining");
+                None
+            }
+        }
+    }
+
+    // Overflow::LeastLoaded, once nothing has room: the eligible backend with the
+    // fewest active connections goes over the limit (primaries before backups)
+    fn pick_overflow(&self, excluded: &[String]) -> Option<Arc<Backend>> {
+        if self.overflow != Overflow::LeastLoaded || self.connection_limit.is_none() {
+            return None;
+        }
+        let selected = [&self.backends, &self.backup_backends].into_iter().find_map(|pool| {
+            pool.load().iter()
+                .filter(|b| !excluded.contains(&b.addr) && self.is_eligible(b))
+                .min_by_key(|b| b.active_connections.load(Ordering::Relaxed))
+                .cloned()
+        });
+        if let Some(backend) = &selected {
+            log::debug!("All backends at the connection limit, overflowing to {} ({} active)", backend.addr, backend.active_connections.load(Ordering::Relaxed));
+        }
+        selected
+    }
+
+    fn pick_by_strategy<'a>(&self, backends: &'a [Arc<Backend>], start_index: usize) -> Option<&'a Arc<Backend>> {
+        match self.strategy {
+            Strategy::RoundRobin => self.pick_weighted_round_robin(backends),
+            Strategy::LeastConnections => self.pick_least_connections(backends, start_index),
+            Strategy::WeightedLeastConnections => self.pick_weighted_least_connections(backends, start_index),
+            Strategy::P2C => self.pick_power_of_two(backends),
+            Strategy::LeastResponseTime => self.pick_least_response_time(backends, start_index),
+            Strategy::Random => self.pick_random(backends),
+            // No client key available: fall back to round-robin
+            Strategy::ConsistentHash | Strategy::Maglev | Strategy::IpHash => self.pick_weighted_round_robin(backends),
+        }
+    }
+
+    // Smooth weighted round-robin (nginx): every available backend gains its weight,
+    // the highest running weight wins and pays back the total. A weight-3 backend
+    // gets 3 of every 4 picks against a weight-1 peer, interleaved rather than bursty.
+    fn pick_weighted_round_robin<'a>(&self, backends: &'a [Arc<Backend>]) -> Option<&'a Arc<Backend>> {
+        let mut total: i64 = 0;
+        let mut best: Option<(&'a Arc<Backend>, i64)> = None;
+        for backend in backends.iter() {
+            if !self.is_available(backend) {
+                continue;
+            }
+            let weight = backend.effective_weight() as i64;
+            let current = backend.current_weight.fetch_add(weight, Ordering::Relaxed) + weight;
+            total += weight;
+            if best.is_none_or(|(_, best_current)| current > best_current) {
+                best = Some((backend, current));
+            }
+        }
+
+        let (backend, _) = best?;
+        backend.current_weight.fetch_sub(total, Ordering::Relaxed);
+        Some(backend)
+    }
+
+    // Lowest active_connections wins. The scan starts at the round-robin position so
+    // ties rotate across backends instead of always landing on the first one.
+    fn pick_least_connections<'a>(&self, backends: &'a [Arc<Backend>], start_index: usize) -> Option<&'a Arc<Backend>> {
         let len = backends.len();
+        let mut best: Option<(&'a Arc<Backend>, usize)> = None;
+        for i in 0..len {
+            let backend = &backends[(start_index + i) % len];
+            if !self.is_available(backend) {
+                continue;
+            }
+            let load = backend.active_connections.load(Ordering::Relaxed);
+            if best.is_none_or(|(_, best_load)| load < best_load) {
+                best = Some((backend, load));
+            }
+        }
+        best.map(|(backend, _)| backend)
+    }
 
+    // Lowest active_connections / weight, compared as cross products to stay in
+    // integers. A weight-3 backend takes three connections for every one on a
+    // weight-1 peer before it is passed over; ties rotate like least_connections.
+    fn pick_weighted_least_connections<'a>(&self, backends: &'a [Arc<Backend>], start_index: usize) -> Option<&'a Arc<Backend>> {
+        let len = backends.len();
+        let mut best: Option<(&'a Arc<Backend>, u64, u64)> = None;
         for i in 0..len {
-            let idx = (start_index + i) % len;
-            let backend = &backends[idx];
+            let backend = &backends[(start_index + i) % len];
+            if !self.is_available(backend) {
+                continue;
+            }
+            let load = backend.active_connections.load(Ordering::Relaxed) as u64;
+            let weight = backend.effective_weight(); // Non-zero: checked by is_available
+            if best.is_none_or(|(_, best_load, best_weight)| load * best_weight < best_load * weight) {
+                best = Some((backend, load, weight));
+            }
+        }
+        best.map(|(backend, _, _)| backend)
+    }
 
-            // Check if backend is manually disabled (draining)
-            if backend.drain.load(Ordering::Relaxed) {
-                log::debug!("Backend {} skipped (draining)", backend.addr);
+    // Lowest response time EWMA, scaled by active connections + 1 so a burst of new
+    // connections doesn't all land on the currently fastest backend before its
+    // durations come in. Backends without samples score lowest and get tried first.
+    fn pick_least_response_time<'a>(&self, backends: &'a [Arc<Backend>], start_index: usize) -> Option<&'a Arc<Backend>> {
+        let len = backends.len();
+        let mut best: Option<(&'a Arc<Backend>, u128)> = None;
+        for i in 0..len {
+            let backend = &backends[(start_index + i) % len];
+            if !self.is_available(backend) {
                 continue;
             }
+            let active = backend.active_connections.load(Ordering::Relaxed) as u128;
+            let score = (backend.response_time.current_us() as u128 + 1) * (active + 1);
+            if best.is_none_or(|(_, best_score)| score < best_score) {
+                best = Some((backend, score));
+            }
+        }
+        best.map(|(backend, _)| backend)
+    }
+
+    // Weighted random pick among the available backends. No shared cursor, so
+    // many acceptors selecting at once don't move through the pool in lockstep.
+    fn pick_random<'a>(&self, backends: &'a [Arc<Backend>]) -> Option<&'a Arc<Backend>> {
+        let available: Vec<(&'a Arc<Backend>, u64)> = backends.iter()
+            .filter(|b| self.is_available(b))
+            .map(|b| (b, b.effective_weight()))
+            .collect();
+        let total: u64 = available.iter().map(|(_, weight)| weight).sum();
+        if total == 0 {
+            return None;
+        }
+        let mut point = rand::rng().random_range(0..total);
+        for (backend, weight) in available {
+            if point < weight {
+                return Some(backend);
+            }
+            point -= weight;
+        }
+        None
+    }
+
+    // Power of two choices: sample two distinct backends, keep the less loaded one.
+    // O(1) in the common case; only when a sample is unavailable do we fall back
+    // to sampling from the (scanned) available set.
+    fn pick_power_of_two<'a>(&self, backends: &'a [Arc<Backend>]) -> Option<&'a Arc<Backend>> {
+        let mut rng = rand::rng();
+        let less_loaded = |a: &'a Arc<Backend>, b: &'a Arc<Backend>| {
+            if b.active_connections.load(Ordering::Relaxed) < a.active_connections.load(Ordering::Relaxed) { b } else { a }
+        };
 
-            if !backend.healthy.load(Ordering::Relaxed) {
-                log::debug!("Backend {} skipped (unhealthy)", backend.addr);
-                continue; // Skip unhealthy backends
+        if backends.len() >= 2 {
+            let (a, b) = two_distinct(&mut rng, backends.len());
+            if self.is_available(&backends[a]) && self.is_available(&backends[b]) {
+                return Some(less_loaded(&backends[a], &backends[b]));
             }
+        }
 
-            if let Some(limit) = self.connection_limit {
-                let current_conns = backend.active_connections.load(Ordering::Relaxed);
-                if current_conns >= limit {
-                    log::debug!("Backend {} skipped (connection limit reached: {}/{})", backend.addr, current_conns, limit);
-                    continue; // Backend full, try next
-                }
+        let available: Vec<&'a Arc<Backend>> = backends.iter().filter(|b| self.is_available(b)).collect();
+        match available.len() {
+            0 => None,
+            1 => Some(available[0]),
+            n => {
+                let (a, b) = two_distinct(&mut rng, n);
+                Some(less_loaded(available[a], available[b]))
             }
+        }
+    }
 
-            // Increment active connections
-            backend.active_connections.fetch_add(1, Ordering::Relaxed);
-            
-            // Metric Increment
-            crate::metrics::BACKEND_ACTIVE_CONNECTIONS.with_label_values(&[&backend.rule_name, &backend.addr]).inc();
+    // Draining, not ready, unhealthy and full backends are never selected
+    // At least one backend could take traffic (ignores the per-backend connection limit)
+    pub fn has_healthy_backend(&self) -> bool {
+        let (backends, backups) = (self.backends.load(), self.backup_backends.load());
+        backends.iter().chain(backups.iter()).any(|b| {
+            b.healthy.load(Ordering::Relaxed)
+                && b.ready.load(Ordering::Relaxed)
+                && !b.draining()
+                && b.weight.load(Ordering::Relaxed) > 0
+                && !(self.passive.is_some() && b.passively_down())
+                && !(self.outlier.is_some() && b.outlier_ejected())
+        })
+    }
 
-            log::debug!("Selected backend: {} (active: {})", backend.addr, backend.active_connections.load(Ordering::Relaxed));
-            return Some((
-                backend.addr.clone(),
-                ConnectionGuard {
-                    rule_name: backend.rule_name.clone(), // Added
-                    backend_addr: backend.addr.clone(),   // Added
-                    counter: backend.active_connections.clone(),
-                }
-            ));
+    fn is_available(&self, backend: &Backend) -> bool {
+        if !self.is_eligible(backend) {
+            return false;
         }
 
-        warn!("All backends are at capacity, unhealthy, or draining");
-        None
+        if let Some(limit) = self.connection_limit {
+            let current_conns = backend.active_connections.load(Ordering::Relaxed);
+            if current_conns >= limit {
+                log::debug!("Backend {} skipped (connection limit reached: {}/{})", backend.addr, current_conns, limit);
+                return false;
+            }
+        }
+
+        true
+    }
+
+    // Everything but the connection limit
+    fn is_eligible(&self, backend: &Backend) -> bool {
+        // Check if backend is manually disabled (draining)
+        if backend.draining() {
+            log::debug!("Backend {} skipped (draining)", backend.addr);
+            return false;
+        }
+
+        if backend.weight.load(Ordering::Relaxed) == 0 {
+            log::debug!("Backend {} skipped (weight 0)", backend.addr);
+            return false;
+        }
+
+        if !backend.ready.load(Ordering::Relaxed) {
+            log::debug!("Backend {} skipped (not ready)", backend.addr);
+            return false;
+        }
+
+        if !backend.healthy.load(Ordering::Relaxed) {
+            log::debug!("Backend {} skipped (unhealthy)", backend.addr);
+            return false;
+        }
+
+        if self.passive.is_some() && backend.passively_down() {
+            log::debug!("Backend {} skipped (passive health)", backend.addr);
+            return false;
+        }
+
+        if self.outlier.is_some() && backend.outlier_ejected() {
+            log::debug!("Backend {} skipped (outlier ejected)", backend.addr);
+            return false;
+        }
+
+        true
+    }
+
+    fn acquire(&self, backend: &Backend) -> (String, ConnectionGuard) {
+        // Increment active connections
+        backend.active_connections.fetch_add(1, Ordering::Relaxed);
+
+        // Metric Increment
+        crate::metrics::BACKEND_ACTIVE_CONNECTIONS.with_label_values(&[&backend.rule_name, &backend.addr]).inc();
+        crate::metrics::BACKEND_SELECTED.with_label_values(&[&backend.rule_name, &backend.addr]).inc();
+        crate::metrics::BACKEND_SELECTIONS.with_label_values(&[&backend.rule_name, if backend.backup { "backup" } else { "primary" }]).inc();
+
+        log::debug!("Selected backend: {} (active: {})", backend.addr, backend.active_connections.load(Ordering::Relaxed));
+        (
+            backend.addr.clone(),
+            ConnectionGuard {
+                rule_name: backend.rule_name.clone(), // Added
+                backend_addr: backend.addr.clone(),   // Added
+                counter: backend.active_connections.clone(),
+                retirement: backend.retirement.clone(),
+            }
+        )
     }
 }
 
+// Two distinct indices in 0..n (n >= 2)
+fn two_distinct(rng: &mut impl Rng, n: usize) -> (usize, usize) {
+    let a = rng.random_range(0..n);
+    let mut b = rng.random_range(0..n - 1);
+    if b >= a {
+        b += 1;
+    }
+    (a, b)
+}
+
+// Applies a backend's saved admin drain and what is left of a saved ejection
+fn restore_backend(backend: &Backend, saved: &SavedRuleState) {
+    if saved.drained.contains(&backend.addr) {
+        backend.admin_drain.store(true, Ordering::Relaxed);
+    }
+    if let Some(left) = saved.ejection_left(&backend.addr) {
+        backend.outlier.lock().unwrap().ejected_until = Some(Instant::now() + left);
+    }
+}
+
+fn ejected_count(backends: &[Arc<Backend>]) -> usize {
+    backends.iter().filter(|b| b.outlier_ejected()).count()
+}
+
+fn hash_of<T: Hash>(value: &T) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+fn build_ring(backends: &[Arc<Backend>]) -> HashRing {
+    let mut ring: HashRing = backends.iter()
+        .flat_map(|backend| (0..RING_VNODES).map(move |i| (hash_of(&(&backend.addr, i)), backend.clone())))
+        .collect();
+    ring.sort_by_key(|(h, _)| *h);
+    ring
+}
+
+fn build_maglev_for(strategy: Strategy, backends: &[Arc<Backend>]) -> MaglevTable {
+    if strategy == Strategy::Maglev { build_maglev(backends, MAGLEV_TABLE_SIZE) } else { MaglevTable::default() }
+}
+
+// Maglev population (Eisenbud et al. 2016): each backend walks its own permutation
+// of the slots, derived from its address, and the backends take turns claiming
+// their next free slot until the table is full. A backend of weight w claims w
+// slots per turn. Keeping permutations address-based means adding or removing a
+// backend only moves a little more than its own share of slots.
+fn build_maglev(backends: &[Arc<Backend>], size: usize) -> MaglevTable {
+    let backends: Vec<Arc<Backend>> = backends.iter()
+        .filter(|b| b.weight.load(Ordering::Relaxed) > 0)
+        .take(u16::MAX as usize)
+        .cloned()
+        .collect();
+    if backends.is_empty() {
+        return MaglevTable::default();
+    }
+
+    let m = size as u64;
+    let offsets: Vec<u64> = backends.iter().map(|b| hash_of(&(&b.addr, "offset")) % m).collect();
+    let skips: Vec<u64> = backends.iter().map(|b| hash_of(&(&b.addr, "skip")) % (m - 1) + 1).collect();
+    let mut next = vec![0u64; backends.len()];
+    let mut slots: Vec<Option<u16>> = vec![None; size];
+    let mut filled = 0;
+
+    'fill: loop {
+        for (i, backend) in backends.iter().enumerate() {
+            for _ in 0..backend.weight.load(Ordering::Relaxed) {
+                let slot = loop {
+                    let candidate = ((offsets[i] + next[i] * skips[i]) % m) as usize;
+                    next[i] += 1;
+                    if slots[candidate].is_none() {
+                        break candidate;
+                    }
+                };
+                slots[slot] = Some(i as u16);
+                filled += 1;
+                if filled == size {
+                    break 'fill;
+                }
+            }
+        }
+    }
+
+    MaglevTable { backends, slots: slots.into_iter().map(|s| s.unwrap_or(0)).collect() }
+}
+
 pub struct ConnectionGuard {
     rule_name: String,
     backend_addr: String,
     counter: Arc<AtomicUsize>,
+    retirement: Arc<Retirement>,
+}
+
+impl ConnectionGuard {
+    /// Resolves once the backend was removed and its grace period is over
+    pub async fn retired(&self) {
+        loop {
+            let notified = self.retirement.notify.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+            if self.retirement.done.load(Ordering::Relaxed) {
+                return;
+            }
+            notified.await;
+        }
+    }
 }
 
 impl Drop for ConnectionGuard {
@@ -175,3 +1221,504 @@ impl Drop for ConnectionGuard {
         crate::metrics::BACKEND_ACTIVE_CONNECTIONS.with_label_values(&[&self.rule_name, &self.backend_addr]).dec();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::BackendConfig;
+
+    fn backends(addrs: &[&str]) -> Vec<BackendConfig> {
+        addrs.iter().map(|a| BackendConfig::Simple(a.to_string())).collect()
+    }
+
+    fn weighted(list: &[(&str, u32)]) -> Vec<BackendConfig> {
+        list.iter().map(|(a, w)| BackendConfig::Detailed { addr: a.to_string(), drain: false, weight: *w, dns: false, proxy_protocol: None, nodelay: None }).collect()
+    }
+
+    #[test]
+    fn test_weighted_round_robin_distribution() {
+        let lb = LoadBalancer::new("wrr_test".to_string(), weighted(&[("10.0.0.1:80", 3), ("10.0.0.2:80", 1), ("10.0.0.3:80", 0)]), None, Strategy::RoundRobin);
+
+        let picks: Vec<String> = (0..8).map(|_| lb.next_backend().unwrap().0).collect();
+        assert_eq!(picks.iter().filter(|a| *a == "10.0.0.1:80").count(), 6);
+        assert_eq!(picks.iter().filter(|a| *a == "10.0.0.2:80").count(), 2);
+        // Weight 0 is never selected
+        assert!(!picks.iter().any(|a| a == "10.0.0.3:80"));
+        // Smooth: the light backend is interleaved, not batched at the end
+        assert_ne!(picks[..4].iter().filter(|a| *a == "10.0.0.2:80").count(), 0);
+        // Every pick is counted per backend
+        let selected = |addr| crate::metrics::BACKEND_SELECTED.with_label_values(&["wrr_test", addr]).get();
+        assert_eq!((selected("10.0.0.1:80"), selected("10.0.0.2:80"), selected("10.0.0.3:80")), (6, 2, 0));
+    }
+
+    #[tokio::test]
+    async fn test_consistent_hash_affinity() {
+        let lb = LoadBalancer::new("ch_test".to_string(), backends(&["10.0.0.1:80", "10.0.0.2:80", "10.0.0.3:80"]), None, Strategy::ConsistentHash);
+        let clients: Vec<IpAddr> = (1..=50).map(|i| IpAddr::from([192, 168, 1, i])).collect();
+
+        // Same IP -> same backend, every time
+        let first: Vec<String> = clients.iter().map(|ip| lb.next_backend_for(*ip).unwrap().0).collect();
+        for _ in 0..3 {
+            let again: Vec<String> = clients.iter().map(|ip| lb.next_backend_for(*ip).unwrap().0).collect();
+            assert_eq!(first, again);
+        }
+
+        // Removing one backend only moves the clients that were on it
+        lb.update_backends(backends(&["10.0.0.1:80", "10.0.0.2:80"])).await;
+        for (ip, before) in clients.iter().zip(&first) {
+            let after = lb.next_backend_for(*ip).unwrap().0;
+            if before != "10.0.0.3:80" {
+                assert_eq!(&after, before);
+            }
+        }
+    }
+
+    #[test]
+    fn test_ip_hash_affinity() {
+        let lb = LoadBalancer::new("ip_hash_test".to_string(), backends(&["10.0.0.1:80", "10.0.0.2:80", "10.0.0.3:80"]), None, Strategy::IpHash);
+        let clients: Vec<IpAddr> = (1..=50).map(|i| IpAddr::from([192, 168, 1, i])).collect();
+
+        let first: Vec<String> = clients.iter().map(|ip| lb.next_backend_for(*ip).unwrap().0).collect();
+        let again: Vec<String> = clients.iter().map(|ip| lb.next_backend_for(*ip).unwrap().0).collect();
+        assert_eq!(first, again);
+        assert!(["10.0.0.1:80", "10.0.0.2:80", "10.0.0.3:80"].iter().all(|b| first.iter().any(|a| a == b)));
+
+        // A draining backend is never picked; its clients spread over the rest
+        lb.backends.load()[2].drain.store(true, Ordering::Relaxed);
+        assert!(clients.iter().all(|ip| lb.next_backend_for(*ip).unwrap().0 != "10.0.0.3:80"));
+    }
+
+    #[tokio::test]
+    async fn test_backup_pool_failover() {
+        let lb = LoadBalancer::new("backup_test".to_string(), backends(&["10.0.0.1:80", "10.0.0.2:80"]), None, Strategy::RoundRobin)
+            .with_backup_backends(backends(&["10.0.1.1:80"]));
+        let client = IpAddr::from([192, 168, 1, 1]);
+        let backup_picks = || crate::metrics::BACKEND_SELECTIONS.with_label_values(&["backup_test", "backup"]).get();
+
+        // Primaries available: the backup is never used
+        assert!((0..10).all(|_| lb.next_backend().unwrap().0 != "10.0.1.1:80"));
+        assert_eq!(backup_picks(), 0);
+
+        lb.set_backend_health("10.0.0.1:80", false).await;
+        lb.set_backend_health("10.0.0.2:80", false).await;
+        assert_eq!(lb.next_backend().unwrap().0, "10.0.1.1:80");
+        assert_eq!(lb.next_backend_for(client).unwrap().0, "10.0.1.1:80");
+        assert!(lb.has_healthy_backend());
+        assert_eq!(backup_picks(), 2);
+
+        // A retry never lands on a backup that already failed
+        assert!(lb.next_backend_excluding(client, &["10.0.1.1:80".to_string()]).is_none());
+
+        // Health checks reach the backup pool too
+        lb.set_backend_health("10.0.1.1:80", false).await;
+        assert!(lb.next_backend().is_none());
+
+        // One primary recovers: new connections go back to it
+        lb.set_backend_health("10.0.0.2:80", true).await;
+        lb.set_backend_health("10.0.1.1:80", true).await;
+        assert!((0..10).all(|_| lb.next_backend().unwrap().0 == "10.0.0.2:80"));
+        assert_eq!(backup_picks(), 2);
+    }
+
+    #[test]
+    fn test_p2c_avoids_loaded_backend() {
+        let lb = LoadBalancer::new("p2c_test".to_string(), backends(&["10.0.0.1:80", "10.0.0.2:80"]), None, Strategy::P2C);
+
+        // With two backends both are always sampled, so the busy one is never chosen
+        let (busy, _busy_guard) = lb.next_backend().unwrap();
+        for _ in 0..20 {
+            let (addr, guard) = lb.next_backend().unwrap();
+            assert_ne!(addr, busy);
+            drop(guard);
+        }
+
+        // Only one available backend: it is returned rather than failing
+        let single = LoadBalancer::new("p2c_single".to_string(), backends(&["10.0.0.1:80"]), None, Strategy::P2C);
+        assert_eq!(single.next_backend().unwrap().0, "10.0.0.1:80");
+    }
+
+    #[tokio::test]
+    async fn test_backend_proxy_protocol_override() {
+        let configs: Vec<BackendConfig> = serde_yaml::from_str(r#"
+- "10.0.0.1:80"
+- { addr: "10.0.0.2:80", proxy_protocol: true }
+- { addr: "10.0.0.3:80", proxy_protocol: false }
+"#).unwrap();
+        let lb = LoadBalancer::new("pp_test".to_string(), configs, None, Strategy::RoundRobin);
+        assert_eq!(lb.backend_proxy_protocol("10.0.0.1:80"), None);
+        assert_eq!(lb.backend_proxy_protocol("10.0.0.2:80"), Some(true));
+        assert_eq!(lb.backend_proxy_protocol("10.0.0.3:80"), Some(false));
+
+        // Flipped on reload without replacing the backend
+        lb.update_backends(serde_yaml::from_str(r#"[{ addr: "10.0.0.1:80", proxy_protocol: true }]"#).unwrap()).await;
+        assert_eq!(lb.backend_proxy_protocol("10.0.0.1:80"), Some(true));
+    }
+
+    #[tokio::test]
+    async fn test_admin_drain_survives_restart() {
+        let path = std::env::temp_dir().join(format!("l4lb_drain_test_{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let start = || LoadBalancer::new("admin_drain_test".to_string(), backends(&["10.0.0.1:80", "10.0.0.2:80"]), None, Strategy::RoundRobin)
+            .with_state_store(Arc::new(StateStore::open(&path)));
+
+        let lb = start();
+        assert!(lb.set_admin_drain("10.0.0.1:80", true));
+        assert!(!lb.set_admin_drain("10.9.9.9:80", true));
+        // A reload re-applies the config's drain, not the admin one
+        lb.update_backends(backends(&["10.0.0.1:80", "10.0.0.2:80"])).await;
+        assert!((0..4).all(|_| lb.next_backend().unwrap().0 == "10.0.0.2:80"));
+
+        // Restarted: still drained
+        let lb = start();
+        assert!(lb.backend("10.0.0.1:80").unwrap().draining());
+        assert!((0..4).all(|_| lb.next_backend().unwrap().0 == "10.0.0.2:80"));
+
+        lb.set_admin_drain("10.0.0.1:80", false);
+        assert!(!start().backend("10.0.0.1:80").unwrap().draining());
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn test_backend_nodelay_override() {
+        let configs: Vec<BackendConfig> = serde_yaml::from_str(r#"
+- "10.0.0.1:80"
+- { addr: "10.0.0.2:80", nodelay: false }
+"#).unwrap();
+        let lb = LoadBalancer::new("nodelay_test".to_string(), configs, None, Strategy::RoundRobin);
+        assert_eq!(lb.backend_nodelay("10.0.0.1:80"), None);
+        assert_eq!(lb.backend_nodelay("10.0.0.2:80"), Some(false));
+
+        lb.update_backends(serde_yaml::from_str(r#"[{ addr: "10.0.0.2:80", nodelay: true }]"#).unwrap()).await;
+        assert_eq!(lb.backend_nodelay("10.0.0.2:80"), Some(true));
+    }
+
+    #[tokio::test]
+    async fn test_removed_backend_drains_then_closes() {
+        let lb = LoadBalancer::new("grace_test".to_string(), backends(&["10.0.0.1:80", "10.0.0.2:80"]), None, Strategy::RoundRobin)
+            .with_removal_grace(Duration::from_millis(50));
+        let (first, guard) = lb.next_backend().unwrap();
+        let kept = if first == "10.0.0.1:80" { "10.0.0.2:80" } else { "10.0.0.1:80" };
+
+        lb.update_backends(backends(&[kept])).await;
+        assert_eq!(lb.retiring_backends().len(), 1);
+        assert!((0..4).all(|_| lb.next_backend().unwrap().0 == kept));
+        // The open connection survives the reload, then is told to close
+        assert!(tokio::time::timeout(Duration::from_millis(20), guard.retired()).await.is_err());
+        tokio::time::timeout(Duration::from_millis(500), guard.retired()).await.unwrap();
+        assert!(lb.retiring_backends().is_empty());
+
+        // Re-added within the grace period: back in service, never closed
+        let (addr, guard) = lb.next_backend().unwrap();
+        lb.update_backends(backends(&[first.as_str()])).await;
+        lb.update_backends(backends(&[first.as_str(), kept])).await;
+        assert!(lb.retiring_backends().is_empty());
+        assert_eq!(addr, kept);
+        assert!(tokio::time::timeout(Duration::from_millis(100), guard.retired()).await.is_err());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_removed_again_gets_full_grace() {
+        let lb = LoadBalancer::new("regrace_test".to_string(), backends(&["10.0.0.1:80", "10.0.0.2:80"]), None, Strategy::RoundRobin)
+            .with_removal_grace(Duration::from_millis(100));
+        let (first, guard) = lb.next_backend().unwrap();
+        let kept = if first == "10.0.0.1:80" { "10.0.0.2:80" } else { "10.0.0.1:80" };
+
+        // Removed, re-added, removed again 60ms in
+        lb.update_backends(backends(&[kept])).await;
+        tokio::time::advance(Duration::from_millis(60)).await;
+        lb.update_backends(backends(&[first.as_str(), kept])).await;
+        lb.update_backends(backends(&[kept])).await;
+
+        // The first removal's timer fires at 100ms and leaves the second grace alone
+        tokio::time::advance(Duration::from_millis(50)).await;
+        tokio::task::yield_now().await;
+        assert_eq!(lb.retiring_backends().len(), 1);
+        assert!(!guard.retirement.done.load(Ordering::Relaxed));
+
+        // Closed once the second grace period (ending at 160ms) is over
+        tokio::time::advance(Duration::from_millis(51)).await;
+        tokio::task::yield_now().await;
+        assert!(lb.retiring_backends().is_empty());
+        tokio::time::timeout(Duration::ZERO, guard.retired()).await.unwrap();
+    }
+
+    #[test]
+    fn test_random_follows_weights() {
+        let lb = LoadBalancer::new("random_test".to_string(), weighted(&[("10.0.0.1:80", 3), ("10.0.0.2:80", 1), ("10.0.0.3:80", 0)]), None, Strategy::Random);
+
+        let picks: Vec<String> = (0..4000).map(|_| lb.next_backend().unwrap().0).collect();
+        let heavy = picks.iter().filter(|a| *a == "10.0.0.1:80").count();
+        assert!((2700..3300).contains(&heavy), "heavy picked {} times", heavy);
+        // Weight 0 is never selected
+        assert!(!picks.iter().any(|a| a == "10.0.0.3:80"));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_sticky_sessions_reuse_and_expire() {
+        let lb = LoadBalancer::new("sticky_test".to_string(), backends(&["10.0.0.1:80", "10.0.0.2:80", "10.0.0.3:80"]), None, Strategy::RoundRobin)
+            .with_sticky(Duration::from_millis(50));
+        let client = IpAddr::from([192, 168, 1, 10]);
+
+        // Round-robin would rotate, sticky keeps the first choice
+        let first = lb.next_backend_for(client).unwrap().0;
+        for _ in 0..5 {
+            assert_eq!(lb.next_backend_for(client).unwrap().0, first);
+        }
+
+        // A draining sticky backend is abandoned for a fresh pick
+        let backends = lb.backends.load();
+        let sticky_backend = backends.iter().find(|b| b.addr == first).unwrap();
+        sticky_backend.drain.store(true, Ordering::Relaxed);
+        let moved = lb.next_backend_for(client).unwrap().0;
+        assert_ne!(moved, first);
+        sticky_backend.drain.store(false, Ordering::Relaxed);
+        assert_eq!(lb.next_backend_for(client).unwrap().0, moved);
+
+        // Each hit refreshes the entry, so the TTL is measured from the last use
+        tokio::time::advance(Duration::from_millis(49)).await;
+        assert_eq!(lb.next_backend_for(client).unwrap().0, moved);
+        tokio::time::advance(Duration::from_millis(49)).await;
+        assert_eq!(lb.next_backend_for(client).unwrap().0, moved);
+
+        // Past the TTL the entry is evicted on lookup and re-assigned
+        tokio::time::advance(Duration::from_millis(51)).await;
+        assert_ne!(lb.next_backend_for(client).unwrap().0, moved);
+        assert_eq!(lb.sticky.as_ref().unwrap().entries.len(), 1);
+    }
+
+    #[test]
+    fn test_least_connections_prefers_idle_backend() {
+        let lb = LoadBalancer::new("lc_test".to_string(), backends(&["10.0.0.1:80", "10.0.0.2:80", "10.0.0.3:80"]), None, Strategy::LeastConnections);
+
+        // Three picks on an idle pool spread across all backends (ties rotate)
+        let g1 = lb.next_backend().unwrap();
+        let g2 = lb.next_backend().unwrap();
+        let g3 = lb.next_backend().unwrap();
+        let mut picked = vec![g1.0.clone(), g2.0.clone(), g3.0.clone()];
+        picked.sort();
+        assert_eq!(picked, vec!["10.0.0.1:80", "10.0.0.2:80", "10.0.0.3:80"]);
+
+        // Release one connection: that backend is now the least loaded
+        let freed = g2.0.clone();
+        drop(g2);
+        for _ in 0..3 {
+            let (addr, guard) = lb.next_backend().unwrap();
+            assert_eq!(addr, freed);
+            drop(guard);
+        }
+    }
+
+    #[test]
+    fn test_overflow_least_loaded() {
+        let full = |overflow| {
+            let lb = LoadBalancer::new("overflow_test".to_string(), backends(&["10.0.0.1:80", "10.0.0.2:80"]), Some(1), Strategy::RoundRobin)
+                .with_overflow(overflow);
+            let guards = vec![lb.next_backend().unwrap(), lb.next_backend().unwrap()];
+            (lb, guards)
+        };
+
+        // Default: at the limit everywhere, the connection is refused
+        let (lb, _guards) = full(Overflow::Reject);
+        assert!(lb.next_backend().is_none());
+
+        // Soft limit: the least loaded backend goes over it
+        let (lb, _guards) = full(Overflow::LeastLoaded);
+        let (first, _g3) = lb.next_backend().unwrap();
+        let (second, _g4) = lb.next_backend().unwrap();
+        assert_ne!(first, second);
+
+        // Unhealthy backends stay out, limit or not
+        lb.backend(&first).unwrap().healthy.store(false, Ordering::Relaxed);
+        assert!((0..3).all(|_| lb.next_backend().unwrap().0 == second));
+    }
+
+    #[test]
+    fn test_weighted_least_connections_scales_by_weight() {
+        let lb = LoadBalancer::new("wlc_test".to_string(), weighted(&[("10.0.0.1:80", 3), ("10.0.0.2:80", 1), ("10.0.0.3:80", 0)]), None, Strategy::WeightedLeastConnections);
+
+        // Held connections settle at the 3:1 weight ratio; weight 0 is never picked
+        let guards: Vec<_> = (0..8).map(|_| lb.next_backend().unwrap()).collect();
+        assert_eq!(guards.iter().filter(|(a, _)| a == "10.0.0.1:80").count(), 6);
+        assert_eq!(guards.iter().filter(|(a, _)| a == "10.0.0.2:80").count(), 2);
+    }
+
+    #[test]
+    fn test_least_response_time_prefers_fast_backend() {
+        let lb = LoadBalancer::new("lrt_test".to_string(), backends(&["10.0.0.1:80", "10.0.0.2:80"]), None, Strategy::LeastResponseTime);
+        lb.report_connection_duration("10.0.0.1:80", Duration::from_millis(500));
+        lb.report_connection_duration("10.0.0.2:80", Duration::from_millis(20));
+        for _ in 0..4 {
+            assert_eq!(lb.next_backend().unwrap().0, "10.0.0.2:80");
+        }
+
+        // Load counts too: 20ms with 30 open connections loses to 500ms idle
+        let held: Vec<_> = (0..30).map(|_| lb.next_backend().unwrap()).collect();
+        assert!(held.iter().any(|(addr, _)| addr == "10.0.0.1:80"));
+
+        // One slow sample moves the EWMA only part of the way
+        let slow = &lb.backends.load()[1].response_time;
+        slow.record(Duration::from_millis(1020));
+        let ewma = slow.current_us();
+        assert!(ewma > 200_000 && ewma < 230_000, "ewma {}", ewma);
+    }
+
+    #[test]
+    fn test_response_time_decays_when_stale() {
+        let rt = ResponseTime::new();
+        assert_eq!(rt.current_us(), 0);
+        rt.record(Duration::from_millis(100));
+        let half_life = RESPONSE_TIME_HALF_LIFE.as_millis() as u64;
+        assert_eq!(rt.current_at(rt.updated_ms.load(Ordering::Relaxed) + half_life), Some(50_000));
+        assert_eq!(rt.current_at(rt.updated_ms.load(Ordering::Relaxed) + 2 * half_life), Some(25_000));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_passive_health_ejects_after_failures() {
+        let lb = LoadBalancer::new("passive_test".to_string(), backends(&["10.0.0.1:80", "10.0.0.2:80"]), None, Strategy::RoundRobin)
+            .with_passive_health(2, Duration::from_millis(50));
+
+        lb.report_backend_failure("10.0.0.1:80");
+        lb.report_backend_success("10.0.0.1:80"); // Success resets the count
+        lb.report_backend_failure("10.0.0.1:80");
+        assert!((0..4).any(|_| lb.next_backend().unwrap().0 == "10.0.0.1:80"));
+
+        lb.report_backend_failure("10.0.0.1:80");
+        assert!((0..4).all(|_| lb.next_backend().unwrap().0 == "10.0.0.2:80"));
+
+        // Back in rotation once fail_timeout passes
+        tokio::time::advance(Duration::from_millis(49)).await;
+        assert!((0..4).all(|_| lb.next_backend().unwrap().0 == "10.0.0.2:80"));
+        tokio::time::advance(Duration::from_millis(2)).await;
+        assert!((0..4).any(|_| lb.next_backend().unwrap().0 == "10.0.0.1:80"));
+    }
+
+    #[test]
+    fn test_next_backend_excluding_skips_failed() {
+        let client: IpAddr = "192.168.1.10".parse().unwrap();
+        for strategy in [Strategy::RoundRobin, Strategy::LeastConnections, Strategy::WeightedLeastConnections, Strategy::P2C, Strategy::ConsistentHash, Strategy::Maglev, Strategy::LeastResponseTime, Strategy::Random, Strategy::IpHash] {
+            let lb = LoadBalancer::new("retry_test".to_string(), backends(&["10.0.0.1:80", "10.0.0.2:80", "10.0.0.3:80"]), None, strategy);
+            let failed = vec!["10.0.0.1:80".to_string(), "10.0.0.3:80".to_string()];
+
+            for _ in 0..4 {
+                assert_eq!(lb.next_backend_excluding(client, &failed).unwrap().0, "10.0.0.2:80");
+            }
+            let all: Vec<String> = ["10.0.0.1:80", "10.0.0.2:80", "10.0.0.3:80"].iter().map(|a| a.to_string()).collect();
+            assert!(lb.next_backend_excluding(client, &all).is_none());
+        }
+    }
+
+    #[test]
+    fn test_maglev_table_is_even() {
+        let lb = LoadBalancer::new("maglev_even".to_string(), backends(&["10.0.0.1:80", "10.0.0.2:80", "10.0.0.3:80", "10.0.0.4:80", "10.0.0.5:80"]), None, Strategy::Maglev);
+        let table = lb.maglev.load();
+        assert_eq!(table.slots.len(), MAGLEV_TABLE_SIZE);
+
+        let share = MAGLEV_TABLE_SIZE / 5;
+        for idx in 0..5u16 {
+            let count = table.slots.iter().filter(|s| **s == idx).count();
+            assert!(count.abs_diff(share) < share / 50, "backend {} owns {} slots, expected ~{}", idx, count, share);
+        }
+
+        // Weight 3 vs 1: three times the slots, weight 0 none
+        let weighted = build_maglev(&LoadBalancer::new("maglev_w".to_string(), weighted(&[("10.0.0.1:80", 3), ("10.0.0.2:80", 1), ("10.0.0.3:80", 0)]), None, Strategy::Maglev).backends.load(), MAGLEV_TABLE_SIZE);
+        assert_eq!(weighted.backends.len(), 2);
+        let heavy = weighted.slots.iter().filter(|s| **s == 0).count();
+        assert!(heavy.abs_diff(MAGLEV_TABLE_SIZE * 3 / 4) < MAGLEV_TABLE_SIZE / 50);
+    }
+
+    #[tokio::test]
+    async fn test_maglev_minimal_remapping() {
+        let addrs = ["10.0.0.1:80", "10.0.0.2:80", "10.0.0.3:80", "10.0.0.4:80", "10.0.0.5:80"];
+        let lb = LoadBalancer::new("maglev_remap".to_string(), backends(&addrs), None, Strategy::Maglev);
+        let clients: Vec<IpAddr> = (0..2000u32).map(|i| IpAddr::from((0x0a01_0000 + i).to_be_bytes())).collect();
+        let before: Vec<String> = clients.iter().map(|ip| lb.next_backend_for(*ip).unwrap().0).collect();
+
+        // Unhealthy: only its own clients move
+        lb.set_backend_health("10.0.0.3:80", false).await;
+        for (ip, old) in clients.iter().zip(&before) {
+            let now = lb.next_backend_for(*ip).unwrap().0;
+            assert!(now == *old || old == "10.0.0.3:80");
+            assert_ne!(now, "10.0.0.3:80");
+        }
+        lb.set_backend_health("10.0.0.3:80", true).await;
+
+        // Removed: its clients move, plus only a sliver of the others
+        lb.update_backends(backends(&["10.0.0.1:80", "10.0.0.2:80", "10.0.0.4:80", "10.0.0.5:80"])).await;
+        let moved = clients.iter().zip(&before)
+            .filter(|(ip, old)| *old != "10.0.0.3:80" && lb.next_backend_for(**ip).unwrap().0 != **old)
+            .count();
+        assert!(moved < clients.len() / 50, "{} of {} unaffected clients moved", moved, clients.len());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_outlier_detection_ejects_with_backoff() {
+        let config: crate::config::OutlierDetectionConfig = serde_yaml::from_str(
+            "{ interval_ms: 1000, error_rate_percent: 50, min_requests: 4, base_ejection_ms: 40, max_ejection_ms: 1000, max_ejection_percent: 50 }").unwrap();
+        let lb = LoadBalancer::new("outlier_test".to_string(), backends(&["10.0.0.1:80", "10.0.0.2:80"]), None, Strategy::RoundRobin)
+            .with_outlier_detection(&config);
+        let bad = "10.0.0.1:80";
+
+        // 1 failure in 4 is under 50%
+        for failed in [true, false, false, false] {
+            if failed { lb.report_backend_failure(bad) } else { lb.report_backend_success(bad) }
+        }
+        assert!((0..4).any(|_| lb.next_backend().unwrap().0 == bad));
+
+        // Fresh backend: 2 of 4 reaches 50%
+        let lb = LoadBalancer::new("outlier_test".to_string(), backends(&["10.0.0.1:80", "10.0.0.2:80"]), None, Strategy::RoundRobin)
+            .with_outlier_detection(&config);
+        for failed in [true, false, true, false] {
+            if failed { lb.report_backend_failure(bad) } else { lb.report_backend_success(bad) }
+        }
+        assert!((0..4).all(|_| lb.next_backend().unwrap().0 == "10.0.0.2:80"));
+
+        // The other backend can't be ejected too: max_ejection_percent 50
+        for _ in 0..4 {
+            lb.report_backend_failure("10.0.0.2:80");
+        }
+        assert!(lb.has_healthy_backend());
+
+        // Auto-recovers after base_ejection_ms, then a repeat offense is ejected for twice as long
+        tokio::time::advance(Duration::from_millis(41)).await;
+        assert!((0..4).any(|_| lb.next_backend().unwrap().0 == bad));
+        for _ in 0..4 {
+            lb.report_backend_failure(bad);
+        }
+        tokio::time::advance(Duration::from_millis(79)).await;
+        assert!(lb.backends.load()[0].outlier_ejected());
+        tokio::time::advance(Duration::from_millis(2)).await;
+        assert!(!lb.backends.load()[0].outlier_ejected());
+    }
+
+    #[test]
+    fn test_adaptive_weights_steer_away_from_failures() {
+        let config: crate::config::AdaptiveWeightsConfig = serde_yaml::from_str(
+            "{ interval_ms: 1000, step_percent: 50, min_weight_percent: 20, max_weight_percent: 150 }").unwrap();
+        let lb = LoadBalancer::new("adaptive_test".to_string(), weighted(&[("10.0.0.1:80", 1), ("10.0.0.2:80", 1)]), None, Strategy::RoundRobin)
+            .with_adaptive_weights(&config);
+        let bad = "10.0.0.1:80";
+        let percent = |i: usize| lb.backends.load()[i].adaptive_percent.load(Ordering::Relaxed);
+
+        // Every connect to `bad` fails: halfway to the floor each step, never below it
+        for step in 0..10 {
+            for _ in 0..4 {
+                lb.report_backend_failure(bad);
+                lb.report_backend_success("10.0.0.2:80");
+            }
+            lb.adapt_weights();
+            if step == 0 {
+                assert_eq!(percent(0), 60);
+            }
+        }
+        assert_eq!(percent(0), 20);
+        assert_eq!(percent(1), 100);
+        let picks = (0..60).filter(|_| lb.next_backend().unwrap().0 == bad).count();
+        assert_eq!(picks, 10); // 20:100
+
+        // Quiet intervals bring it back to its configured weight
+        for _ in 0..10 {
+            lb.adapt_weights();
+        }
+        assert_eq!(percent(0), 100);
+    }
+}
@@ -0,0 +1,142 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use log::{info, warn};
+use tokio::task::JoinHandle;
+use crate::config::{BackendConfig, HealthCheckConfig, LBRule};
+use crate::core::balancer::LoadBalancer;
+use crate::core::health::HealthCheckRegistry;
+
+/// How often `dns: true` backends are re-resolved when a rule sets no `dns_refresh_ms`
+pub const DEFAULT_DNS_REFRESH_MS: u64 = 30_000;
+
+/// Service discovery via DNS: re-resolves a rule's `dns: true` backends on an
+/// interval and swaps the resolved endpoints into its load balancer (and
+/// health checks). Dropping it stops the refresh task.
+pub struct DnsDiscovery {
+    handle: JoinHandle<()>,
+}
+
+impl Drop for DnsDiscovery {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+impl DnsDiscovery {
+    /// None if the rule has no `dns: true` backends
+    pub fn spawn(rule: &LBRule, lb: Arc<LoadBalancer>, health_checks: Arc<HealthCheckRegistry>) -> Option<Self> {
+        if !rule.backends.iter().any(BackendConfig::dns) {
+            return None;
+        }
+        let rule_name = rule.name.clone();
+        let backends = rule.backends.clone();
+        let backup_addrs: Vec<String> = rule.backup_backends.iter().map(|b| b.addr().to_string()).collect();
+        let checks = rule.health_check.as_ref().map(|hc| hc.checks()).unwrap_or_default();
+        let interval = Duration::from_millis(rule.dns_refresh_ms.unwrap_or(DEFAULT_DNS_REFRESH_MS));
+        let handle = tokio::spawn(run(rule_name, backends, backup_addrs, checks, lb, health_checks, interval));
+        Some(Self { handle })
+    }
+
+    /// Stops the refresh task and waits for it, so it can't touch the rule afterwards
+    pub async fn stop(mut self) {
+        self.handle.abort();
+        let _ = (&mut self.handle).await;
+    }
+}
+
+async fn run(
+    rule_name: String,
+    backends: Vec<BackendConfig>,
+    backup_addrs: Vec<String>,
+    checks: Vec<HealthCheckConfig>,
+    lb: Arc<LoadBalancer>,
+    health_checks: Arc<HealthCheckRegistry>,
+    interval: Duration,
+) {
+    let mut last_good: HashMap<String, Vec<SocketAddr>> = HashMap::new();
+    let mut current: Option<Vec<String>> = None;
+    let mut ticker = tokio::time::interval(interval);
+    loop {
+        ticker.tick().await;
+        let resolved = resolve(&rule_name, &backends, &mut last_good).await;
+        let addrs: Vec<String> = resolved.iter().map(|b| b.addr().to_string()).collect();
+        if current.as_ref() == Some(&addrs) {
+            continue;
+        }
+        info!("[{}] DNS backends resolved to {:?}", rule_name, addrs);
+        lb.update_backends(resolved).await;
+        // Backup backends are static, but their checkers must survive the sync
+        let checked: Vec<String> = addrs.iter().chain(&backup_addrs).cloned().collect();
+        health_checks.sync(&rule_name, &lb, &checked, &checks).await;
+        current = Some(addrs);
+    }
+}
+
+// Expands each `dns: true` entry into one backend per resolved address; static
+// entries pass through. A failed or empty lookup keeps that entry's last good
+// addresses, or the hostname itself (resolved at connect time) if there are none yet.
+async fn resolve(rule_name: &str, backends: &[BackendConfig], last_good: &mut HashMap<String, Vec<SocketAddr>>) -> Vec<BackendConfig> {
+    let mut out = Vec::with_capacity(backends.len());
+    for backend in backends {
+        if !backend.dns() {
+            out.push(backend.clone());
+            continue;
+        }
+        let name = backend.addr();
+        match tokio::net::lookup_host(name).await {
+            Ok(addrs) => {
+                let mut addrs: Vec<SocketAddr> = addrs.collect();
+                addrs.sort();
+                addrs.dedup();
+                if addrs.is_empty() {
+                    warn!("[{}] {} resolved to no addresses, keeping the last good set", rule_name, name);
+                } else {
+                    last_good.insert(name.to_string(), addrs);
+                }
+            }
+            Err(e) => warn!("[{}] Failed to resolve {}: {}, keeping the last good set", rule_name, name, e),
+        }
+        match last_good.get(name) {
+            Some(addrs) => out.extend(addrs.iter().map(|addr| BackendConfig::Detailed {
+                addr: addr.to_string(),
+                drain: backend.drain(),
+                weight: backend.weight(),
+                dns: false,
+                proxy_protocol: backend.proxy_protocol(),
+                nodelay: backend.nodelay(),
+            })),
+            None => out.push(backend.clone()),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dns_backend(addr: &str) -> BackendConfig {
+        BackendConfig::Detailed { addr: addr.to_string(), drain: false, weight: 2, dns: true, proxy_protocol: None, nodelay: None }
+    }
+
+    #[tokio::test]
+    async fn test_resolve_expands_and_keeps_last_good() {
+        let mut last_good = HashMap::new();
+        let backends = vec![BackendConfig::Simple("10.0.0.1:80".to_string()), dns_backend("localhost:8080")];
+        let resolved = resolve("web", &backends, &mut last_good).await;
+        assert_eq!(resolved[0].addr(), "10.0.0.1:80");
+        assert!(resolved.len() >= 2);
+        assert!(resolved[1..].iter().all(|b| !b.dns() && b.weight() == 2 && b.addr().ends_with(":8080")));
+
+        // The name stops resolving: the previous addresses stay
+        last_good.insert("unresolvable.invalid:8080".to_string(), vec!["10.9.9.9:8080".parse().unwrap()]);
+        let resolved = resolve("web", &[dns_backend("unresolvable.invalid:8080")], &mut last_good).await;
+        assert_eq!(resolved.iter().map(|b| b.addr()).collect::<Vec<_>>(), ["10.9.9.9:8080"]);
+
+        // Never resolved: the hostname entry itself is kept
+        let resolved = resolve("web", &[dns_backend("other.invalid:8080")], &mut last_good).await;
+        assert_eq!(resolved[0].addr(), "other.invalid:8080");
+    }
+}
@@ -1,99 +1,772 @@
-use std::sync::Arc;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use arc_swap::ArcSwapOption;
+use rand::Rng;
 use tokio::net::TcpStream;
+use tokio::sync::Semaphore;
+use tokio::task::JoinHandle;
 use tokio::time::{sleep, Duration};
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio_rustls::TlsConnector;
 use log::{debug, info};
-use crate::config::HealthCheckConfig;
+use crate::config::{HealthCheckAction, HealthCheckConfig};
 use crate::core::balancer::LoadBalancer;
+use crate::networking::proxy::{backend_connector, backend_server_name};
 
-pub fn start_health_check(
+// Latest verdict of every check for one backend. A backend is healthy only if all
+// of its `eject` checks pass, and ready only if all of its `drain` checks pass.
+struct CheckVerdicts {
+    results: Vec<(HealthCheckAction, AtomicBool)>,
+    streaks: Vec<Mutex<Streak>>, // Shared with on-demand rechecks
+}
+
+impl CheckVerdicts {
+    fn new(checks: &[HealthCheckConfig]) -> Self {
+        CheckVerdicts {
+            results: checks.iter().map(|c| (c.on_fail, AtomicBool::new(true))).collect(),
+            streaks: checks.iter().map(|c| Mutex::new(Streak::new(c.rise, c.fall))).collect(),
+        }
+    }
+
+    // Periodic probe result, debounced by the check's rise/fall thresholds
+    fn observe(&self, idx: usize, passed: bool) {
+        let passing = self.streaks[idx].lock().unwrap().observe(passed);
+        self.results[idx].1.store(passing, Ordering::Relaxed);
+    }
+
+    // On-demand probe result, taken as the new verdict straight away
+    fn force(&self, idx: usize, passed: bool) {
+        self.streaks[idx].lock().unwrap().reset(passed);
+        self.results[idx].1.store(passed, Ordering::Relaxed);
+    }
+
+    fn all_passing(&self, action: HealthCheckAction) -> bool {
+        self.results.iter()
+            .filter(|(a, _)| *a == action)
+            .all(|(_, ok)| ok.load(Ordering::Relaxed))
+    }
+
+    async fn apply(&self, lb: &LoadBalancer, backend_addr: &str, action: HealthCheckAction) {
+        match action {
+            HealthCheckAction::Eject => lb.set_backend_health(backend_addr, self.all_passing(HealthCheckAction::Eject)).await,
+            HealthCheckAction::Drain => lb.set_backend_ready(backend_addr, self.all_passing(HealthCheckAction::Drain)).await,
+        }
+    }
+}
+
+// Actions `old` checks could fail a backend with that `new` checks no longer can
+fn released_actions<'a>(old: &'a [HealthCheckConfig], new: &'a [HealthCheckConfig]) -> impl Iterator<Item = HealthCheckAction> + 'a {
+    [HealthCheckAction::Eject, HealthCheckAction::Drain].into_iter()
+        .filter(|action| old.iter().any(|c| c.on_fail == *action) && !new.iter().any(|c| c.on_fail == *action))
+}
+
+// Checker tasks of one backend, aborted when dropped
+struct RunningChecks {
+    checks: Vec<HealthCheckConfig>,
+    verdicts: Arc<CheckVerdicts>,
+    handles: Vec<JoinHandle<()>>,
+}
+
+impl Drop for RunningChecks {
+    fn drop(&mut self) {
+        for handle in &self.handles {
+            handle.abort();
+        }
+    }
+}
+
+/// Tracks the checker tasks spawned per (rule, backend) so a config reload only
+/// starts checkers for new backends (or changed checks) and stops removed ones.
+#[derive(Default)]
+pub struct HealthCheckRegistry {
+    running: Mutex<HashMap<(String, String), RunningChecks>>,
+    probe_slots: Arc<ArcSwapOption<Semaphore>>, // `health_checks.max_concurrent_probes`, shared by all checkers
+    max_probes: Mutex<Option<usize>>,
+}
+
+impl HealthCheckRegistry {
+    /// Caps the periodic probes in flight across all rules (None = unlimited).
+    /// Probes already waiting on the previous cap finish under it.
+    pub fn set_max_concurrent_probes(&self, max: Option<usize>) {
+        let mut current = self.max_probes.lock().unwrap();
+        if *current == max {
+            return;
+        }
+        *current = max;
+        self.probe_slots.store(max.map(|n| Arc::new(Semaphore::new(n))));
+    }
+
+    /// Stopping or replacing a backend's checkers hands back the flags they owned:
+    /// a backend whose `eject` (`drain`) checks are gone is marked healthy (ready) again.
+    pub async fn sync(&self, rule_name: &str, lb: &Arc<LoadBalancer>, backends: &[String], checks: &[HealthCheckConfig]) {
+        let mut released: Vec<(String, HealthCheckAction)> = Vec::new();
+        {
+            let mut running = self.running.lock().unwrap();
+
+            running.retain(|(rule, backend), entry| {
+                let keep = rule != rule_name || (!checks.is_empty() && backends.contains(backend));
+                if !keep {
+                    info!("[{}] Stopping health checks for {}", rule, backend);
+                    released.extend(released_actions(&entry.checks, &[]).map(|action| (backend.clone(), action)));
+                }
+                keep
+            });
+
+            for backend in backends.iter().filter(|_| !checks.is_empty()) {
+                let key = (rule_name.to_string(), backend.clone());
+                if running.get(&key).is_some_and(|r| r.checks == checks) {
+                    continue;
+                }
+                let verdicts = Arc::new(CheckVerdicts::new(checks));
+                let handles = start_health_checks(lb.clone(), backend.clone(), checks.to_vec(), verdicts.clone(), self.probe_slots.clone());
+                // Replacing an entry drops (aborts) the checkers running with the old settings
+                if let Some(old) = running.insert(key, RunningChecks { checks: checks.to_vec(), verdicts, handles }) {
+                    released.extend(released_actions(&old.checks, checks).map(|action| (backend.clone(), action)));
+                }
+            }
+        }
+
+        for (backend, action) in released {
+            match action {
+                HealthCheckAction::Eject => lb.set_backend_health(&backend, true).await,
+                HealthCheckAction::Drain => lb.set_backend_ready(&backend, true).await,
+            }
+        }
+    }
+
+    /// Runs every check of one backend once and applies the results immediately,
+    /// bypassing rise/fall. Returns (protocol, passed) per check, or None if the
+    /// backend has no checks running.
+    pub async fn check_now(&self, rule_name: &str, backend_addr: &str, lb: &LoadBalancer) -> Option<Vec<(String, bool)>> {
+        let (checks, verdicts) = {
+            let running = self.running.lock().unwrap();
+            let entry = running.get(&(rule_name.to_string(), backend_addr.to_string()))?;
+            (entry.checks.clone(), entry.verdicts.clone())
+        };
+
+        let mut results = Vec::with_capacity(checks.len());
+        for (idx, config) in checks.iter().enumerate() {
+            let passed = probe(rule_name, config, &probe_addr(backend_addr, config.port)).await;
+            verdicts.force(idx, passed);
+            results.push((config.protocol.clone(), passed));
+        }
+        for action in [HealthCheckAction::Eject, HealthCheckAction::Drain] {
+            if checks.iter().any(|c| c.on_fail == action) {
+                verdicts.apply(lb, backend_addr, action).await;
+            }
+        }
+        info!("[{}] On-demand health check of {}: {:?}", rule_name, backend_addr, results);
+        Some(results)
+    }
+}
+
+// Debounces raw probe results: the verdict only flips after `fall` consecutive
+// failures (or `rise` consecutive passes), so a single dropped probe doesn't flap.
+struct Streak {
+    rise: u32,
+    fall: u32,
+    passing: bool,
+    count: u32, // Consecutive results disagreeing with `passing`
+}
+
+impl Streak {
+    fn new(rise: u32, fall: u32) -> Self {
+        Streak { rise: rise.max(1), fall: fall.max(1), passing: true, count: 0 }
+    }
+
+    fn reset(&mut self, passing: bool) {
+        self.passing = passing;
+        self.count = 0;
+    }
+
+    fn observe(&mut self, passed: bool) -> bool {
+        if passed == self.passing {
+            self.count = 0;
+            return self.passing;
+        }
+        self.count += 1;
+        let threshold = if self.passing { self.fall } else { self.rise };
+        if self.count >= threshold {
+            self.passing = passed;
+            self.count = 0;
+        }
+        self.passing
+    }
+}
+
+fn start_health_checks(
     lb: Arc<LoadBalancer>,
     backend_addr: String,
-    config: HealthCheckConfig,
-) {
-    let config = config.clone();
-    tokio::spawn(async move {
-        // Initial delay to let things start?
-        sleep(Duration::from_millis(100)).await;
-        
-        info!("Starting health check for {} ({})", backend_addr, config.protocol);
+    checks: Vec<HealthCheckConfig>,
+    verdicts: Arc<CheckVerdicts>,
+    probe_slots: Arc<ArcSwapOption<Semaphore>>,
+) -> Vec<JoinHandle<()>> {
+    checks.into_iter().enumerate().map(|(idx, config)| {
+        let lb = lb.clone();
+        let backend_addr = backend_addr.clone();
+        let verdicts = verdicts.clone();
+        let probe_slots = probe_slots.clone();
 
-        loop {
-            let timeout = Duration::from_millis(config.timeout_ms);
-            let check_res = match config.protocol.as_str() {
-                "http" => {
-                    let path = config.path.as_deref().unwrap_or("/");
-                    check_http(&backend_addr, path, timeout).await
-                },
-                _ => check_tcp(&backend_addr, timeout).await,
-            };
+        tokio::spawn(async move {
+            // Random start within the jitter window, so checkers started together
+            // (at startup or by a reload) don't probe their backends in lockstep
+            let jitter_ms = config.jitter_ms.unwrap_or(config.interval_ms);
+            sleep(Duration::from_millis(rand::rng().random_range(0..=jitter_ms))).await;
 
-            lb.set_backend_health(&backend_addr, check_res).await;
+            let probe_addr = probe_addr(&backend_addr, config.port);
+            info!("[{}] Starting health check for {} ({} on {}, on_fail={:?})", lb.rule_name, backend_addr, config.protocol, probe_addr, config.on_fail);
 
-            sleep(Duration::from_millis(config.interval_ms)).await;
-        }
-    });
+            loop {
+                let slots = probe_slots.load_full();
+                let permit = match &slots {
+                    Some(slots) => slots.acquire().await.ok(),
+                    None => None,
+                };
+                verdicts.observe(idx, probe(&lb.rule_name, &config, &probe_addr).await);
+                drop(permit);
+                verdicts.apply(&lb, &backend_addr, config.on_fail).await;
+
+                sleep(Duration::from_millis(config.interval_ms)).await;
+            }
+        })
+    }).collect()
+}
+
+async fn probe(rule: &str, config: &HealthCheckConfig, probe_addr: &str) -> bool {
+    let timeout = Duration::from_millis(config.timeout_ms);
+    match config.protocol.as_str() {
+        "http" => {
+            let expected = config.expected_statuses.as_deref().unwrap_or(&[200]);
+            check_http(rule, probe_addr, &http_check_request(config, probe_addr), expected, config.expect.as_deref(), https(config, probe_addr), timeout).await
+        },
+        "grpc" => check_grpc(rule, probe_addr, config.grpc_service.as_deref().unwrap_or(""), timeout).await,
+        "udp" => check_udp(rule, probe_addr, config.send.as_deref(), config.expect.as_deref(), timeout).await,
+        _ => check_tcp(rule, probe_addr, config.send.as_deref(), config.expect.as_deref(), timeout).await,
+    }
+}
+
+// Backend address with the port swapped for the check's own port, if any
+fn probe_addr(backend_addr: &str, port: Option<u16>) -> String {
+    match (port, backend_addr.rsplit_once(':')) {
+        (Some(port), Some((host, _))) => format!("{}:{}", host, port),
+        _ => backend_addr.to_string(),
+    }
 }
 
-async fn check_tcp(addr: &str, timeout: Duration) -> bool {
+// Cap on response bytes buffered by a check
+const MAX_CHECK_RESPONSE: usize = 64 * 1024;
+
+async fn check_tcp(rule: &str, addr: &str, send: Option<&str>, expect: Option<&str>, timeout: Duration) -> bool {
     let start = std::time::Instant::now();
-    let connect = TcpStream::connect(addr);
-    match tokio::time::timeout(timeout, connect).await {
-        Ok(Ok(_)) => {
-            debug!("TCP check passed for {} in {:?}", addr, start.elapsed());
-            true
+    let check_fut = async {
+        let mut stream = TcpStream::connect(addr).await?;
+        if let Some(send) = send {
+            stream.write_all(send.as_bytes()).await?;
+        }
+        let Some(expect) = expect else {
+            return Ok(true);
+        };
+
+        // The backend may keep the connection open, so stop as soon as the answer matches
+        let mut response = Vec::new();
+        let mut chunk = [0u8; 4096];
+        while response.len() < MAX_CHECK_RESPONSE {
+            let n = stream.read(&mut chunk).await?;
+            if n == 0 {
+                break;
+            }
+            response.extend_from_slice(&chunk[..n]);
+            if contains(&response, expect.as_bytes()) {
+                return Ok(true);
+            }
+        }
+        debug!("[{}] TCP check failed for {}: response does not contain {:?}", rule, addr, expect);
+        Ok::<bool, std::io::Error>(false)
+    };
+
+    match tokio::time::timeout(timeout, check_fut).await {
+        Ok(Ok(passed)) => {
+            if passed {
+                debug!("[{}] TCP check passed for {} in {:?}", rule, addr, start.elapsed());
+            }
+            passed
         },
         Ok(Err(e)) => {
-            debug!("TCP check failed for {}: {} (took {:?})", addr, e, start.elapsed());
+            debug!("[{}] TCP check failed for {}: {} (took {:?})", rule, addr, e, start.elapsed());
             false
         },
         Err(_) => {
-            debug!("TCP check timed out for {} (after {:?})", addr, start.elapsed());
+            debug!("[{}] TCP check timed out for {} (after {:?})", rule, addr, start.elapsed());
             false
         }
     }
 }
 
-async fn check_http(addr: &str, path: &str, timeout: Duration) -> bool {
+// UDP has no handshake: healthy means a reply (containing `expect`, if set) arrived
+// within the timeout. An ICMP port unreachable fails fast as a refused recv.
+async fn check_udp(rule: &str, addr: &str, send: Option<&str>, expect: Option<&str>, timeout: Duration) -> bool {
+    let start = std::time::Instant::now();
     let check_fut = async {
-        match TcpStream::connect(addr).await {
-            Ok(mut stream) => {
-                let request = format!("GET {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\r\n", path, addr);
-                if let Err(e) = stream.write_all(request.as_bytes()).await {
-                    debug!("HTTP write failed for {}: {}", addr, e);
-                    return false;
+        let socket = crate::networking::udp::connect_backend(addr).await?;
+        socket.send(send.unwrap_or("").as_bytes()).await?;
+
+        // Stray datagrams (e.g. late replies to an earlier probe) don't end the check
+        let mut buf = vec![0u8; MAX_CHECK_RESPONSE];
+        loop {
+            let n = socket.recv(&mut buf).await?;
+            match expect {
+                Some(expect) if !contains(&buf[..n], expect.as_bytes()) => {
+                    debug!("[{}] UDP check for {}: reply does not contain {:?}", rule, addr, expect);
                 }
+                _ => return Ok::<bool, std::io::Error>(true),
+            }
+        }
+    };
 
-                let mut buf = [0u8; 1024];
-                match stream.read(&mut buf).await {
-                    Ok(n) if n > 0 => {
-                        let response = String::from_utf8_lossy(&buf[..n]);
-                        if response.contains("200 OK") {
-                            true
-                        } else {
-                            debug!("HTTP check failed for {}: Status not 200", addr);
-                            false
-                        }
-                    }
-                    Ok(_) => false,
-                    Err(e) => {
-                        debug!("HTTP read failed for {}: {}", addr, e);
-                        false
-                    }
+    match tokio::time::timeout(timeout, check_fut).await {
+        Ok(Ok(passed)) => {
+            debug!("[{}] UDP check passed for {} in {:?}", rule, addr, start.elapsed());
+            passed
+        },
+        Ok(Err(e)) => {
+            debug!("[{}] UDP check failed for {}: {} (took {:?})", rule, addr, e, start.elapsed());
+            false
+        },
+        Err(_) => {
+            debug!("[{}] UDP check timed out for {} (after {:?})", rule, addr, start.elapsed());
+            false
+        }
+    }
+}
+
+// Status code from an HTTP/1.x status line ("HTTP/1.1 204 No Content")
+fn parse_status(response: &[u8]) -> Option<u16> {
+    let line = response.split(|&b| b == b'\n').next()?;
+    let line = std::str::from_utf8(line).ok()?;
+    let mut parts = line.split_whitespace();
+    if !parts.next()?.starts_with("HTTP/") {
+        return None;
+    }
+    parts.next()?.parse().ok()
+}
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    needle.is_empty() || haystack.windows(needle.len()).any(|w| w == needle)
+}
+
+// Verdict for a complete (or capped) HTTP response
+fn http_verdict(rule: &str, addr: &str, response: &[u8], expected_statuses: &[u16], expect: Option<&str>) -> bool {
+    match parse_status(response) {
+        Some(status) if expected_statuses.contains(&status) => {}
+        Some(status) => {
+            debug!("[{}] HTTP check failed for {}: status {} not in {:?}", rule, addr, status, expected_statuses);
+            return false;
+        }
+        None => {
+            debug!("[{}] HTTP check failed for {}: malformed status line", rule, addr);
+            return false;
+        }
+    }
+    let Some(expect) = expect else {
+        return true;
+    };
+    let body = response.windows(4).position(|w| w == b"\r\n\r\n").map(|i| &response[i + 4..]).unwrap_or(&[]);
+    if contains(body, expect.as_bytes()) {
+        true
+    } else {
+        debug!("[{}] HTTP check failed for {}: body does not contain {:?}", rule, addr, expect);
+        false
+    }
+}
+
+// Request line and headers of an HTTP check. Host defaults to the probed
+// address; set `host` when the backend routes on a name.
+fn http_check_request(config: &HealthCheckConfig, addr: &str) -> String {
+    let method = config.method.as_deref().unwrap_or("GET");
+    let path = config.path.as_deref().unwrap_or("/");
+    let host = config.host.as_deref().unwrap_or(addr);
+    let mut request = format!("{} {} HTTP/1.1\r\nHost: {}\r\n", method, path, host);
+    for (name, value) in config.headers.iter().flatten() {
+        request.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    request.push_str("Connection: close\r\n\r\n");
+    request
+}
+
+// TLS settings of an HTTPS check. SNI is the `host` override (without its
+// port) if set, else the probed address, same as for proxied backend TLS.
+fn https(config: &HealthCheckConfig, addr: &str) -> Option<(TlsConnector, String)> {
+    config.tls.then(|| (backend_connector(config.ignore_verify), config.host.clone().unwrap_or_else(|| addr.to_string())))
+}
+
+async fn check_http(rule: &str, addr: &str, request: &str, expected_statuses: &[u16], expect: Option<&str>, tls: Option<(TlsConnector, String)>, timeout: Duration) -> bool {
+    let check_fut = async {
+        let stream = TcpStream::connect(addr).await?;
+        let response = match tls {
+            Some((connector, host)) => {
+                let server_name = backend_server_name(None, &host).map_err(std::io::Error::other)?;
+                http_exchange(connector.connect(server_name, stream).await?, request, expect).await?
+            }
+            None => http_exchange(stream, request, expect).await?,
+        };
+        Ok::<bool, std::io::Error>(http_verdict(rule, addr, &response, expected_statuses, expect))
+    };
+
+    match tokio::time::timeout(timeout, check_fut).await {
+        Ok(Ok(passed)) => passed,
+        Ok(Err(e)) => {
+            debug!("[{}] HTTP check failed for {}: {}", rule, addr, e);
+            false
+        }
+        Err(_) => {
+            debug!("[{}] HTTP check timed out for {}", rule, addr);
+            false
+        }
+    }
+}
+
+async fn http_exchange<S: AsyncRead + AsyncWrite + Unpin>(mut stream: S, request: &str, expect: Option<&str>) -> std::io::Result<Vec<u8>> {
+    stream.write_all(request.as_bytes()).await?;
+
+    let mut response = Vec::new();
+    let mut chunk = [0u8; 4096];
+    loop {
+        let n = match stream.read(&mut chunk).await {
+            Ok(n) => n,
+            // TLS peers often close without close_notify once the response is out
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof && !response.is_empty() => 0,
+            Err(e) => return Err(e),
+        };
+        if n == 0 {
+            break;
+        }
+        response.extend_from_slice(&chunk[..n]);
+        // Without a body match the status line is enough
+        if expect.is_none() && response.contains(&b'\n') {
+            break;
+        }
+        if response.len() >= MAX_CHECK_RESPONSE {
+            break;
+        }
+    }
+    Ok(response)
+}
+
+// grpc.health.v1.HealthCheckResponse.ServingStatus.SERVING
+const GRPC_SERVING: u64 = 1;
+
+// Length-prefixed gRPC message wrapping HealthCheckRequest { string service = 1; }
+fn grpc_check_request(service: &str) -> Vec<u8> {
+    let mut msg = Vec::with_capacity(service.len() + 8);
+    if !service.is_empty() {
+        msg.push(0x0A); // field 1, length-delimited
+        encode_varint(service.len() as u64, &mut msg);
+        msg.extend_from_slice(service.as_bytes());
+    }
+    let mut frame = Vec::with_capacity(5 + msg.len());
+    frame.push(0); // Not compressed
+    frame.extend_from_slice(&(msg.len() as u32).to_be_bytes());
+    frame.extend_from_slice(&msg);
+    frame
+}
+
+fn encode_varint(mut value: u64, out: &mut Vec<u8>) {
+    while value >= 0x80 {
+        out.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn decode_varint(buf: &[u8]) -> Option<(u64, usize)> {
+    let mut value = 0u64;
+    for (i, byte) in buf.iter().enumerate().take(10) {
+        value |= ((byte & 0x7F) as u64) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Some((value, i + 1));
+        }
+    }
+    None
+}
+
+// ServingStatus from a length-prefixed HealthCheckResponse { ServingStatus status = 1; }
+fn parse_grpc_status(frame: &[u8]) -> Option<u64> {
+    if frame.len() < 5 || frame[0] != 0 {
+        return None; // Truncated or compressed
+    }
+    let len = u32::from_be_bytes([frame[1], frame[2], frame[3], frame[4]]) as usize;
+    let mut msg = frame.get(5..5 + len)?;
+    let mut status = 0; // proto3 default: UNKNOWN
+    while !msg.is_empty() {
+        let (tag, n) = decode_varint(msg)?;
+        msg = &msg[n..];
+        match tag & 0x7 {
+            0 => {
+                let (value, n) = decode_varint(msg)?;
+                msg = &msg[n..];
+                if tag >> 3 == 1 {
+                    status = value;
                 }
             }
-            Err(e) => {
-                debug!("HTTP Connect failed for {}: {}", addr, e);
-                false
+            2 => {
+                let (len, n) = decode_varint(msg)?;
+                msg = msg.get(n + len as usize..)?;
             }
+            _ => return None,
+        }
+    }
+    Some(status)
+}
+
+// grpc.health.v1.Health/Check over cleartext HTTP/2 (prior knowledge)
+async fn check_grpc(rule: &str, addr: &str, service: &str, timeout: Duration) -> bool {
+    use http_body_util::{BodyExt, Full};
+    use hyper_util::rt::{TokioExecutor, TokioIo};
+
+    let check_fut = async {
+        let stream = TcpStream::connect(addr).await?;
+        let (mut sender, conn) = hyper::client::conn::http2::handshake(TokioExecutor::new(), TokioIo::new(stream)).await?;
+        tokio::spawn(conn); // Ends once `sender` is dropped
+
+        let request = hyper::Request::post(format!("http://{}/grpc.health.v1.Health/Check", addr))
+            .header("content-type", "application/grpc")
+            .header("te", "trailers")
+            .body(Full::new(bytes::Bytes::from(grpc_check_request(service))))?;
+        let response = sender.send_request(request).await?;
+        if response.status() != hyper::StatusCode::OK {
+            anyhow::bail!("HTTP status {}", response.status());
         }
+
+        // grpc-status arrives in the trailers, or in the headers for trailers-only errors
+        let headers = response.headers().clone();
+        let collected = response.into_body().collect().await?;
+        let grpc_status = collected.trailers()
+            .and_then(|t| t.get("grpc-status"))
+            .or_else(|| headers.get("grpc-status"))
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or("")
+            .to_string();
+        if grpc_status != "0" {
+            anyhow::bail!("grpc-status {:?}", grpc_status);
+        }
+        parse_grpc_status(&collected.to_bytes()).ok_or_else(|| anyhow::anyhow!("malformed HealthCheckResponse"))
     };
 
     match tokio::time::timeout(timeout, check_fut).await {
-        Ok(res) => res,
+        Ok(Ok(GRPC_SERVING)) => true,
+        Ok(Ok(status)) => {
+            debug!("[{}] gRPC check failed for {} (service {:?}): status {}", rule, addr, service, status);
+            false
+        }
+        Ok(Err(e)) => {
+            debug!("[{}] gRPC check failed for {} (service {:?}): {}", rule, addr, service, e);
+            false
+        }
         Err(_) => {
-            debug!("HTTP check timed out for {}", addr);
+            debug!("[{}] gRPC check timed out for {}", rule, addr);
             false
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{BackendConfig, Strategy};
+
+    fn tcp_check(interval_ms: u64) -> HealthCheckConfig {
+        serde_yaml::from_str(&format!("{{ enabled: true, interval_ms: {}, timeout_ms: 100, protocol: tcp }}", interval_ms)).unwrap()
+    }
+
+    #[test]
+    fn test_http_check_request() {
+        let mut check: HealthCheckConfig = serde_yaml::from_str("{ enabled: true, interval_ms: 1000, timeout_ms: 100, protocol: http }").unwrap();
+        assert_eq!(http_check_request(&check, "10.0.0.1:80"), "GET / HTTP/1.1\r\nHost: 10.0.0.1:80\r\nConnection: close\r\n\r\n");
+
+        check = serde_yaml::from_str(r#"
+enabled: true
+interval_ms: 1000
+timeout_ms: 100
+protocol: http
+method: HEAD
+path: /healthz
+host: api.example.com
+headers: { User-Agent: l4lb-health, Authorization: Bearer t0k }
+"#).unwrap();
+        assert_eq!(http_check_request(&check, "10.0.0.1:80"),
+            "HEAD /healthz HTTP/1.1\r\nHost: api.example.com\r\nAuthorization: Bearer t0k\r\nUser-Agent: l4lb-health\r\nConnection: close\r\n\r\n");
+    }
+
+    #[test]
+    fn test_http_verdict_body_match() {
+        let ok = b"HTTP/1.1 200 OK\r\nContent-Type: application/json\r\n\r\n{\"status\":\"UP\"}";
+        assert!(http_verdict("hc", "b", ok, &[200], None));
+        assert!(http_verdict("hc", "b", ok, &[200], Some("\"UP\"")));
+        assert!(!http_verdict("hc", "b", ok, &[200], Some("DOWN")));
+        // Header text doesn't count as body
+        assert!(!http_verdict("hc", "b", ok, &[200], Some("application/json")));
+        assert!(!http_verdict("hc", "b", b"HTTP/1.1 503 Unavailable\r\n\r\nUP", &[200], Some("UP")));
+    }
+
+    #[tokio::test]
+    async fn test_tcp_send_expect() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let mut buf = [0u8; 64];
+                    if let Ok(n) = stream.read(&mut buf).await
+                        && &buf[..n] == b"PING\r\n" {
+                            let _ = stream.write_all(b"+PONG\r\n").await;
+                    }
+                    // Keep the connection open, like a real server would
+                    tokio::time::sleep(Duration::from_secs(5)).await;
+                });
+            }
+        });
+
+        let timeout = Duration::from_millis(500);
+        assert!(check_tcp("hc", &addr, None, None, timeout).await);
+        assert!(check_tcp("hc", &addr, Some("PING\r\n"), Some("PONG"), timeout).await);
+        assert!(!check_tcp("hc", &addr, Some("PING\r\n"), Some("LOADING"), timeout).await);
+        assert!(!check_tcp("hc", &addr, Some("HELLO\r\n"), Some("PONG"), timeout).await);
+    }
+
+    #[tokio::test]
+    async fn test_udp_send_expect() {
+        let server = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = server.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            let mut buf = [0u8; 64];
+            while let Ok((n, peer)) = server.recv_from(&mut buf).await {
+                if &buf[..n] == b"PING" {
+                    let _ = server.send_to(b"PONG", peer).await;
+                }
+            }
+        });
+
+        let timeout = Duration::from_millis(300);
+        assert!(check_udp("hc", &addr, Some("PING"), None, timeout).await);
+        assert!(check_udp("hc", &addr, Some("PING"), Some("PONG"), timeout).await);
+        assert!(!check_udp("hc", &addr, Some("PING"), Some("LOADING"), timeout).await);
+        // No reply at all
+        assert!(!check_udp("hc", &addr, Some("HELLO"), None, timeout).await);
+    }
+
+    #[test]
+    fn test_streak_thresholds() {
+        // Defaults (1/1) follow every probe
+        let mut s = Streak::new(1, 1);
+        assert!(!s.observe(false));
+        assert!(s.observe(true));
+
+        let mut s = Streak::new(2, 3);
+        assert!(s.observe(false) && s.observe(false));
+        assert!(s.observe(true)); // Streak broken
+        assert!(s.observe(false) && s.observe(false));
+        assert!(!s.observe(false)); // Third consecutive failure
+        assert!(!s.observe(true));
+        assert!(s.observe(true)); // Second consecutive pass
+    }
+
+    #[test]
+    fn test_grpc_health_messages() {
+        assert_eq!(grpc_check_request(""), vec![0, 0, 0, 0, 0]);
+        assert_eq!(grpc_check_request("api"), vec![0, 0, 0, 0, 5, 0x0A, 3, b'a', b'p', b'i']);
+
+        assert_eq!(parse_grpc_status(&[0, 0, 0, 0, 2, 0x08, 1]), Some(GRPC_SERVING));
+        assert_eq!(parse_grpc_status(&[0, 0, 0, 0, 2, 0x08, 2]), Some(2)); // NOT_SERVING
+        assert_eq!(parse_grpc_status(&[0, 0, 0, 0, 0]), Some(0)); // Default UNKNOWN
+        assert_eq!(parse_grpc_status(&[0, 0, 0, 0, 2, 0x08]), None);
+        assert_eq!(parse_grpc_status(&[1, 0, 0, 0, 2, 0x08, 1]), None);
+    }
+
+    #[test]
+    fn test_parse_status() {
+        assert_eq!(parse_status(b"HTTP/1.1 200 OK\r\n\r\n"), Some(200));
+        assert_eq!(parse_status(b"HTTP/1.0 204 No Content\r\n"), Some(204));
+        assert_eq!(parse_status(b"HTTP/1.1 301\r\nLocation: /\r\n"), Some(301));
+        assert_eq!(parse_status(b"SSH-2.0-OpenSSH\r\n"), None);
+        assert_eq!(parse_status(b"HTTP/1.1 abc OK\r\n"), None);
+    }
+
+    fn task_ids(registry: &HealthCheckRegistry) -> Vec<tokio::task::Id> {
+        registry.running.lock().unwrap().values().flat_map(|r| r.handles.iter().map(|h| h.id())).collect()
+    }
+
+    #[tokio::test]
+    async fn test_reload_does_not_duplicate_checkers() {
+        let addrs = vec!["127.0.0.1:1".to_string(), "127.0.0.1:2".to_string()];
+        let lb = Arc::new(LoadBalancer::new("hc_test".to_string(), addrs.iter().cloned().map(BackendConfig::Simple).collect(), None, Strategy::RoundRobin));
+        let registry = HealthCheckRegistry::default();
+        let checks = vec![tcp_check(1000)];
+
+        registry.sync("hc_test", &lb, &addrs, &checks).await;
+        let first_ids = task_ids(&registry);
+        assert_eq!(first_ids.len(), 2);
+
+        // Same config twice: nothing restarted
+        registry.sync("hc_test", &lb, &addrs, &checks).await;
+        registry.sync("hc_test", &lb, &addrs, &checks).await;
+        let ids = task_ids(&registry);
+        assert_eq!(ids.len(), 2);
+        assert!(ids.iter().all(|id| first_ids.contains(id)));
+
+        // Backend removed: its checker is aborted
+        registry.sync("hc_test", &lb, &addrs[..1], &checks).await;
+        assert_eq!(registry.running.lock().unwrap().len(), 1);
+
+        // Changed settings restart the checker
+        registry.sync("hc_test", &lb, &addrs[..1], &[tcp_check(500)]).await;
+        let ids = task_ids(&registry);
+        assert_eq!(ids.len(), 1);
+        assert!(!first_ids.contains(&ids[0]));
+
+        // Health checks disabled: everything stops
+        registry.sync("hc_test", &lb, &addrs, &[]).await;
+        assert!(registry.running.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_check_now_skips_debounce() {
+        let addrs = vec!["127.0.0.1:1".to_string()]; // Nothing listens on port 1
+        let lb = Arc::new(LoadBalancer::new("hc_now".to_string(), addrs.iter().cloned().map(BackendConfig::Simple).collect(), None, Strategy::RoundRobin));
+        let registry = HealthCheckRegistry::default();
+        let mut check = tcp_check(60_000);
+        check.fall = 5;
+        registry.sync("hc_now", &lb, &addrs, &[check]).await;
+
+        // One failed on-demand probe ejects, even with fall: 5
+        let results = registry.check_now("hc_now", "127.0.0.1:1", &lb).await.unwrap();
+        assert_eq!(results, vec![("tcp".to_string(), false)]);
+        assert!(!lb.backends.load()[0].healthy.load(Ordering::Relaxed));
+
+        assert!(registry.check_now("hc_now", "127.0.0.1:2", &lb).await.is_none());
+        assert!(registry.check_now("other", "127.0.0.1:1", &lb).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_removed_checks_release_backend() {
+        let addrs = vec!["127.0.0.1:1".to_string(), "127.0.0.1:2".to_string()]; // Nothing listens on port 1
+        let lb = Arc::new(LoadBalancer::new("hc_release".to_string(), addrs.iter().cloned().map(BackendConfig::Simple).collect(), None, Strategy::RoundRobin));
+        let registry = HealthCheckRegistry::default();
+        let eject = tcp_check(60_000);
+        registry.sync("hc_release", &lb, &addrs[..1], &[eject.clone()]).await;
+        registry.check_now("hc_release", "127.0.0.1:1", &lb).await.unwrap();
+        assert!((0..4).all(|_| lb.next_backend().unwrap().0 == "127.0.0.1:2"));
+
+        // Switched to drain: the eject verdict is dropped, the drain one applies
+        let mut drain = eject.clone();
+        drain.on_fail = HealthCheckAction::Drain;
+        registry.sync("hc_release", &lb, &addrs[..1], &[drain]).await;
+        assert!(lb.backends.load()[0].healthy.load(Ordering::Relaxed));
+        registry.check_now("hc_release", "127.0.0.1:1", &lb).await.unwrap();
+        assert!((0..4).all(|_| lb.next_backend().unwrap().0 == "127.0.0.1:2"));
+
+        // health_check removed from the rule: the backend is selectable again
+        registry.sync("hc_release", &lb, &addrs, &[]).await;
+        assert!((0..4).any(|_| lb.next_backend().unwrap().0 == "127.0.0.1:1"));
+    }
+}
@@ -1,2 +1,4 @@
 pub mod balancer;
 pub mod health;
+pub mod discovery;
+pub mod state;
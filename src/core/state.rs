@@ -0,0 +1,126 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+/// Operational backend state of one rule, as kept in the `state_file`
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SavedRuleState {
+    #[serde(default)]
+    pub drained: BTreeSet<String>, // Drained via the admin API
+    #[serde(default)]
+    pub ejected_until: BTreeMap<String, u64>, // Outlier ejections, Unix ms
+}
+
+impl SavedRuleState {
+    /// What is left of a saved ejection, if it hasn't run out yet
+    pub fn ejection_left(&self, addr: &str) -> Option<Duration> {
+        let until = UNIX_EPOCH + Duration::from_millis(*self.ejected_until.get(addr)?);
+        until.duration_since(SystemTime::now()).ok()
+    }
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct SavedState {
+    #[serde(default)]
+    rules: BTreeMap<String, SavedRuleState>,
+}
+
+/// Backend state that should outlive the process (admin drains, outlier
+/// ejections), read once at startup and rewritten whenever a rule's changes.
+/// Config-file settings are not stored: the file stays authoritative for them.
+pub struct StateStore {
+    path: PathBuf,
+    state: Mutex<SavedState>,
+}
+
+impl StateStore {
+    /// A missing file starts empty; an unreadable one is logged and ignored,
+    /// so a corrupt state file can't keep the LB from starting
+    pub fn open(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        let state = match std::fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+                warn!("Ignoring state file {}: {}", path.display(), e);
+                SavedState::default()
+            }),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => SavedState::default(),
+            Err(e) => {
+                warn!("Ignoring state file {}: {}", path.display(), e);
+                SavedState::default()
+            }
+        };
+        for (rule, saved) in &state.rules {
+            if !saved.drained.is_empty() {
+                info!("Rule '{}' restoring drained backends from {}: {:?}", rule, path.display(), saved.drained);
+            }
+        }
+        Self { path, state: Mutex::new(state) }
+    }
+
+    pub fn rule(&self, rule_name: &str) -> SavedRuleState {
+        self.state.lock().unwrap().rules.get(rule_name).cloned().unwrap_or_default()
+    }
+
+    /// Replaces a rule's saved state and rewrites the file if it changed
+    pub fn update(&self, rule_name: &str, saved: SavedRuleState) {
+        let mut state = self.state.lock().unwrap();
+        if state.rules.get(rule_name).cloned().unwrap_or_default() == saved {
+            return;
+        }
+        if saved == SavedRuleState::default() {
+            state.rules.remove(rule_name);
+        } else {
+            state.rules.insert(rule_name.to_string(), saved);
+        }
+        // Written aside and renamed, so a crash mid-write leaves the old file
+        let tmp = self.path.with_extension("tmp");
+        let res = serde_json::to_vec_pretty(&*state).map_err(std::io::Error::other)
+            .and_then(|body| std::fs::write(&tmp, body))
+            .and_then(|_| std::fs::rename(&tmp, &self.path));
+        if let Err(e) = res {
+            crate::throttled_error!("state_file", "Failed to write state file {}: {}", self.path.display(), e);
+        }
+    }
+}
+
+/// Unix ms of an ejection ending `left` from now
+pub fn ejection_deadline(left: Duration) -> u64 {
+    (SystemTime::now() + left).duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_state_survives_reopen() {
+        let path = std::env::temp_dir().join(format!("l4lb_state_test_{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let store = StateStore::open(&path);
+        assert_eq!(store.rule("web"), SavedRuleState::default());
+        store.update("web", SavedRuleState {
+            drained: BTreeSet::from(["10.0.0.1:80".to_string()]),
+            ejected_until: BTreeMap::from([("10.0.0.2:80".to_string(), ejection_deadline(Duration::from_secs(60)))]),
+        });
+
+        // As after a restart
+        let saved = StateStore::open(&path).rule("web");
+        assert!(saved.drained.contains("10.0.0.1:80"));
+        assert!(saved.ejection_left("10.0.0.2:80").is_some_and(|left| left > Duration::from_secs(50)));
+        assert_eq!(saved.ejection_left("10.0.0.1:80"), None);
+
+        // Clearing a rule drops it from the file
+        store.update("web", SavedRuleState::default());
+        assert_eq!(StateStore::open(&path).rule("web"), SavedRuleState::default());
+        let _ = std::fs::remove_file(&path);
+
+        // Garbage is ignored rather than fatal
+        std::fs::write(&path, "not json").unwrap();
+        assert_eq!(StateStore::open(&path).rule("web"), SavedRuleState::default());
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -2,10 +2,9 @@ use clap::Parser;
 use std::path::PathBuf;
 use std::sync::Arc;
 use tokio::net::TcpListener;
-use log::{info, error, warn};
+use log::{debug, info, error};
 use notify::{Watcher, RecursiveMode, RecommendedWatcher, Event};
 use tokio::sync::mpsc;
-use tokio::sync::RwLock;
 use std::collections::HashMap;
 
 
@@ -15,12 +14,18 @@ mod networking;
 mod traffic;
 mod common;
 mod cluster;
+mod admin;
 pub mod metrics;
 
-use config::{Config, RateLimitConfig, BandwidthLimitConfig};
-use traffic::limiter::{RateLimiter, BandwidthManager};
-use networking::proxy::{self, ProxyConfig};
-use core::{balancer, health};
+use cluster::usage::UsageSync;
+use config::{Config, ConfigFormat};
+use networking::capture::CaptureStore;
+use networking::listener::{spawn_rule_listeners, AcceptThrottle, RuleListeners};
+use networking::proxy::BackendDial;
+use core::{balancer, discovery::DnsDiscovery, health};
+
+// Upper bound on shutdown delay when the cluster socket is unresponsive
+const CLUSTER_LEAVE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(2);
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
@@ -34,281 +39,124 @@ async fn main() -> anyhow::Result<()> {
     let args = Args::parse();
 
     // 1. Load Initial Configuration
-    let config_content = std::fs::read_to_string(&args.config)?;
-    let config: Config = serde_yaml::from_str(&config_content)?;
+    let config = Config::load(&args.config)?;
     config.validate()?;
 
     // Initialize Logger
-    let log_level = if let Some(log_config) = &config.log {
-        &log_config.level
-    } else {
-        "info"
-    };
-    
-    let mut builder = env_logger::Builder::from_env(env_logger::Env::default().default_filter_or(log_level));
-    builder.init();
+    common::rule_log::init(config.log.as_ref().and_then(|log| log.level_filter()))?;
+    common::rule_log::set_rule_levels(rule_log_levels(&config.rules));
+    common::log_throttle::spawn_summary_task();
 
     info!("Loaded configuration with {} rules", config.rules.len());
 
-    // Store LBs for hot reload: Rule Name -> LoadBalancer
-    let lbs: Arc<RwLock<HashMap<String, Arc<balancer::LoadBalancer>>>> = Arc::new(RwLock::new(HashMap::new()));
-    
-    // 2. Initialize Rules & spawn listeners
-    for rule in config.rules.iter() {
-        info!("Initializing rule: {}", rule.name);
-        
-        let lb = Arc::new(balancer::LoadBalancer::new(rule.name.clone(), rule.backends.clone(), rule.backend_connection_limit));
-        lbs.write().await.insert(rule.name.clone(), lb.clone());
-
-        // Spawn Health Checkers
-        if let Some(hc_config) = &rule.health_check {
-            info!("Spawning health checkers for rule '{}'", rule.name);
-            for backend_config in &rule.backends {
-                let backend_addr = match backend_config {
-                     crate::config::BackendConfig::Simple(a) => a.clone(),
-                     crate::config::BackendConfig::Detailed { addr, .. } => addr.clone(),
-                };
-                health::start_health_check(lb.clone(), backend_addr, hc_config.clone());
-            }
-        }
+    if let Some(buckets) = config.metrics.as_ref().and_then(|m| m.connection_duration_buckets.clone()) {
+        info!("Connection duration histogram buckets: {:?}", buckets);
+        metrics::set_connection_duration_buckets(buckets);
+    }
 
-        info!("Rule '{}' Bandwidth Config: {:?}", rule.name, rule.bandwidth_limit);
-
-        let rate_limiter = Arc::new(RateLimiter::new(rule.rate_limit.clone().unwrap_or(RateLimitConfig {
-            enabled: false,
-            requests_per_second: 0,
-            burst: 0,
-        })));
-
-        let bandwidth_manager = Arc::new(BandwidthManager::new(rule.bandwidth_limit.clone().unwrap_or(BandwidthLimitConfig {
-            enabled: false,
-            client: None,
-            backend: None,
-        })));
-
-        // TLS Setup
-        let tls_acceptor = if let Some(tls_config) = &rule.tls {
-             if tls_config.enabled {
-                 Some(Arc::new(crate::networking::tls::load_tls_config(&tls_config.cert, &tls_config.key)?))
-             } else {
-                 None
-             }
-        } else {
-            None
-        };
-
-        // Create a socket2 TCP builder
-        use socket2::{Socket, Domain, Type, Protocol};
-        use std::net::SocketAddr;
-        
-        let addr: SocketAddr = rule.listen.parse().map_err(|e| anyhow::anyhow!("Invalid address: {}", e))?;
-        
-        
-        // Spawn multiple acceptors (one per core is good for high ops)
-        // Default to available parallelism or 4 if unknown.
-        let default_acceptors = std::thread::available_parallelism().map(|n| n.get()).unwrap_or(4);
-        let num_acceptors = std::env::var("NUM_ACCEPTORS")
-            .ok()
-            .and_then(|s| s.parse().ok())
-            .unwrap_or(default_acceptors);
-        
-        info!("Starting {} acceptors for rule: {}", num_acceptors, rule.name);
-
-        for i in 0..num_acceptors {
-            let rule_name = rule.name.clone();
-            // let lb_clone = lb.clone(); // Unused here
-            // let tls_acceptor = tls_acceptor.clone(); // Unused here 
-            // let bw_clone = bandwidth_manager.clone();
-            // let rl_clone = rate_limiter.clone();
-            let backend_tls_config = rule.backend_tls.clone(); // Clone config for closure capture
-
-            // Re-bind needs a new socket for each thread if using SO_REUSEPORT
-            let socket = Socket::new(Domain::IPV4, Type::STREAM, Some(Protocol::TCP))?;
-            
-            #[cfg(unix)]
-            {
-                // socket.set_reuse_port(true)?; // socket2 might need feature "all" or specific handling
-                // Manual setsockopt for SO_REUSEPORT (state 15 on linux, 0x0200 on mac?)
-                // Actually socket2 has `set_reuse_port` if feature is enabled.
-                // Creating socket2 dependency was "all".
-                if let Err(e) = socket.set_reuse_port(true) {
-                     warn!("Failed to set SO_REUSEPORT: {}", e);
-                }
-            }
-            socket.set_reuse_address(true)?;
-            socket.bind(&addr.into())?;
-            socket.listen(1024)?; // Increased backlog
-
-            let std_listener: std::net::TcpListener = socket.into();
-            std_listener.set_nonblocking(true)?;
-
-            let listener: TcpListener = match TcpListener::from_std(std_listener) {
-                Ok(l) => l,
-                Err(e) => {
-                    error!("Failed to convert to tokio listener: {}", e);
-                    continue;
-                }
-            };
+    // Shared with the admin API; also holds the LBs (Rule Name -> LoadBalancer) for hot reload
+    let state_store = config.state_file.as_deref().map(|path| {
+        info!("Keeping admin drains and outlier ejections in {}", path);
+        Arc::new(core::state::StateStore::open(path))
+    });
+    let admin_state = Arc::new(admin::AdminState { state_store, ..Default::default() });
+    let health_checks = admin_state.health_checks.clone();
+    health_checks.set_max_concurrent_probes(config.health_checks.as_ref().and_then(|hc| hc.max_concurrent_probes));
+    // `accept_rate_per_sec`, shared by every rule's acceptors
+    let accept_throttle = Arc::new(AcceptThrottle::default());
+    accept_throttle.set_rate(config.accept_rate_per_sec);
+    
+    // Rule Name -> acceptor tasks (dropping an entry closes the listener)
+    let mut listeners: HashMap<String, RuleListeners> = HashMap::new();
+    // Rule Name -> DNS re-resolution task of its `dns: true` backends
+    let mut discoveries: HashMap<String, DnsDiscovery> = HashMap::new();
 
-            // Use the outer tls_acceptor
+    // Rate limit usage shared with the cluster (enforces limits cluster-wide)
+    let cluster_config = config.cluster.as_ref().filter(|c| c.enabled);
+    let usage_sync = cluster_config.map(|_| Arc::new(UsageSync::default()));
 
-            
-            // let tls_acceptor_clone = tls_acceptor.clone(); // No, TlsAcceptor is Arc internally usually, but here we can clone it. 
-            // Actually TlsAcceptor is cheap to clone (Arc).
-
-            info!("Spawning acceptor {}/{} for rule '{}' on {}", i+1, num_acceptors, rule_name, addr);
-
-            let lb_clone = lb.clone();
-            let bw_clone = bandwidth_manager.clone();
-            let rl_clone = rate_limiter.clone();
-            let r_name_clone = rule_name.clone();
-            let tls_clone = tls_acceptor.clone(); // tokio_rustls::TlsAcceptor is cheap to clone
-            let backend_tls_clone = backend_tls_config.clone();
-            let rule_proxy_protocol = rule.proxy_protocol;
-            
-            // Initialize ACL
-            let acl = Arc::new(crate::networking::acl::AccessControl::new(rule.allow_list.clone(), rule.deny_list.clone()));
-
-            tokio::spawn(async move {
-                loop {
-                     match listener.accept().await {
-                        Ok((stream, client_addr)) => {
-                            if let Err(e) = stream.set_nodelay(true) {
-                                warn!("Failed to set nodelay on client stream: {}", e);
-                            }
-                            
-                            // ACL Check
-                            if !acl.is_allowed(client_addr.ip()) {
-                                warn!("Connection from {} denied by ACL", client_addr);
-                                continue; // Drop connection silently (or we could close explicitly)
-                            }
-                            
-                            // Rate Limit
-                             if !rl_clone.check(client_addr.ip()) {
-                                continue;
-                            }
-                            
-                            let lb = lb_clone.clone();
-                            let bw = bw_clone.clone();
-                            let r_name = r_name_clone.clone();
-                            let tls = tls_clone.clone();
-                            let b_tls = backend_tls_clone.clone(); // Clone for this connection
-
-                            tokio::spawn(async move {
-                                // ... existing proxy logic ...
-                                // Select Backend
-                                let backend = match lb.next_backend() {
-                                    Some(b) => b,
-                                    None => {
-                                        error!("[{}] No available backends", r_name);
-                                        return;
-                                    }
-                                };
-                                let (backend_addr, _guard) = backend;
-
-                                // Bandwidth Limiters
-                                let local_addr = stream.local_addr().unwrap_or_else(|_| "0.0.0.0:0".parse().unwrap());
-                                let proxy_config = ProxyConfig {
-                                    client_read_limiter: bw.get_client_upload_limiter(client_addr.ip()),
-                                    client_write_limiter: bw.get_client_download_limiter(client_addr.ip()),
-                                    backend_read_limiter: bw.get_backend_download_limiter(client_addr.ip().to_string()), 
-                                    backend_write_limiter: bw.get_backend_upload_limiter(client_addr.ip().to_string()),
-                                    backend_tls: b_tls,
-                                    proxy_protocol: rule_proxy_protocol,
-                                    client_addr,
-                                    local_addr,
-                                };
-
-                                if let Some(acceptor) = tls {
-                                    match acceptor.accept(stream).await {
-                                        Ok(tls_stream) => {
-                                    if let Err(_e) = proxy::proxy_connection(tls_stream, backend_addr, proxy_config, r_name.clone()).await {
-                                                // error!("[{}] Proxy error: {}", r_name, e);
-                                            }
-                                         }
-                                        Err(e) => error!("[{}] TLS handshake error: {}", r_name, e),
-                                    }
-                                } else {
-                                    if let Err(e) = proxy::proxy_connection(stream, backend_addr, proxy_config, r_name.clone()).await {
-                                        error!("[{}] Proxy error: {}", r_name, e);
-                                    }
-                                }
-                            });
-                        }
-                        Err(e) => error!("Accept error: {}", e),
-                     }
-                }
-            });
-        }
+    // 2. Initialize Rules & spawn listeners
+    for rule in config.rules.iter() {
+        let (lb, rule_listeners) = start_rule(rule, &admin_state, &health_checks, usage_sync.as_ref(), &accept_throttle).await?;
+        start_discovery(rule, &lb, &health_checks, &mut discoveries);
+        admin_state.load_balancers.write().await.insert(rule.name.clone(), lb);
+        listeners.insert(rule.name.clone(), rule_listeners);
     }
 
 
     // --- Cluster Setup ---
     // Channel for application to send commands to cluster
-    let (_tx_cluster_cmd, rx_cluster_cmd) = mpsc::channel(100);
-    // Channel for cluster to send state updates (node_id, key, usage)
+    let (tx_cluster_cmd, rx_cluster_cmd) = mpsc::channel(1000);
+    // Channel for cluster to send state updates (remote usage, departed nodes)
     let (tx_cluster_state, mut rx_cluster_state) = mpsc::channel(1000);
 
-    if let Some(cluster_config) = &config.cluster {
-        if cluster_config.enabled {
+    if let Some(cluster_config) = cluster_config {
             info!("Initializing Cluster on {}", cluster_config.bind_addr);
             let bind_addr = cluster_config.bind_addr.parse().expect("Invalid cluster bind address");
             let seeds: Vec<std::net::SocketAddr> = cluster_config.peers.iter()
                 .map(|s| s.parse().expect("Invalid seed address"))
                 .collect();
             
-            match cluster::Cluster::new(bind_addr, seeds.clone(), rx_cluster_cmd, tx_cluster_state).await {
+            match cluster::Cluster::new(bind_addr, seeds, rx_cluster_cmd, tx_cluster_state).await {
                 Ok(cluster) => {
-                    tokio::spawn(async move {
-                        cluster.run(seeds).await;
-                    });
+                    tokio::spawn(cluster.run());
                     info!("Cluster started.");
                 }
                 Err(e) => error!("Failed to start cluster: {}", e),
             }
-        }
     }
     
-    // Spawn a task to handle cluster state updates (placeholder for now)
-    tokio::spawn(async move {
-        while let Some((node_id, key, usage)) = rx_cluster_state.recv().await {
-            info!("Cluster Update: Node {} Key {} Usage {}", node_id, key, usage);
-            // TODO: Update global rate limiter
-        }
-    });
-
-
-    // --- Metrics Server ---
-    tokio::spawn(async move {
-        use hyper::server::conn::http1;
-        use hyper::service::service_fn;
-        // use hyper::{Request, Response, StatusCode}; // Removed unused imports
-        use hyper_util::rt::TokioIo;
-        
-        let addr = std::net::SocketAddr::from(([0, 0, 0, 0], 9091));
-        let listener = match tokio::net::TcpListener::bind(addr).await {
-            Ok(l) => l,
-            Err(e) => {
-                error!("Failed to bind metrics port: {}", e);
-                return;
+    // Kept for the leave on shutdown
+    let cluster_cmd = cluster_config.map(|_| tx_cluster_cmd.clone());
+
+    if let Some(sync) = &usage_sync {
+        // Remote usage drains the local buckets
+        let sync_rx = sync.clone();
+        tokio::spawn(async move {
+            while let Some(event) = rx_cluster_state.recv().await {
+                match event {
+                    cluster::ClusterEvent::Usage(node_id, key, usage) => {
+                        debug!("Cluster Update: Node {} Key {} Usage {}", node_id, key, usage);
+                        sync_rx.apply_remote(node_id, &key, usage);
+                    }
+                    cluster::ClusterEvent::NodeDown(node_id) => sync_rx.remove_node(node_id),
+                }
+            }
+        });
+
+        // Idle keys are dropped like the limiters' own buckets
+        let sync_prune = sync.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(cluster::usage::USAGE_PRUNE_INTERVAL);
+            loop {
+                ticker.tick().await;
+                sync_prune.prune();
             }
-        };
-        info!("Metrics server listening on http://{}", addr);
-
-        loop {
-            if let Ok((stream, _)) = listener.accept().await {
-                let io = TokioIo::new(stream);
-                tokio::spawn(async move {
-                    if let Err(_err) = http1::Builder::new()
-                        .serve_connection(io, service_fn(crate::metrics::metrics_handler))
-                        .await
-                    {
-                        // error!("Error serving metrics: {:?}", err);
+        });
+
+        // Local usage goes out on every sync tick
+        let sync_tx = sync.clone();
+        let interval_ms = cluster_config.and_then(|c| c.sync_interval_ms).unwrap_or(cluster::usage::DEFAULT_USAGE_SYNC_INTERVAL_MS);
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(std::time::Duration::from_millis(interval_ms));
+            loop {
+                ticker.tick().await;
+                for (key, usage) in sync_tx.take_dirty() {
+                    if tx_cluster_cmd.send(cluster::ClusterCommand::BroadcastUsage(key, usage)).await.is_err() {
+                        return; // Cluster failed to start or stopped
                     }
-                });
+                }
             }
-        }
-    });
+        });
+    }
+
+
+    // --- Metrics Server (+ admin API under /api/) ---
+    let metrics_listen = config.metrics.as_ref().map(|m| m.listen.as_str()).unwrap_or(metrics::DEFAULT_METRICS_LISTEN);
+    let metrics_listener = TcpListener::bind(metrics_listen).await
+        .map_err(|e| anyhow::anyhow!("Failed to bind metrics server on {}: {}", metrics_listen, e))?;
+    info!("Metrics server listening on http://{}/metrics", metrics_listener.local_addr()?);
+    tokio::spawn(metrics::serve(metrics_listener, admin_state.clone()));
 
     // 3. Setup Config Watcher (Hot Reload)
     let (tx, mut rx) = mpsc::channel(1);
@@ -328,41 +176,206 @@ async fn main() -> anyhow::Result<()> {
     watcher.watch(&config_path, RecursiveMode::NonRecursive)?;
     info!("Watching config file for changes...");
 
-    // Main loop: wait for config updates
-    while let Some(_) = rx.recv().await {
+    // Main loop: wait for config updates until asked to stop
+    let shutdown = shutdown_signal();
+    tokio::pin!(shutdown);
+    loop {
+        tokio::select! {
+            change = rx.recv() => {
+                if change.is_none() {
+                    break;
+                }
+            }
+            _ = &mut shutdown => {
+                info!("Shutdown signal received");
+                break;
+            }
+        }
         info!("Config change detected, reloading...");
         match std::fs::read_to_string(&config_path) {
             Ok(content) => {
-                match serde_yaml::from_str::<Config>(&content) {
+                match Config::parse(&content, ConfigFormat::from_path(&config_path)).and_then(|c| c.validate().map(|_| c)) {
                     Ok(new_config) => {
+                        common::rule_log::set_rule_levels(rule_log_levels(&new_config.rules));
+                        health_checks.set_max_concurrent_probes(new_config.health_checks.as_ref().and_then(|hc| hc.max_concurrent_probes));
+                        accept_throttle.set_rate(new_config.accept_rate_per_sec);
+
                         // Reconcile rules
-                        let lbs_read = lbs.read().await;
+                        let mut lbs_write = admin_state.load_balancers.write().await;
+                        let removed: Vec<String> = lbs_write.keys()
+                            .filter(|name| !new_config.rules.iter().any(|r| &r.name == *name))
+                            .cloned()
+                            .collect();
+                        for name in removed {
+                            info!("Rule '{}' removed, stopping its listeners", name);
+                            listeners.remove(&name);
+                            if let Some(sync) = &usage_sync {
+                                sync.unregister(&name);
+                            }
+                            if let Some(discovery) = discoveries.remove(&name) {
+                                discovery.stop().await;
+                            }
+                            if let Some(lb) = lbs_write.remove(&name) {
+                                health_checks.sync(&name, &lb, &[], &[]).await;
+                            }
+                            admin_state.captures.write().await.remove(&name);
+                            admin_state.listen_addrs.write().await.remove(&name);
+                            admin_state.backend_dials.write().await.remove(&name);
+                        }
+
                         for rule in new_config.rules {
-                            if let Some(lb) = lbs_read.get(&rule.name) {
+                            if let Some(lb) = lbs_write.get(&rule.name) {
                                 info!("Updating backends for rule '{}'", rule.name);
-                                lb.update_backends(rule.backends.clone()).await;
-                                
-                                // Spawn health checks for new backends (NOTE: this duplicates checkers for existing backends)
-                                if let Some(hc_config) = &rule.health_check {
-                                     for backend_config in &rule.backends {
-                                         let backend_addr = match backend_config {
-                                             crate::config::BackendConfig::Simple(a) => a.clone(),
-                                             crate::config::BackendConfig::Detailed { addr, .. } => addr.clone(),
-                                         };
-                                         health::start_health_check(lb.clone(), backend_addr, hc_config.clone());
-                                     }
+                                if let Some(discovery) = discoveries.remove(&rule.name) {
+                                    discovery.stop().await;
+                                }
+                                // With DNS backends, the new discovery task applies the resolved set itself
+                                lb.update_backup_backends(rule.backup_backends.clone()).await;
+                                if !start_discovery(&rule, lb, &health_checks, &mut discoveries) {
+                                    lb.update_backends(rule.backends.clone()).await;
+
+                                    // Start checkers for new backends, stop those of removed ones
+                                    let checks = rule.health_check.as_ref().map(|hc| hc.checks()).unwrap_or_default();
+                                    health_checks.sync(&rule.name, lb, &backend_addrs(&rule), &checks).await;
                                 }
                             } else {
-                                warn!("New rule '{}' detected but dynamic listener spawning is not yet supported. Restart required.", rule.name);
+                                info!("New rule '{}' detected, starting listeners", rule.name);
+                                match start_rule(&rule, &admin_state, &health_checks, usage_sync.as_ref(), &accept_throttle).await {
+                                    Ok((lb, rule_listeners)) => {
+                                        start_discovery(&rule, &lb, &health_checks, &mut discoveries);
+                                        lbs_write.insert(rule.name.clone(), lb);
+                                        listeners.insert(rule.name.clone(), rule_listeners);
+                                    }
+                                    Err(e) => error!("Failed to start rule '{}': {}", rule.name, e),
+                                }
                             }
                         }
                     }
-                    Err(e) => error!("Failed to parse new config: {}", e),
+                    Err(e) => error!("Rejected new config: {}", e),
                 }
             },
             Err(e) => error!("Failed to read config file: {}", e),
         }
     }
 
+    if let Some(cluster_cmd) = cluster_cmd {
+        let (done_tx, done_rx) = tokio::sync::oneshot::channel();
+        if cluster_cmd.send(cluster::ClusterCommand::Leave(done_tx)).await.is_ok() {
+            let _ = tokio::time::timeout(CLUSTER_LEAVE_TIMEOUT, done_rx).await;
+        }
+    }
+
     Ok(())
 }
+
+// SIGTERM (Kubernetes / systemd stop) or Ctrl-C
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut term) => {
+                tokio::select! {
+                    _ = term.recv() => {}
+                    _ = tokio::signal::ctrl_c() => {}
+                }
+            }
+            Err(e) => {
+                error!("Failed to install SIGTERM handler: {}", e);
+                let _ = tokio::signal::ctrl_c().await;
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+// Primary and backup backends; both get health checks
+fn backend_addrs(rule: &config::LBRule) -> Vec<String> {
+    rule.backends.iter().chain(&rule.backup_backends).map(|b| b.addr().to_string()).collect()
+}
+
+fn rule_log_levels(rules: &[config::LBRule]) -> HashMap<String, log::LevelFilter> {
+    rules.iter()
+        .filter_map(|rule| Some((rule.name.clone(), rule.log_level_filter()?)))
+        .collect()
+}
+
+// Starts re-resolving the rule's `dns: true` backends; false if it has none
+fn start_discovery(
+    rule: &config::LBRule,
+    lb: &Arc<balancer::LoadBalancer>,
+    health_checks: &Arc<health::HealthCheckRegistry>,
+    discoveries: &mut HashMap<String, DnsDiscovery>,
+) -> bool {
+    let Some(discovery) = DnsDiscovery::spawn(rule, lb.clone(), health_checks.clone()) else {
+        return false;
+    };
+    info!("Rule '{}' re-resolving DNS backends every {}ms", rule.name, rule.dns_refresh_ms.unwrap_or(core::discovery::DEFAULT_DNS_REFRESH_MS));
+    discoveries.insert(rule.name.clone(), discovery);
+    true
+}
+
+// Builds a rule's load balancer, health checks and capture store, then binds its listeners
+async fn start_rule(
+    rule: &config::LBRule,
+    admin_state: &admin::AdminState,
+    health_checks: &health::HealthCheckRegistry,
+    usage_sync: Option<&Arc<UsageSync>>,
+    accept_throttle: &Arc<AcceptThrottle>,
+) -> anyhow::Result<(Arc<balancer::LoadBalancer>, RuleListeners)> {
+    info!("Initializing rule: {}", rule.name);
+
+    let mut lb = balancer::LoadBalancer::new(rule.name.clone(), rule.backends.clone(), rule.backend_connection_limit, rule.strategy)
+        .with_overflow(rule.overflow);
+    if let Some(sticky) = &rule.sticky {
+        lb = lb.with_sticky(std::time::Duration::from_millis(sticky.ttl_ms));
+    }
+    if let Some(passive) = &rule.passive_health {
+        lb = lb.with_passive_health(passive.max_failures, std::time::Duration::from_millis(passive.fail_timeout_ms));
+    }
+    if let Some(outlier) = &rule.outlier_detection {
+        lb = lb.with_outlier_detection(outlier);
+    }
+    if let Some(adaptive) = &rule.adaptive_weights {
+        lb = lb.with_adaptive_weights(adaptive);
+    }
+    if let Some(grace_ms) = rule.removal_grace_ms {
+        lb = lb.with_removal_grace(std::time::Duration::from_millis(grace_ms));
+    }
+    if !rule.backup_backends.is_empty() {
+        info!("Rule '{}' failing over to {} backup backend(s) when no primary is available", rule.name, rule.backup_backends.len());
+        lb = lb.with_backup_backends(rule.backup_backends.clone());
+    }
+    if let Some(store) = &admin_state.state_store {
+        lb = lb.with_state_store(store.clone());
+    }
+    let lb = Arc::new(lb);
+    lb.spawn_adaptive_weights();
+
+    let capture_store = match &rule.capture {
+        Some(c) if c.enabled => {
+            info!("Rule '{}' capturing first {} bytes of {:.1}% of connections", rule.name, c.max_bytes, c.sample_rate * 100.0);
+            Some(Arc::new(CaptureStore::new(c.clone())))
+        }
+        _ => None,
+    };
+
+    // Bind first so a port clash leaves no checkers or captures behind
+    let dial = Arc::new(BackendDial::for_rule(rule));
+    let rule_listeners = spawn_rule_listeners(rule, lb.clone(), capture_store.clone(), dial.clone(), usage_sync.cloned(), accept_throttle.clone()).await?;
+
+    // Spawn Health Checkers
+    let checks = rule.health_check.as_ref().map(|hc| hc.checks()).unwrap_or_default();
+    if !checks.is_empty() {
+        info!("Spawning {} health checker(s) per backend for rule '{}'", checks.len(), rule.name);
+    }
+    health_checks.sync(&rule.name, &lb, &backend_addrs(rule), &checks).await;
+
+    if let Some(store) = capture_store {
+        admin_state.captures.write().await.insert(rule.name.clone(), store);
+    }
+    admin_state.listen_addrs.write().await.insert(rule.name.clone(), rule.listen.clone());
+    admin_state.backend_dials.write().await.insert(rule.name.clone(), dial);
+    Ok((lb, rule_listeners))
+}
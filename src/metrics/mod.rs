@@ -1,3 +1,4 @@
+use std::sync::OnceLock;
 use lazy_static::lazy_static;
 use prometheus::{
     register_gauge_vec, register_int_counter_vec, register_histogram_vec,
@@ -18,6 +19,12 @@ lazy_static! {
         &["rule_name"]
     ).unwrap();
 
+    pub static ref CONNECTIONS_BY_PROTOCOL: IntCounterVec = register_int_counter_vec!(
+        "l4lb_connections_by_protocol_total",
+        "Connections by protocol guessed from their first bytes (rules with protocol_sniff)",
+        &["rule_name", "proto"]
+    ).unwrap();
+
     // --- Traffic Metrics ---
     // incoming traffic: client -> lb -> backend
     // outgoing traffic: backend -> lb -> client
@@ -36,27 +43,182 @@ lazy_static! {
         &["rule_name", "backend_addr"]
     ).unwrap();
 
+    pub static ref BACKEND_SELECTED: IntCounterVec = register_int_counter_vec!(
+        "l4lb_backend_selected_total",
+        "Times a backend was selected for a new connection (lifetime, unlike active connections)",
+        &["rule_name", "backend_addr"]
+    ).unwrap();
+
     pub static ref BACKEND_HEALTH_STATUS: GaugeVec = register_gauge_vec!(
         "l4lb_backend_health_status",
         "Health status of backend (1 = healthy, 0 = unhealthy)",
         &["rule_name", "backend_addr"]
     ).unwrap();
 
+    pub static ref BACKEND_EFFECTIVE_WEIGHT: GaugeVec = register_gauge_vec!(
+        "l4lb_backend_effective_weight",
+        "Backend weight after adaptive_weights adjustment",
+        &["rule_name", "backend_addr"]
+    ).unwrap();
+
+    pub static ref OUTLIER_EJECTIONS: IntCounterVec = register_int_counter_vec!(
+        "l4lb_outlier_ejections_total",
+        "Backends ejected by outlier detection (connect error rate over threshold)",
+        &["rule_name", "backend_addr"]
+    ).unwrap();
+
+    pub static ref TLS_HANDSHAKES: IntCounterVec = register_int_counter_vec!(
+        "l4lb_tls_handshakes_total",
+        "Completed client TLS handshakes by negotiated protocol version and cipher suite",
+        &["rule_name", "version", "cipher"]
+    ).unwrap();
+
+    pub static ref TLS_HANDSHAKE_ABORTED: IntCounterVec = register_int_counter_vec!(
+        "l4lb_tls_handshake_aborted_total",
+        "TLS handshakes abandoned by the client (EOF/reset before completion)",
+        &["rule_name"]
+    ).unwrap();
+    pub static ref TLS_CLIENT_CERT_REJECTED: IntCounterVec = register_int_counter_vec!(
+        "l4lb_tls_client_cert_rejected_total",
+        "TLS handshakes rejected for a missing or invalid client certificate (mTLS)",
+        &["rule_name"]
+    ).unwrap();
+
+    pub static ref TIMEOUTS: IntCounterVec = register_int_counter_vec!(
+        "l4lb_timeouts_total",
+        "Connections closed by a timeout (connect: backend connect, idle: no traffic either way)",
+        &["rule_name", "kind"]
+    ).unwrap();
+
+    pub static ref BACKEND_RETRIES: IntCounterVec = register_int_counter_vec!(
+        "l4lb_backend_retries_total",
+        "Connections moved to another backend after a failed backend connect",
+        &["rule_name"]
+    ).unwrap();
+
+    pub static ref BACKEND_SELECTIONS: IntCounterVec = register_int_counter_vec!(
+        "l4lb_backend_selections_total",
+        "Backends selected for new connections, by pool (primary, or backup while no primary is available)",
+        &["rule_name", "pool"]
+    ).unwrap();
+
+    pub static ref BACKEND_POOL: IntCounterVec = register_int_counter_vec!(
+        "l4lb_backend_pool_total",
+        "Backend connections taken from the rule's backend_pool (hit) or connected fresh because none was ready (miss)",
+        &["rule_name", "result"]
+    ).unwrap();
+
+    pub static ref REJECTED_CONNECTIONS: IntCounterVec = register_int_counter_vec!(
+        "l4lb_rejected_connections_total",
+        "Connections (or UDP sessions) refused before reaching a backend",
+        &["rule_name", "reason"]
+    ).unwrap();
+
+    pub static ref RATE_LIMITER_ACTIVE_KEYS: GaugeVec = register_gauge_vec!(
+        "l4lb_rate_limiter_active_keys",
+        "Per-key limiters currently tracked (rate_limit: client IPs/subnets; client_*/backend_*: bandwidth limiters)",
+        &["rule_name", "kind"]
+    ).unwrap();
+
+    pub static ref ERRORS: IntCounterVec = register_int_counter_vec!(
+        "l4lb_errors_total",
+        "Failed or refused connections (tls_handshake, backend_connect, proxy_io, backend_eof, rate_limited, acl_denied)",
+        &["rule_name", "kind"]
+    ).unwrap();
+
     // --- Latency (P95, P99, etc. calculated by histogram) ---
     pub static ref CONNECTION_DURATION: HistogramVec = register_histogram_vec!(
         "l4lb_connection_duration_seconds",
         "Duration of connections in seconds",
         &["rule_name"],
-        vec![0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0, 5.0, 10.0, 30.0, 60.0, 300.0] 
+        CONNECTION_DURATION_BUCKETS.get().cloned().unwrap_or_else(|| DEFAULT_CONNECTION_DURATION_BUCKETS.to_vec())
     ).unwrap();
 }
 
-use hyper::{Request, Response, StatusCode};
+/// Bucket upper bounds (seconds) of `l4lb_connection_duration_seconds` without
+/// `metrics.connection_duration_buckets`
+pub const DEFAULT_CONNECTION_DURATION_BUCKETS: [f64; 12] = [0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0, 5.0, 10.0, 30.0, 60.0, 300.0];
+
+static CONNECTION_DURATION_BUCKETS: OnceLock<Vec<f64>> = OnceLock::new();
+
+/// Overrides the connection duration buckets. The histogram is built on first
+/// use, so this only works at startup, before any connection is recorded.
+pub fn set_connection_duration_buckets(buckets: Vec<f64>) {
+    if CONNECTION_DURATION_BUCKETS.set(buckets).is_err() {
+        log::warn!("Connection duration buckets already set, keeping the first ones");
+    }
+}
+
+use hyper::{Method, Request, Response, StatusCode};
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper_util::rt::TokioIo;
 use http_body_util::Full;
 use bytes::Bytes;
 use prometheus::{Encoder, TextEncoder};
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use crate::admin::AdminState;
 
-// ... existing lazy_static ...
+pub const DEFAULT_METRICS_LISTEN: &str = "0.0.0.0:9091";
+
+// Serves GET /metrics and the admin API (/api/...) on an already-bound listener
+pub async fn serve(listener: TcpListener, admin: Arc<AdminState>) {
+    loop {
+        let stream = match listener.accept().await {
+            Ok((stream, _)) => stream,
+            Err(e) => {
+                log::debug!("Metrics accept error: {}", e);
+                continue;
+            }
+        };
+        let io = TokioIo::new(stream);
+        let admin = admin.clone();
+        tokio::spawn(async move {
+            let service = service_fn(move |req| route(req, admin.clone()));
+            if let Err(e) = http1::Builder::new().serve_connection(io, service).await {
+                log::debug!("Error serving metrics connection: {}", e);
+            }
+        });
+    }
+}
+
+async fn route(req: Request<hyper::body::Incoming>, admin: Arc<AdminState>) -> Result<Response<Full<Bytes>>, hyper::Error> {
+    let path = req.uri().path();
+    if path.starts_with("/api/") {
+        return crate::admin::admin_handler(req, admin).await;
+    }
+    if req.method() == Method::GET {
+        match path {
+            "/metrics" => return metrics_handler(req).await,
+            // Liveness: answering at all means the event loop is running
+            "/healthz" => return Ok(plain(StatusCode::OK, "ok".to_string())),
+            // Readiness: every rule can reach at least one backend
+            "/ready" => {
+                let down = admin.rules_without_backends().await;
+                return Ok(if down.is_empty() {
+                    plain(StatusCode::OK, "ready".to_string())
+                } else {
+                    plain(StatusCode::SERVICE_UNAVAILABLE, format!("no healthy backends for rules: {}", down.join(", ")))
+                });
+            }
+            _ => {}
+        }
+    }
+
+    Ok(Response::builder()
+        .status(StatusCode::NOT_FOUND)
+        .body(Full::new(Bytes::from("Not Found")))
+        .unwrap())
+}
+
+fn plain(status: StatusCode, body: String) -> Response<Full<Bytes>> {
+    Response::builder()
+        .status(status)
+        .header("Content-Type", "text/plain")
+        .body(Full::new(Bytes::from(body)))
+        .unwrap()
+}
 
 pub async fn metrics_handler(_req: Request<hyper::body::Incoming>) -> Result<Response<Full<Bytes>>, hyper::Error> {
     let encoder = TextEncoder::new();
@@ -2,6 +2,7 @@ use ipnet::IpNet;
 use std::net::IpAddr;
 use std::str::FromStr;
 use log::{warn, debug};
+use crate::config::LBRule;
 
 #[derive(Clone, Debug)]
 pub struct AccessControl {
@@ -20,6 +21,16 @@ impl AccessControl {
         }
     }
 
+    /// Builds the ACL for a rule from its `acl` block plus the legacy `allow_list`/`deny_list`.
+    pub fn for_rule(rule: &LBRule) -> Self {
+        let acl = rule.acl.clone().unwrap_or_default();
+        let merge = |mut list: Vec<String>, legacy: &Option<Vec<String>>| {
+            list.extend(legacy.iter().flatten().cloned());
+            Some(list).filter(|l| !l.is_empty())
+        };
+        Self::new(merge(acl.allow, &rule.allow_list), merge(acl.deny, &rule.deny_list))
+    }
+
     pub fn is_allowed(&self, ip: IpAddr) -> bool {
         // 1. Check Deny List first (Blocklist)
         for net in &self.deny_list {
@@ -106,4 +117,21 @@ mod tests {
         assert!(!acl.is_allowed(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1))));
         assert!(acl.is_allowed(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 2))));
     }
+
+    #[test]
+    fn test_for_rule_merges_legacy_lists() {
+        let yaml = r#"
+name: "acl_test"
+listen: "0.0.0.0:8080"
+backends: ["10.0.0.1:80"]
+acl:
+  allow: ["10.0.0.0/24"]
+deny_list: ["10.0.0.1"]
+"#;
+        let rule: LBRule = serde_yaml::from_str(yaml).unwrap();
+        let acl = AccessControl::for_rule(&rule);
+        assert!(!acl.is_allowed(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1))));
+        assert!(acl.is_allowed(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2))));
+        assert!(!acl.is_allowed(IpAddr::V4(Ipv4Addr::new(192, 168, 1, 1))));
+    }
 }
@@ -0,0 +1,254 @@
+use std::collections::VecDeque;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, SystemTime};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf};
+use log::debug;
+use crate::config::CaptureConfig;
+
+// Hard ceiling on bytes kept per sample regardless of config
+pub const MAX_CAPTURE_BYTES: usize = 1024 * 1024;
+
+#[derive(Debug)]
+pub struct CapturedSample {
+    pub id: u64,
+    pub client_addr: SocketAddr,
+    pub local_addr: SocketAddr, // Destination the client connected to (PROXY header of a replay)
+    pub backend_addr: String,
+    pub captured_at: SystemTime,
+    pub data: Vec<u8>,
+}
+
+/// Bounded ring of sampled connection prefixes (the first `max_bytes` sent by the client).
+pub struct CaptureStore {
+    config: CaptureConfig,
+    next_id: AtomicU64,
+    samples: Mutex<VecDeque<Arc<CapturedSample>>>,
+}
+
+impl CaptureStore {
+    pub fn new(config: CaptureConfig) -> Self {
+        CaptureStore {
+            config,
+            next_id: AtomicU64::new(1),
+            samples: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    pub fn should_sample(&self) -> bool {
+        self.config.enabled && self.config.sample_rate > 0.0 && rand::random::<f64>() < self.config.sample_rate
+    }
+
+    pub(crate) fn record(&self, client_addr: SocketAddr, local_addr: SocketAddr, backend_addr: String, data: Vec<u8>) {
+        let sample = Arc::new(CapturedSample {
+            id: self.next_id.fetch_add(1, Ordering::Relaxed),
+            client_addr,
+            local_addr,
+            backend_addr,
+            captured_at: SystemTime::now(),
+            data,
+        });
+        debug!("Captured {} bytes from {} (sample {})", sample.data.len(), client_addr, sample.id);
+
+        let mut samples = self.samples.lock().unwrap();
+        if samples.len() >= self.config.max_samples.max(1) {
+            samples.pop_front();
+        }
+        samples.push_back(sample);
+    }
+
+    pub fn list(&self) -> Vec<Arc<CapturedSample>> {
+        self.samples.lock().unwrap().iter().cloned().collect()
+    }
+
+    pub fn get(&self, id: u64) -> Option<Arc<CapturedSample>> {
+        self.samples.lock().unwrap().iter().find(|s| s.id == id).cloned()
+    }
+}
+
+/// Passthrough stream recording the first bytes read from the client. The sample is
+/// committed to the store once the cap is reached or the stream is dropped.
+pub struct CapturingStream<S> {
+    inner: S,
+    store: Option<Arc<CaptureStore>>,
+    buf: Vec<u8>,
+    limit: usize,
+    client_addr: SocketAddr,
+    local_addr: SocketAddr,
+    backend_addr: String,
+}
+
+impl<S> CapturingStream<S> {
+    pub fn new(inner: S, store: Option<Arc<CaptureStore>>, client_addr: SocketAddr, local_addr: SocketAddr, backend_addr: &str) -> Self {
+        let limit = store.as_ref().map(|s| s.config.max_bytes.min(MAX_CAPTURE_BYTES)).unwrap_or(0);
+        CapturingStream {
+            inner,
+            store,
+            buf: Vec::new(),
+            limit,
+            client_addr,
+            local_addr,
+            backend_addr: backend_addr.to_string(),
+        }
+    }
+
+    fn commit(&mut self) {
+        if let Some(store) = self.store.take()
+            && !self.buf.is_empty() {
+                store.record(self.client_addr, self.local_addr, self.backend_addr.clone(), std::mem::take(&mut self.buf));
+        }
+    }
+}
+
+impl<S> Drop for CapturingStream<S> {
+    fn drop(&mut self) {
+        self.commit();
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for CapturingStream<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let this = self.get_mut();
+        let before = buf.filled().len();
+        let res = Pin::new(&mut this.inner).poll_read(cx, buf);
+
+        if this.store.is_some() {
+            let read = &buf.filled()[before..];
+            let take = read.len().min(this.limit - this.buf.len());
+            this.buf.extend_from_slice(&read[..take]);
+            if this.buf.len() >= this.limit {
+                this.commit();
+            }
+        }
+        res
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for CapturingStream<S> {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<std::io::Result<usize>> {
+        Pin::new(&mut self.get_mut().inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+/// Sends a captured request over an open backend connection (see
+/// `proxy::open_backend`) and returns whatever the backend answers (up to
+/// `max_response` bytes) before it closes or `timeout` elapses.
+pub async fn replay<S: AsyncRead + AsyncWrite + Unpin>(mut stream: S, data: &[u8], backend_addr: &str, timeout: Duration, max_response: usize) -> anyhow::Result<Vec<u8>> {
+    stream.write_all(data).await?;
+
+    let mut response = Vec::new();
+    let mut chunk = [0u8; 8192];
+    let read_all = async {
+        while response.len() < max_response {
+            let n = stream.read(&mut chunk).await?;
+            if n == 0 {
+                break;
+            }
+            let take = n.min(max_response - response.len());
+            response.extend_from_slice(&chunk[..take]);
+        }
+        Ok::<(), std::io::Error>(())
+    };
+
+    // A backend that keeps the connection open just ends the replay at the timeout
+    match tokio::time::timeout(timeout, read_all).await {
+        Ok(res) => res?,
+        Err(_) => debug!("Replay to {} timed out after {} bytes", backend_addr, response.len()),
+    }
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::duplex;
+
+    fn store(max_bytes: usize, max_samples: usize) -> Arc<CaptureStore> {
+        Arc::new(CaptureStore::new(CaptureConfig { enabled: true, sample_rate: 1.0, max_bytes, max_samples }))
+    }
+
+    fn addr(port: u16) -> SocketAddr {
+        SocketAddr::from(([10, 0, 0, 1], port))
+    }
+
+    #[tokio::test]
+    async fn test_capturing_stream_truncates_and_commits_on_drop() {
+        let store = store(4, 8);
+        let (mut client, server) = duplex(64);
+        let mut stream = CapturingStream::new(server, Some(store.clone()), addr(5000), addr(443), "10.0.0.2:80");
+        client.write_all(b"hello world").await.unwrap();
+        let mut buf = [0u8; 11];
+        stream.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"hello world"); // The client's bytes pass through untouched
+
+        // Committed as soon as the cap is reached, not again on drop
+        let samples = store.list();
+        assert_eq!(samples.len(), 1);
+        assert_eq!(samples[0].data, b"hell");
+        assert_eq!((samples[0].client_addr, samples[0].local_addr, samples[0].backend_addr.as_str()), (addr(5000), addr(443), "10.0.0.2:80"));
+        drop(stream);
+        assert_eq!(store.list().len(), 1);
+
+        // Under the cap: committed when the connection ends
+        let (mut client, server) = duplex(64);
+        let mut stream = CapturingStream::new(server, Some(store.clone()), addr(5001), addr(443), "10.0.0.2:80");
+        client.write_all(b"hi").await.unwrap();
+        let mut buf = [0u8; 2];
+        stream.read_exact(&mut buf).await.unwrap();
+        assert_eq!(store.list().len(), 1);
+        drop(stream);
+        assert_eq!(store.list()[1].data, b"hi");
+    }
+
+    #[test]
+    fn test_store_evicts_oldest_sample() {
+        let store = store(16, 2);
+        for port in 1..=3 {
+            store.record(addr(port), addr(443), "10.0.0.2:80".to_string(), vec![port as u8]);
+        }
+        let ids: Vec<u64> = store.list().iter().map(|s| s.id).collect();
+        assert_eq!(ids, vec![2, 3]);
+        assert!(store.get(1).is_none());
+        assert_eq!(store.get(3).unwrap().data, vec![3]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_replay_caps_response_and_times_out() {
+        // Backend answering with more than the cap
+        let (replayer, mut backend) = duplex(1024);
+        let server = tokio::spawn(async move {
+            let mut request = [0u8; 4];
+            backend.read_exact(&mut request).await.unwrap();
+            backend.write_all(&[b'x'; 100]).await.unwrap();
+            (request, backend)
+        });
+        let response = replay(replayer, b"PING", "b", Duration::from_secs(5), 10).await.unwrap();
+        assert_eq!(response, vec![b'x'; 10]);
+        let (request, _backend) = server.await.unwrap();
+        assert_eq!(&request, b"PING");
+
+        // Backend that answers and keeps the connection open: the replay ends at the timeout
+        let (replayer, mut backend) = duplex(1024);
+        backend.write_all(b"OK").await.unwrap();
+        let started = tokio::time::Instant::now();
+        let response = replay(replayer, b"PING", "b", Duration::from_millis(200), 1024).await.unwrap();
+        assert_eq!(response, b"OK");
+        assert!(started.elapsed() >= Duration::from_millis(200));
+        drop(backend);
+    }
+}
@@ -0,0 +1,85 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Instant, SystemTime};
+use dashmap::DashMap;
+use lazy_static::lazy_static;
+use crate::common::io::ByteCounters;
+
+lazy_static! {
+    // Connection id -> live TCP connection, across all rules
+    static ref ACTIVE: DashMap<u64, Arc<ActiveConnection>> = DashMap::new();
+}
+
+/// A proxied TCP connection between backend connect and close
+pub struct ActiveConnection {
+    pub id: u64,
+    pub rule_name: String,
+    pub client_addr: SocketAddr,
+    pub backend_addr: String,
+    pub started_at: SystemTime,
+    pub started: Instant,
+    pub bytes: Arc<ByteCounters>, // Updated by the relay as bytes move
+}
+
+/// Listing of a connection in the registry; dropping it removes the entry
+pub struct Registration(Arc<ActiveConnection>);
+
+impl Registration {
+    pub fn bytes(&self) -> &Arc<ByteCounters> {
+        &self.0.bytes
+    }
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        ACTIVE.remove(&self.0.id);
+    }
+}
+
+pub fn register(id: u64, rule_name: &str, client_addr: SocketAddr, backend_addr: &str) -> Registration {
+    let conn = Arc::new(ActiveConnection {
+        id,
+        rule_name: rule_name.to_string(),
+        client_addr,
+        backend_addr: backend_addr.to_string(),
+        started_at: SystemTime::now(),
+        started: Instant::now(),
+        bytes: Arc::new(ByteCounters::default()),
+    });
+    ACTIVE.insert(id, conn.clone());
+    Registration(conn)
+}
+
+/// Open connections (of one rule, or all), oldest first
+pub fn active(rule_name: Option<&str>) -> Vec<Arc<ActiveConnection>> {
+    let mut conns: Vec<_> = ACTIVE.iter()
+        .filter(|entry| rule_name.is_none_or(|name| entry.rule_name == name))
+        .map(|entry| entry.value().clone())
+        .collect();
+    conns.sort_by_key(|conn| conn.id);
+    conns
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::Ordering;
+
+    #[test]
+    fn test_registry_tracks_open_connections() {
+        let client = "10.0.0.1:5000".parse().unwrap();
+        let first = register(u64::MAX - 1, "conn_registry_test", client, "10.0.1.1:80");
+        let second = register(u64::MAX, "conn_registry_test", client, "10.0.1.2:80");
+        first.bytes().client_to_backend.fetch_add(42, Ordering::Relaxed);
+
+        let conns = active(Some("conn_registry_test"));
+        assert_eq!(conns.iter().map(|c| c.id).collect::<Vec<_>>(), [u64::MAX - 1, u64::MAX]);
+        assert_eq!(conns[0].bytes.client_to_backend.load(Ordering::Relaxed), 42);
+        assert_eq!(conns[1].backend_addr, "10.0.1.2:80");
+
+        // Closed connections leave the registry
+        drop(first);
+        drop(second);
+        assert!(active(Some("conn_registry_test")).is_empty());
+    }
+}
@@ -0,0 +1,119 @@
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+use tokio::net::{TcpSocket, TcpStream};
+use tokio::task::JoinSet;
+
+/// Head start each connection attempt gets before the next address is tried
+/// (RFC 8305 "Connection Attempt Delay")
+pub const CONNECTION_ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+
+/// Resolves `addr` ("host:port") and races connections to its addresses, RFC
+/// 8305 style: attempts start `CONNECTION_ATTEMPT_DELAY` apart (or as soon as
+/// the previous one fails), alternating address families, and the first to
+/// connect wins. A dead IPv6 path then costs 250ms instead of a full connect
+/// timeout.
+///
+/// With a `source` address the socket is bound to it first, and only
+/// addresses of its family are tried.
+pub async fn connect(addr: &str, source: Option<IpAddr>) -> io::Result<TcpStream> {
+    let addrs = interleave(tokio::net::lookup_host(addr).await?
+        .filter(|a| source.is_none_or(|s| s.is_ipv4() == a.is_ipv4()))
+        .collect());
+    match addrs.as_slice() {
+        [] => Err(io::Error::new(io::ErrorKind::NotFound, format!("{} resolved to no usable addresses", addr))),
+        [only] => connect_from(*only, source).await,
+        _ => race(addrs, source, CONNECTION_ATTEMPT_DELAY).await,
+    }
+}
+
+async fn connect_from(addr: SocketAddr, source: Option<IpAddr>) -> io::Result<TcpStream> {
+    let Some(source) = source else {
+        return TcpStream::connect(addr).await;
+    };
+    let socket = if addr.is_ipv4() { TcpSocket::new_v4()? } else { TcpSocket::new_v6()? };
+    socket.bind(SocketAddr::new(source, 0))?;
+    socket.connect(addr).await
+}
+
+async fn race(addrs: Vec<SocketAddr>, source: Option<IpAddr>, delay: Duration) -> io::Result<TcpStream> {
+    let mut remaining = addrs.into_iter();
+    let mut attempts = JoinSet::new();
+    let mut last_err = None;
+    loop {
+        if let Some(addr) = remaining.next() {
+            attempts.spawn(connect_from(addr, source));
+        } else if attempts.is_empty() {
+            return Err(last_err.unwrap_or_else(|| io::Error::other("no connection attempts made")));
+        }
+        let more = remaining.len() > 0;
+        tokio::select! {
+            Some(res) = attempts.join_next() => match res {
+                // Dropping the JoinSet aborts the attempts still in flight
+                Ok(Ok(stream)) => return Ok(stream),
+                Ok(Err(e)) => last_err = Some(e),
+                Err(e) => last_err = Some(io::Error::other(e)),
+            },
+            _ = tokio::time::sleep(delay), if more => {}
+        }
+    }
+}
+
+// Alternates families, starting with whichever the resolver listed first
+fn interleave(addrs: Vec<SocketAddr>) -> Vec<SocketAddr> {
+    let Some(first) = addrs.first() else {
+        return addrs;
+    };
+    let preferred_v6 = first.is_ipv6();
+    let len = addrs.len();
+    let (preferred, other): (Vec<_>, Vec<_>) = addrs.into_iter().partition(|a| a.is_ipv6() == preferred_v6);
+    let mut other = other.into_iter();
+    let mut out = Vec::with_capacity(len);
+    for addr in preferred {
+        out.push(addr);
+        out.extend(other.next());
+    }
+    out.extend(other);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    #[test]
+    fn test_interleave_families() {
+        let addrs: Vec<SocketAddr> = ["[::1]:80", "[::2]:80", "[::3]:80", "10.0.0.1:80", "10.0.0.2:80"]
+            .iter().map(|a| a.parse().unwrap()).collect();
+        let ordered: Vec<String> = interleave(addrs).iter().map(|a| a.to_string()).collect();
+        assert_eq!(ordered, ["[::1]:80", "10.0.0.1:80", "[::2]:80", "10.0.0.2:80", "[::3]:80"]);
+    }
+
+    #[tokio::test]
+    async fn test_race_skips_dead_address() {
+        // A port with nothing listening: refused straight away
+        let dead = TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let live = listener.local_addr().unwrap();
+
+        let start = std::time::Instant::now();
+        let stream = race(vec![dead, live], None, Duration::from_secs(5)).await.unwrap();
+        assert_eq!(stream.peer_addr().unwrap(), live);
+        // The failure moved on to the next address without waiting out the delay
+        assert!(start.elapsed() < Duration::from_secs(1));
+
+        assert!(race(vec![dead], None, Duration::from_millis(10)).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_connect_from_source() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+
+        let stream = connect(&addr, Some("127.0.0.1".parse().unwrap())).await.unwrap();
+        assert_eq!(stream.local_addr().unwrap().ip(), "127.0.0.1".parse::<IpAddr>().unwrap());
+        // No address of the source's family to try
+        assert!(connect(&addr, Some("::1".parse().unwrap())).await.is_err());
+    }
+}
@@ -0,0 +1,581 @@
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use socket2::{Socket, Domain, Type, Protocol};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+use arc_swap::ArcSwapOption;
+use log::{debug, info, warn};
+use crate::cluster::usage::UsageSync;
+use crate::config::{BandwidthLimitConfig, LBRule, RejectResponse};
+use crate::core::balancer::LoadBalancer;
+use crate::networking::acl::AccessControl;
+use crate::networking::capture::CaptureStore;
+use crate::networking::pool::BackendPool;
+use crate::networking::proxy::{self, BackendDial, ProxyConfig};
+use crate::networking::proxy_protocol;
+use crate::networking::sniff::{self, looks_like_http};
+use crate::networking::tls::ReloadableTls;
+use crate::traffic::limiter::{BandwidthManager, RateLimiter, SimpleLimiter};
+
+// Upstream proxies send the header immediately; don't let idle connections pin a task
+const PROXY_HEADER_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+// Upper bound on the time spent saying goodbye to a rate-limited client
+const REJECT_LINGER: std::time::Duration = std::time::Duration::from_secs(2);
+
+// Process-wide, so "[c<id>]" in the logs picks out one connection across all rules
+static NEXT_CONNECTION_ID: AtomicU64 = AtomicU64::new(1);
+
+const HTTP_TOO_MANY_REQUESTS: &[u8] = b"HTTP/1.1 429 Too Many Requests\r\nContent-Length: 0\r\nRetry-After: 1\r\nConnection: close\r\n\r\n";
+
+/// Acceptor tasks of one rule. Dropping it aborts them, which closes the
+/// listening sockets; connections already being proxied are left to finish.
+pub struct RuleListeners {
+    handles: Vec<JoinHandle<()>>,
+    _tls_watcher: Option<notify::RecommendedWatcher>, // Cert hot reload, stops with the rule
+}
+
+impl Drop for RuleListeners {
+    fn drop(&mut self) {
+        for handle in &self.handles {
+            handle.abort();
+        }
+    }
+}
+
+// Per-rule state shared by all acceptors of the rule
+struct RuleState {
+    rule_name: String,
+    lb: Arc<LoadBalancer>,
+    rate_limiter: Arc<RateLimiter>,
+    bandwidth: Arc<BandwidthManager>,
+    acl: Arc<AccessControl>,
+    reject_response: Option<RejectResponse>,
+    tls: Option<Arc<ReloadableTls>>,
+    dial: Arc<BackendDial>,
+    accept_proxy_protocol: bool,
+    protocol_sniff: bool,
+    nodelay: bool,
+    capture: Option<Arc<CaptureStore>>,
+    recv_buffer_bytes: Option<usize>,
+    send_buffer_bytes: Option<usize>,
+    idle_timeout: Option<std::time::Duration>,
+    backend_pool: Option<Arc<BackendPool>>,
+    max_connections: Option<usize>,
+    max_retries: u32,
+    copy_buffer_size: usize,
+    accept_error_backoff_max: std::time::Duration,
+    accept_throttle: Arc<AcceptThrottle>,
+    connections: AtomicUsize, // Open client connections across all acceptors
+}
+
+// A connection counted against the rule's `max_connections`, released on drop
+struct ConnectionSlot(Arc<RuleState>);
+
+impl ConnectionSlot {
+    fn acquire(state: &Arc<RuleState>) -> Option<Self> {
+        let open = state.connections.fetch_add(1, Ordering::AcqRel);
+        if state.max_connections.is_some_and(|max| open >= max) {
+            state.connections.fetch_sub(1, Ordering::AcqRel);
+            return None;
+        }
+        Some(Self(state.clone()))
+    }
+}
+
+impl Drop for ConnectionSlot {
+    fn drop(&mut self) {
+        self.0.connections.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+/// Binds the rule's listen address and spawns its acceptor tasks (or the UDP
+/// session proxy for `protocol: udp`).
+pub async fn spawn_rule_listeners(
+    rule: &LBRule,
+    lb: Arc<LoadBalancer>,
+    capture: Option<Arc<CaptureStore>>,
+    dial: Arc<BackendDial>,
+    usage_sync: Option<Arc<UsageSync>>,
+    accept_throttle: Arc<AcceptThrottle>,
+) -> anyhow::Result<RuleListeners> {
+    info!("Rule '{}' Bandwidth Config: {:?}", rule.name, rule.bandwidth_limit);
+
+    let mut rate_limiter = RateLimiter::new(rule.rate_limit.clone().unwrap_or_default()).with_metrics(&rule.name);
+    if let Some(sync) = usage_sync {
+        rate_limiter = rate_limiter.with_cluster(rule.name.clone(), sync);
+    }
+    let rate_limiter = Arc::new(rate_limiter);
+
+    let acl = Arc::new(AccessControl::for_rule(rule));
+
+    // One address, or every port of a "first-last" range sharing this rule's balancer and policies
+    let addrs = rule.listen_addrs().ok_or_else(|| anyhow::anyhow!("Invalid address: {}", rule.listen))?;
+    if let [first, .., last] = addrs.as_slice() {
+        info!("Rule '{}' listening on {} ports ({} to {})", rule.name, addrs.len(), first, last.port());
+    }
+
+    if rule.is_udp() {
+        let idle_timeout = std::time::Duration::from_millis(rule.udp_idle_timeout_ms.unwrap_or(crate::networking::udp::DEFAULT_UDP_IDLE_TIMEOUT_MS));
+        let max_sessions = rule.udp_max_sessions.unwrap_or(crate::networking::udp::DEFAULT_UDP_MAX_SESSIONS);
+        let mut handles = Vec::with_capacity(addrs.len());
+        for addr in addrs {
+            let socket = crate::networking::udp::bind(&rule.name, addr).await?;
+            if rule.recv_buffer_bytes.is_some() || rule.send_buffer_bytes.is_some() {
+                crate::networking::socket::set_buffer_sizes(socket2::SockRef::from(&socket), rule.recv_buffer_bytes, rule.send_buffer_bytes, &rule.listen);
+            }
+            let proxy = crate::networking::udp::UdpProxy::new(rule.name.clone(), socket, lb.clone(), rate_limiter.clone(), acl.clone(), idle_timeout, max_sessions);
+            handles.push(tokio::spawn(proxy.run()));
+        }
+        return Ok(RuleListeners { handles, _tls_watcher: None });
+    }
+
+    let bandwidth = Arc::new(BandwidthManager::new(rule.bandwidth_limit.clone().unwrap_or(BandwidthLimitConfig {
+        enabled: false,
+        client: None,
+        backend: None,
+        chunk_size: crate::traffic::bandwidth::DEFAULT_CHUNK_SIZE,
+        idle_ttl_ms: None,
+        rule_upload_per_sec: None,
+        rule_download_per_sec: None,
+    })).with_metrics(&rule.name));
+
+    // TLS Setup
+    let tls = match &rule.tls {
+        Some(tls_config) if tls_config.enabled => {
+            Some(Arc::new(ReloadableTls::load(rule.name.clone(), tls_config.clone())?))
+        }
+        _ => None,
+    };
+    // A failed watch only costs hot reload; the loaded cert keeps serving
+    let tls_watcher = tls.as_ref().and_then(|tls| match tls.watch() {
+        Ok(watcher) => Some(watcher),
+        Err(e) => {
+            warn!("Rule '{}': not watching TLS files for changes: {}", rule.name, e);
+            None
+        }
+    });
+
+    let backend_pool = rule.backend_pool.as_ref().map(|pool| BackendPool::new(rule.name.clone(), pool, dial.backend_bind_addr, dial.connect_timeout));
+
+    let state = Arc::new(RuleState {
+        rule_name: rule.name.clone(),
+        lb,
+        rate_limiter,
+        bandwidth,
+        acl,
+        reject_response: rule.rate_limit.as_ref().and_then(|rl| rl.reject_response),
+        tls,
+        dial,
+        accept_proxy_protocol: rule.accept_proxy_protocol,
+        protocol_sniff: rule.protocol_sniff,
+        nodelay: rule.nodelay.unwrap_or(true),
+        capture,
+        recv_buffer_bytes: rule.recv_buffer_bytes,
+        send_buffer_bytes: rule.send_buffer_bytes,
+        idle_timeout: rule.idle_timeout_ms.map(std::time::Duration::from_millis),
+        backend_pool,
+        max_connections: rule.max_connections,
+        max_retries: rule.max_retries.unwrap_or(0),
+        copy_buffer_size: rule.copy_buffer_size.unwrap_or(proxy::DEFAULT_COPY_BUFFER_SIZE),
+        accept_error_backoff_max: std::time::Duration::from_millis(rule.accept_error_backoff_max_ms.unwrap_or(DEFAULT_ACCEPT_ERROR_BACKOFF_MAX_MS)),
+        accept_throttle,
+        connections: AtomicUsize::new(0),
+    });
+
+    // Spawn multiple acceptors (one per core is good for high ops)
+    // The rule's `acceptors` wins, then NUM_ACCEPTORS, then available parallelism
+    // (or 4 if unknown). Without `acceptors`, a port range gets one acceptor per
+    // port instead: those are many ports with little traffic each.
+    let default_acceptors = std::thread::available_parallelism().map(|n| n.get()).unwrap_or(4);
+    let num_acceptors = match rule.acceptors {
+        Some(n) => n,
+        None if addrs.len() > 1 => 1,
+        None => std::env::var("NUM_ACCEPTORS")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(default_acceptors),
+    };
+
+    info!("Starting {} acceptors for rule: {}", num_acceptors * addrs.len(), rule.name);
+
+    let mut listeners = RuleListeners { handles: Vec::with_capacity(num_acceptors * addrs.len()), _tls_watcher: tls_watcher };
+    for &addr in &addrs {
+        // Without reuse_port, every acceptor takes turns on one shared socket
+        let shared = if rule.reuse_port { None } else { Some(Arc::new(bind_tcp(rule, addr)?)) };
+        for i in 0..num_acceptors {
+            // Each acceptor binds its own socket; SO_REUSEPORT lets the kernel spread connections
+            let listener = match &shared {
+                Some(listener) => listener.clone(),
+                None => Arc::new(bind_tcp(rule, addr)?),
+            };
+            if addrs.len() == 1 {
+                info!("Spawning acceptor {}/{} for rule '{}' on {}", i + 1, num_acceptors, rule.name, addr);
+            }
+            listeners.handles.push(tokio::spawn(accept_loop(listener, state.clone())));
+        }
+    }
+    Ok(listeners)
+}
+
+/// `accept_rate_per_sec` (rate, bucket). One instance is shared by the
+/// acceptors of every rule.
+#[derive(Default)]
+pub struct AcceptThrottle {
+    bucket: ArcSwapOption<(u32, SimpleLimiter)>,
+}
+
+impl AcceptThrottle {
+    /// Sets the accept rate (None: unlimited). An unchanged rate keeps the
+    /// current bucket, so a config reload doesn't refill it.
+    pub fn set_rate(&self, rate_per_sec: Option<u32>) {
+        if self.bucket.load().as_ref().map(|bucket| bucket.0) == rate_per_sec {
+            return;
+        }
+        self.bucket.store(rate_per_sec.map(|rate| Arc::new((rate, SimpleLimiter::new(rate, rate)))));
+    }
+
+    // Over the accept rate; checked before any per-connection work
+    fn accept_throttled(&self) -> bool {
+        self.bucket.load().as_ref().is_some_and(|bucket| bucket.1.check_n(1).is_err())
+    }
+}
+
+/// Listen backlog when a rule sets no `listen_backlog`
+pub const DEFAULT_LISTEN_BACKLOG: u32 = 1024;
+
+fn bind_tcp(rule: &LBRule, addr: SocketAddr) -> anyhow::Result<TcpListener> {
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+    if addr.is_ipv6() {
+        // "[::]" takes IPv6 only, whatever net.ipv6.bindv6only says, so a rule
+        // on "0.0.0.0" with the same port can coexist with it
+        socket.set_only_v6(true)?;
+    }
+
+    #[cfg(unix)]
+    {
+        if rule.reuse_port
+            && let Err(e) = socket.set_reuse_port(true) {
+                 warn!("Failed to set SO_REUSEPORT: {}", e);
+        }
+    }
+    socket.set_reuse_address(true)?;
+    let (eff_recv, eff_send) = crate::networking::socket::set_buffer_sizes((&socket).into(), rule.recv_buffer_bytes, rule.send_buffer_bytes, &rule.listen);
+    if rule.recv_buffer_bytes.is_some() || rule.send_buffer_bytes.is_some() {
+        // Kernel may clamp (rmem_max/wmem_max) or double the requested values
+        info!("Rule '{}' socket buffers: SO_RCVBUF={:?} (requested {:?}), SO_SNDBUF={:?} (requested {:?})",
+            rule.name, eff_recv, rule.recv_buffer_bytes, eff_send, rule.send_buffer_bytes);
+    }
+    socket.bind(&addr.into())?;
+    let backlog = rule.listen_backlog.unwrap_or(DEFAULT_LISTEN_BACKLOG);
+    if rule.listen_backlog.is_some() {
+        // The kernel silently caps it at net.core.somaxconn
+        info!("Rule '{}' listen backlog: {}", rule.name, backlog);
+    }
+    socket.listen(backlog as i32)?;
+
+    let std_listener: std::net::TcpListener = socket.into();
+    std_listener.set_nonblocking(true)?;
+    Ok(TcpListener::from_std(std_listener)?)
+}
+
+/// First pause after a failed accept; doubled per consecutive failure
+const ACCEPT_ERROR_BACKOFF_MIN: std::time::Duration = std::time::Duration::from_millis(5);
+/// Cap on that pause when a rule sets no `accept_error_backoff_max_ms`
+pub const DEFAULT_ACCEPT_ERROR_BACKOFF_MAX_MS: u64 = 1000;
+
+fn next_accept_backoff(current: Option<std::time::Duration>, max: std::time::Duration) -> std::time::Duration {
+    current.map_or(ACCEPT_ERROR_BACKOFF_MIN, |d| d * 2).min(max)
+}
+
+async fn accept_loop(listener: Arc<TcpListener>, state: Arc<RuleState>) {
+    let r_name = &state.rule_name;
+    let mut backoff = None;
+    loop {
+        match listener.accept().await {
+            Ok((stream, client_addr)) => {
+                backoff = None;
+                let id = NEXT_CONNECTION_ID.fetch_add(1, Ordering::Relaxed);
+                debug!("[{}] [c{}] Accepted connection from {}", r_name, id, client_addr);
+                if state.accept_throttle.accept_throttled() {
+                    // Shed before the connection costs a task, a TLS handshake or a limiter lookup
+                    debug!("[{}] [c{}] Connection from {} rejected: accept_rate_per_sec reached", r_name, id, client_addr);
+                    crate::metrics::REJECTED_CONNECTIONS.with_label_values(&[r_name, "accept_rate"]).inc();
+                    continue;
+                }
+                let Some(slot) = ConnectionSlot::acquire(&state) else {
+                    // Dropping the stream closes it before any per-connection work
+                    debug!("[{}] [c{}] Connection from {} rejected: max_connections reached", r_name, id, client_addr);
+                    crate::metrics::REJECTED_CONNECTIONS.with_label_values(&[r_name, "max_connections"]).inc();
+                    continue;
+                };
+                // Off: the socket keeps the kernel default (Nagle on)
+                if state.nodelay
+                    && let Err(e) = stream.set_nodelay(true) {
+                        warn!("Failed to set nodelay on client stream: {}", e);
+                }
+                crate::networking::socket::set_buffer_sizes(socket2::SockRef::from(&stream), state.recv_buffer_bytes, state.send_buffer_bytes, "client stream");
+                let local_addr = connection_local_addr(&stream, &listener);
+
+                if state.accept_proxy_protocol {
+                    // The real client is only known once the header arrives; admission runs in the task
+                    tokio::spawn(handle_proxied_connection(stream, id, client_addr, local_addr, state.clone(), slot));
+                    continue;
+                }
+
+                match admit(&state, id, client_addr) {
+                    Admission::Allowed => {
+                        tokio::spawn(handle_connection(stream, id, client_addr, local_addr, state.clone(), slot));
+                    }
+                    Admission::RateLimited => {
+                        if let Some(response) = state.reject_response {
+                            tokio::spawn(reject(stream, response, slot));
+                        }
+                    }
+                    Admission::Denied => {}
+                }
+            }
+            Err(e) => {
+                // Out of fds (EMFILE/ENFILE) the listener stays readable, so
+                // retrying at once would spin; back off until accepts succeed again
+                let delay = next_accept_backoff(backoff, state.accept_error_backoff_max);
+                backoff = Some(delay);
+                crate::throttled_error!(r_name, "[{}] Accept error: {}, retrying in {:?}", r_name, e, delay);
+                tokio::time::sleep(delay).await;
+            }
+        }
+    }
+}
+
+enum Admission {
+    Allowed,
+    Denied, // By the ACL: dropping the stream closes it
+    RateLimited,
+}
+
+// ACL then rate limit (in that order, so blocked IPs never consume tokens)
+fn admit(state: &RuleState, id: u64, client_addr: SocketAddr) -> Admission {
+    if !state.acl.is_allowed(client_addr.ip()) {
+        debug!("[{}] [c{}] Connection from {} denied by ACL", state.rule_name, id, client_addr);
+        crate::metrics::REJECTED_CONNECTIONS.with_label_values(&[&state.rule_name, "acl"]).inc();
+        crate::metrics::ERRORS.with_label_values(&[&state.rule_name, "acl_denied"]).inc();
+        return Admission::Denied;
+    }
+    if !state.rate_limiter.check(client_addr.ip()) {
+        debug!("[{}] [c{}] Connection from {} rate limited", state.rule_name, id, client_addr);
+        crate::metrics::REJECTED_CONNECTIONS.with_label_values(&[&state.rule_name, "rate_limit"]).inc();
+        crate::metrics::ERRORS.with_label_values(&[&state.rule_name, "rate_limited"]).inc();
+        return Admission::RateLimited;
+    }
+    Admission::Allowed
+}
+
+// Best-effort `rate_limit.reject_response`: a 429 for what looks like HTTP, then
+// an orderly close. Whatever the client still sends is drained so the close
+// isn't turned into a reset. Holds the connection slot, so `max_connections`
+// also bounds these.
+async fn reject(mut stream: TcpStream, response: RejectResponse, _slot: ConnectionSlot) {
+    let _ = tokio::time::timeout(REJECT_LINGER, async {
+        let mut buf = [0u8; 1024];
+        if response == RejectResponse::Http {
+            let n = stream.read(&mut buf).await?;
+            if looks_like_http(&buf[..n]) {
+                stream.write_all(HTTP_TOO_MANY_REQUESTS).await?;
+            }
+        }
+        stream.shutdown().await?;
+        while stream.read(&mut buf).await? > 0 {}
+        Ok::<(), std::io::Error>(())
+    }).await;
+}
+
+// Address the client actually connected to (the PROXY v2 destination). A
+// wildcard listener ("0.0.0.0", "[::]") accepts on every local IP, so only
+// the accepted socket knows which one; the listener's address is the fallback.
+fn connection_local_addr(stream: &TcpStream, listener: &TcpListener) -> SocketAddr {
+    stream.local_addr()
+        .or_else(|_| listener.local_addr())
+        .unwrap_or_else(|_| SocketAddr::from(([0, 0, 0, 0], 0)))
+}
+
+// Decodes the upstream PROXY header so ACL, rate limiting and the downstream
+// header all see the original client rather than the upstream proxy. The
+// downstream header also keeps the original destination, so a chain of
+// proxies hands the backend the addresses of the first hop.
+async fn handle_proxied_connection(mut stream: tokio::net::TcpStream, id: u64, peer_addr: SocketAddr, local_addr: SocketAddr, state: Arc<RuleState>, slot: ConnectionSlot) {
+    let (client_addr, original_dst) = match proxy_protocol::read_header(&mut stream, PROXY_HEADER_TIMEOUT).await {
+        // LOCAL / UNKNOWN: the upstream speaks for itself (e.g. its own health checks)
+        Ok(header) => (header.source.unwrap_or(peer_addr), header.destination),
+        Err(e) => {
+            debug!("[{}] [c{}] Dropping connection from {}: {}", state.rule_name, id, peer_addr, e);
+            crate::metrics::REJECTED_CONNECTIONS.with_label_values(&[&state.rule_name, "proxy_protocol"]).inc();
+            return;
+        }
+    };
+    if client_addr != peer_addr {
+        debug!("[{}] [c{}] PROXY header: client {} via {}", state.rule_name, id, client_addr, peer_addr);
+    }
+    match admit(&state, id, client_addr) {
+        Admission::Allowed => handle_connection(stream, id, client_addr, original_dst.unwrap_or(local_addr), state, slot).await,
+        Admission::RateLimited => {
+            if let Some(response) = state.reject_response {
+                reject(stream, response, slot).await;
+            }
+        }
+        Admission::Denied => {}
+    }
+}
+
+// `client_addr` is the effective client: the decoded PROXY header source if
+// there was one, else the TCP peer. `local_addr` is likewise the decoded
+// destination, else the address this connection was accepted on.
+async fn handle_connection(stream: tokio::net::TcpStream, id: u64, client_addr: SocketAddr, local_addr: SocketAddr, state: Arc<RuleState>, _slot: ConnectionSlot) {
+    let r_name = &state.rule_name;
+
+    // Select Backend
+    let Some(backend) = state.lb.next_backend_for(client_addr.ip()) else {
+        crate::throttled_error!(r_name, "[{}] [c{}] No available backends", r_name, id);
+        return;
+    };
+    debug!("[{}] [c{}] Selected backend {} for {}", r_name, id, backend.0, client_addr);
+
+    // Bandwidth Limiters
+    let bw = &state.bandwidth;
+    let proxy_config = ProxyConfig {
+        client_read_limiter: bw.get_client_upload_limiter(client_addr.ip()),
+        client_write_limiter: bw.get_client_download_limiter(client_addr.ip()),
+        rule_upload_limiter: bw.rule_upload_limiter(),
+        rule_download_limiter: bw.rule_download_limiter(),
+        bandwidth: bw.clone(),
+        bandwidth_chunk_size: bw.chunk_size(),
+        dial: state.dial.clone(),
+        client_addr,
+        local_addr,
+        connection_id: id,
+        capture: state.capture.clone(),
+        lb: state.lb.clone(),
+        idle_timeout: state.idle_timeout,
+        backend_pool: state.backend_pool.clone(),
+        max_retries: state.max_retries,
+        copy_buffer_size: state.copy_buffer_size,
+        // Terminated TLS is known to be TLS; anything else is guessed from the first client read
+        protocol_sniff: state.protocol_sniff && state.tls.is_none(),
+    };
+
+    if state.protocol_sniff && state.tls.is_some() {
+        sniff::record_protocol(r_name, id, "tls");
+    }
+
+    // Proxy errors are logged (throttled) inside proxy_connection
+    if let Some(tls) = &state.tls {
+        match tls.acceptor().accept(stream).await {
+            Ok(tls_stream) => {
+                let (version, cipher) = crate::networking::tls::negotiated(tls_stream.get_ref().1);
+                let (version, cipher) = (version.unwrap_or("unknown"), cipher.unwrap_or("unknown"));
+                debug!("[{}] [c{}] TLS handshake completed: {} {}", r_name, id, version, cipher);
+                crate::metrics::TLS_HANDSHAKES.with_label_values(&[r_name, version, cipher]).inc();
+                if let Err(reason) = tls.check_client_identity(tls_stream.get_ref().1) {
+                    crate::throttled_error!(r_name, "[{}] [c{}] Client {} rejected by client_cert_allow: {}", r_name, id, client_addr, reason);
+                    crate::metrics::REJECTED_CONNECTIONS.with_label_values(&[r_name, "client_cert_allow"]).inc();
+                    return;
+                }
+                let _ = proxy::proxy_connection(tls_stream, backend, proxy_config, r_name.clone()).await;
+            }
+            Err(e) if is_client_abort(&e) => {
+                // Scanners / probes hanging up mid-handshake: expected noise
+                debug!("[{}] [c{}] Client {} went away during TLS handshake: {}", r_name, id, client_addr, e);
+                crate::metrics::TLS_HANDSHAKE_ABORTED.with_label_values(&[r_name]).inc();
+            }
+            Err(e) if is_client_cert_rejected(&e) => {
+                // mTLS: missing or untrusted client cert, rejected before any proxying
+                crate::throttled_error!(r_name, "[{}] [c{}] Client {} failed certificate verification: {}", r_name, id, client_addr, e);
+                crate::metrics::TLS_CLIENT_CERT_REJECTED.with_label_values(&[r_name]).inc();
+                crate::metrics::ERRORS.with_label_values(&[r_name, "tls_handshake"]).inc();
+            }
+            Err(e) => {
+                crate::throttled_error!(r_name, "[{}] [c{}] TLS handshake error from {}: {}", r_name, id, client_addr, e);
+                crate::metrics::ERRORS.with_label_values(&[r_name, "tls_handshake"]).inc();
+            }
+        }
+    } else {
+        let _ = proxy::proxy_connection(stream, backend, proxy_config, r_name.clone()).await;
+    }
+}
+
+// Client closed/reset the connection before the TLS handshake completed, as opposed
+// to a genuine protocol failure (bad cert, version mismatch) surfaced as InvalidData.
+fn is_client_abort(e: &std::io::Error) -> bool {
+    matches!(
+        e.kind(),
+        std::io::ErrorKind::UnexpectedEof
+            | std::io::ErrorKind::ConnectionReset
+            | std::io::ErrorKind::ConnectionAborted
+            | std::io::ErrorKind::BrokenPipe
+    )
+}
+
+fn is_client_cert_rejected(e: &std::io::Error) -> bool {
+    matches!(
+        e.get_ref().and_then(|inner| inner.downcast_ref::<rustls::Error>()),
+        Some(rustls::Error::NoCertificatesPresented | rustls::Error::InvalidCertificate(_))
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_connection_local_addr_is_per_connection() {
+        let listener = TcpListener::bind("0.0.0.0:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (accepted, _client) = tokio::join!(listener.accept(), TcpStream::connect(("127.0.0.1", port)));
+        let (stream, _) = accepted.unwrap();
+        // The specific IP the client dialed, not the wildcard the rule listens on
+        assert_eq!(connection_local_addr(&stream, &listener), SocketAddr::from(([127, 0, 0, 1], port)));
+    }
+
+    #[test]
+    fn test_accept_backoff_doubles_up_to_max() {
+        let max = std::time::Duration::from_millis(30);
+        let mut backoff = None;
+        let delays: Vec<u64> = (0..5).map(|_| {
+            let delay = next_accept_backoff(backoff, max);
+            backoff = Some(delay);
+            delay.as_millis() as u64
+        }).collect();
+        assert_eq!(delays, [5, 10, 20, 30, 30]);
+    }
+
+    #[test]
+    fn test_accept_throttle() {
+        let throttle = AcceptThrottle::default();
+        assert!(!throttle.accept_throttled());
+
+        throttle.set_rate(Some(2));
+        assert!(!throttle.accept_throttled());
+        assert!(!throttle.accept_throttled());
+        assert!(throttle.accept_throttled());
+
+        // Same rate again: the drained bucket is kept
+        throttle.set_rate(Some(2));
+        assert!(throttle.accept_throttled());
+
+        throttle.set_rate(None);
+        assert!(!throttle.accept_throttled());
+    }
+
+    #[tokio::test]
+    async fn test_bind_tcp_ipv6() {
+        if std::net::TcpListener::bind("[::1]:0").is_err() {
+            return; // No IPv6 on this host
+        }
+        let rule: LBRule = serde_yaml::from_str(r#"{ name: "v6", listen: "[::1]:0", backends: ["[::1]:9001"] }"#).unwrap();
+        let listener = bind_tcp(&rule, "[::1]:0".parse().unwrap()).unwrap();
+        let addr = listener.local_addr().unwrap();
+        assert!(addr.is_ipv6());
+        assert!(TcpStream::connect(addr).await.is_ok());
+    }
+}
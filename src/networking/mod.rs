@@ -2,3 +2,13 @@ pub mod proxy;
 pub mod tls;
 pub mod proxy_protocol;
 pub mod acl;
+pub mod capture;
+pub mod sniff;
+pub mod connections;
+pub mod socket;
+pub mod happy_eyeballs;
+pub mod pool;
+#[cfg(target_os = "linux")]
+pub mod splice;
+pub mod udp;
+pub mod listener;
@@ -0,0 +1,187 @@
+use std::collections::VecDeque;
+use std::mem::MaybeUninit;
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use dashmap::DashMap;
+use log::debug;
+use socket2::SockRef;
+use tokio::net::TcpStream;
+use crate::config::BackendPoolConfig;
+use crate::networking::happy_eyeballs;
+
+/// Spares kept per backend when `backend_pool` sets no `max_idle`
+pub const DEFAULT_POOL_MAX_IDLE: usize = 4;
+/// How long a spare may sit unused when `backend_pool` sets no `idle_timeout_ms`
+pub const DEFAULT_POOL_IDLE_TIMEOUT_MS: u64 = 30_000;
+
+#[derive(Default)]
+struct Spares {
+    idle: VecDeque<(TcpStream, Instant)>, // Oldest first
+    pending: usize, // Connects in flight to top this backend up
+}
+
+/// Backend connections opened ahead of time, so a new client skips the connect
+/// round trip. A spare is handed out once and never returned: after a client
+/// used it, the stream's state belongs to that session. Taking one tops the
+/// backend's spares back up in the background; backends nobody connects to
+/// let theirs expire.
+pub struct BackendPool {
+    rule_name: String,
+    max_idle: usize,
+    idle_timeout: Duration,
+    source: Option<IpAddr>,
+    connect_timeout: Option<Duration>,
+    backends: DashMap<String, Spares>,
+}
+
+impl BackendPool {
+    pub fn new(rule_name: String, config: &BackendPoolConfig, source: Option<IpAddr>, connect_timeout: Option<Duration>) -> Arc<Self> {
+        let pool = Arc::new(Self {
+            rule_name,
+            max_idle: config.max_idle,
+            idle_timeout: Duration::from_millis(config.idle_timeout_ms),
+            source,
+            connect_timeout,
+            backends: DashMap::new(),
+        });
+        // Closes spares past idle_timeout; ends once the rule drops the pool
+        let weak = Arc::downgrade(&pool);
+        let interval = pool.idle_timeout / 2;
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                let Some(pool) = weak.upgrade() else { break };
+                pool.reap();
+            }
+        });
+        pool
+    }
+
+    /// A live spare connection to `addr`, if one is ready. Either way the
+    /// backend's spares are refilled towards `max_idle`.
+    pub fn take(self: &Arc<Self>, addr: &str) -> Option<TcpStream> {
+        let mut spares = self.backends.entry(addr.to_string()).or_default();
+        let mut found = None;
+        while let Some((stream, since)) = spares.idle.pop_front() {
+            // Closed by the backend (or its idle timeout) while waiting: skip it
+            if since.elapsed() < self.idle_timeout && is_alive(&stream) {
+                found = Some(stream);
+                break;
+            }
+        }
+        let missing = self.max_idle.saturating_sub(spares.idle.len() + spares.pending);
+        spares.pending += missing;
+        drop(spares);
+        for _ in 0..missing {
+            tokio::spawn(self.clone().refill(addr.to_string()));
+        }
+        let result = if found.is_some() { "hit" } else { "miss" };
+        crate::metrics::BACKEND_POOL.with_label_values(&[&self.rule_name, result]).inc();
+        found
+    }
+
+    async fn refill(self: Arc<Self>, addr: String) {
+        let connect = happy_eyeballs::connect(&addr, self.source);
+        let res = match self.connect_timeout {
+            Some(timeout) => tokio::time::timeout(timeout, connect).await
+                .unwrap_or_else(|_| Err(std::io::Error::new(std::io::ErrorKind::TimedOut, "connect timed out"))),
+            None => connect.await,
+        };
+        let mut spares = self.backends.entry(addr.clone()).or_default();
+        spares.pending -= 1;
+        match res {
+            Ok(stream) => spares.idle.push_back((stream, Instant::now())),
+            // Clients connecting meanwhile still go through the normal connect,
+            // which reports the failure to health tracking
+            Err(e) => debug!("[{}] Failed to open a spare connection to {}: {}", self.rule_name, addr, e),
+        }
+    }
+
+    fn reap(&self) {
+        self.backends.retain(|_, spares| {
+            spares.idle.retain(|(stream, since)| since.elapsed() < self.idle_timeout && is_alive(stream));
+            !spares.idle.is_empty() || spares.pending > 0
+        });
+    }
+}
+
+// A spare is unusable once the backend closed or reset it. Nothing is consumed:
+// a greeting the backend already sent stays queued for the client.
+fn is_alive(stream: &TcpStream) -> bool {
+    let sock = SockRef::from(stream);
+    if !matches!(sock.take_error(), Ok(None)) {
+        return false;
+    }
+    let mut buf = [MaybeUninit::<u8>::uninit(); 1];
+    match sock.peek(&mut buf) {
+        Ok(0) => false,
+        Ok(_) => true,
+        Err(e) => e.kind() == std::io::ErrorKind::WouldBlock,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    fn pool(max_idle: usize, idle_timeout_ms: u64) -> Arc<BackendPool> {
+        BackendPool::new("pool_test".to_string(), &BackendPoolConfig { max_idle, idle_timeout_ms }, None, None)
+    }
+
+    async fn wait_for_spares(pool: &BackendPool, addr: &str, count: usize) {
+        for _ in 0..100 {
+            if pool.backends.get(addr).is_some_and(|s| s.idle.len() == count && s.pending == 0) {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("spares for {} never reached {}", addr, count);
+    }
+
+    #[tokio::test]
+    async fn test_pool_hands_out_warm_connections() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let pool = pool(2, 30_000);
+
+        // Cold: nothing yet, but the first miss warms the backend up
+        assert!(pool.take(&addr).is_none());
+        wait_for_spares(&pool, &addr, 2).await;
+        let stream = pool.take(&addr).unwrap();
+        assert_eq!(stream.peer_addr().unwrap(), listener.local_addr().unwrap());
+        // Handed out for good; the pool tops itself back up
+        wait_for_spares(&pool, &addr, 2).await;
+    }
+
+    #[tokio::test]
+    async fn test_pool_skips_closed_spares() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let pool = pool(1, 30_000);
+        assert!(pool.take(&addr).is_none());
+        wait_for_spares(&pool, &addr, 1).await;
+
+        // The backend hangs up on the idle spare
+        let (accepted, _) = listener.accept().await.unwrap();
+        drop(accepted);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(pool.take(&addr).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_pool_expires_idle_spares() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let pool = pool(1, 50);
+        assert!(pool.take(&addr).is_none());
+        wait_for_spares(&pool, &addr, 1).await;
+
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        assert!(pool.backends.get(&addr).is_none());
+        drop(listener);
+    }
+}
@@ -1,42 +1,192 @@
 use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
 use tokio::net::TcpStream;
 use log::debug;
-use std::sync::Arc;
+use std::sync::{Arc, OnceLock};
 use crate::traffic::bandwidth::RateLimitedStream;
-use crate::traffic::limiter::RateLimiterType;
-use crate::config::BackendTlsConfig;
+use crate::traffic::limiter::{BandwidthManager, RateLimiterType};
+use crate::config::{BackendTlsConfig, LBRule};
+use crate::core::balancer::{ConnectionGuard, LoadBalancer};
+use crate::networking::capture::{CaptureStore, CapturingStream};
+use crate::networking::happy_eyeballs;
+use crate::networking::pool::BackendPool;
+use crate::networking::proxy_protocol::Tlv;
+use crate::networking::sniff::SniffingStream;
 use anyhow::Result;
 use tokio_rustls::TlsConnector;
 use rustls::pki_types::ServerName;
 use rustls::{ClientConfig, RootCertStore};
 use webpki_roots;
-use std::net::SocketAddr;
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+use socket2::SockRef;
+use crate::common::io::{copy_bidirectional, is_backend_closed, Activity, ActivityStream, BackendStream, ByteCounters, CountingStream};
 
 pub struct ProxyConfig {
     pub client_read_limiter: Option<Arc<RateLimiterType>>,
     pub client_write_limiter: Option<Arc<RateLimiterType>>,
-    pub backend_read_limiter: Option<Arc<RateLimiterType>>,
-    pub backend_write_limiter: Option<Arc<RateLimiterType>>,
-    pub backend_tls: Option<BackendTlsConfig>,
-    pub proxy_protocol: bool,
+    pub rule_upload_limiter: Option<Arc<RateLimiterType>>, // Aggregate caps shared by the whole rule
+    pub rule_download_limiter: Option<Arc<RateLimiterType>>,
+    pub bandwidth: Arc<BandwidthManager>, // Backend limiters are looked up once the backend is known
+    pub bandwidth_chunk_size: usize,
+    pub dial: Arc<BackendDial>,
     pub client_addr: SocketAddr,
-    pub local_addr: SocketAddr,
+    pub local_addr: SocketAddr, // Destination in the outbound PROXY header (original one when decoded upstream)
+    pub connection_id: u64, // "[c<id>]" in this connection's log lines
+    pub capture: Option<Arc<CaptureStore>>,
+    pub lb: Arc<LoadBalancer>, // Receives connect outcomes (passive health)
+    pub idle_timeout: Option<Duration>, // No bytes in either direction for this long closes both sides
+    pub backend_pool: Option<Arc<BackendPool>>, // Spare connections tried before connecting
+    pub max_retries: u32, // Other backends tried after a failed connect
+    pub copy_buffer_size: usize, // Per direction
+    pub protocol_sniff: bool, // Count the connection by the protocol of the client's first read
+}
+
+/// Relay buffer per direction when a rule sets no `copy_buffer_size`
+pub const DEFAULT_COPY_BUFFER_SIZE: usize = 64 * 1024;
+
+/// How a rule connects to its backends. Proxied connections and admin capture
+/// replays both go through it, so a replay reaches the backend the way a
+/// client's connection would.
+pub struct BackendDial {
+    pub backend_tls: Option<BackendTlsConfig>,
+    pub proxy_protocol: bool, // Rule default; backends may override it
+    pub backend_bind_addr: Option<IpAddr>, // Source address of the backend socket
+    pub connect_timeout: Option<Duration>,
+    pub nodelay: bool, // Rule default for TCP_NODELAY on the backend socket; backends may override it
+    pub recv_buffer_bytes: Option<usize>,
+    pub send_buffer_bytes: Option<usize>,
+}
+
+impl BackendDial {
+    pub fn for_rule(rule: &LBRule) -> Self {
+        BackendDial {
+            backend_tls: rule.backend_tls.clone(),
+            proxy_protocol: rule.proxy_protocol,
+            backend_bind_addr: rule.backend_bind_addr.as_deref().and_then(|addr| addr.parse().ok()),
+            connect_timeout: rule.connect_timeout_ms.map(Duration::from_millis),
+            nodelay: rule.nodelay.unwrap_or(true),
+            recv_buffer_bytes: rule.recv_buffer_bytes,
+            send_buffer_bytes: rule.send_buffer_bytes,
+        }
+    }
+
+    async fn connect(&self, backend_addr: &str, rule_name: &str) -> std::io::Result<TcpStream> {
+        connect_backend(backend_addr, self.backend_bind_addr, self.connect_timeout, rule_name).await
+    }
+
+    // Socket options and, if this backend wants one, the PROXY header: all a
+    // fresh backend connection needs before the first payload byte
+    async fn prepare(&self, stream: &mut TcpStream, lb: &LoadBalancer, backend_addr: &str, header: impl FnOnce() -> Vec<u8>, log_prefix: &str) -> std::io::Result<()> {
+        let nodelay = lb.backend_nodelay(backend_addr).unwrap_or(self.nodelay);
+        if let Err(e) = stream.set_nodelay(nodelay) {
+            debug!("{} Failed to set nodelay on backend stream: {}", log_prefix, e);
+        }
+        crate::networking::socket::set_buffer_sizes(SockRef::from(&*stream), self.recv_buffer_bytes, self.send_buffer_bytes, backend_addr);
+
+        if lb.backend_proxy_protocol(backend_addr).unwrap_or(self.proxy_protocol) {
+            stream.write_all(&header()).await?;
+            debug!("{} Sent Proxy Protocol v2 header to {}", log_prefix, backend_addr);
+        }
+        Ok(())
+    }
+
+    fn tls(&self) -> Option<&BackendTlsConfig> {
+        self.backend_tls.as_ref().filter(|tls| tls.enabled)
+    }
+}
+
+/// A backend connection opened by `open_backend`: plain TCP or TLS
+pub trait BackendIo: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> BackendIo for T {}
+
+/// A backend connection set up like a proxied one (bind address, connect
+/// timeout, socket options, PROXY header, TLS), for capture replays. Unlike a
+/// proxied connect, the outcome is not reported to health tracking.
+pub async fn open_backend(dial: &BackendDial, lb: &LoadBalancer, backend_addr: &str, client_addr: SocketAddr, local_addr: SocketAddr, rule_name: &str) -> Result<Box<dyn BackendIo>> {
+    let mut stream = dial.connect(backend_addr, rule_name).await?;
+    let header = || crate::networking::proxy_protocol::create_v2_header(client_addr, local_addr, &[]);
+    dial.prepare(&mut stream, lb, backend_addr, header, &format!("[{}]", rule_name)).await?;
+    match dial.tls() {
+        Some(tls_cfg) => Ok(Box::new(connect_tls(tls_cfg, backend_addr, stream).await?)),
+        None => Ok(Box::new(stream)),
+    }
+}
+
+/// Client side of a proxied connection. Plain TCP clients expose their socket
+/// so the relay can splice(2) on Linux.
+pub trait ClientStream: AsyncRead + AsyncWrite + Unpin + Send + 'static {
+    #[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+    fn as_tcp(&self) -> Option<&TcpStream> {
+        None
+    }
+
+    // PROXY v2 TLVs describing the client connection (TLS details when terminated)
+    fn proxy_tlvs(&self) -> Vec<Tlv> {
+        Vec::new()
+    }
+}
+
+impl ClientStream for TcpStream {
+    fn as_tcp(&self) -> Option<&TcpStream> {
+        Some(self)
+    }
+}
+
+impl ClientStream for tokio_rustls::server::TlsStream<TcpStream> {
+    fn proxy_tlvs(&self) -> Vec<Tlv> {
+        crate::networking::tls::session_tlvs(self.get_ref().1)
+    }
+}
+
+// Sniffing relies on reads passing through it, so the relay only splices when it is off
+impl<S: ClientStream> ClientStream for SniffingStream<S> {
+    fn as_tcp(&self) -> Option<&TcpStream> {
+        self.get_ref().as_tcp()
+    }
+
+    fn proxy_tlvs(&self) -> Vec<Tlv> {
+        self.get_ref().proxy_tlvs()
+    }
 }
 
 pub async fn proxy_connection<I>(
     client_stream: I,
-    backend_addr: String,
+    backend: (String, ConnectionGuard),
     config: ProxyConfig,
     rule_name: String, // Added rule_name for metrics
 ) -> Result<()>
 where
-    I: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    I: ClientStream,
+{
+    let (mut backend_addr, guard) = backend;
+    let id = config.connection_id;
+    // backend_addr ends up as the backend actually used (or the last one tried)
+    let res = proxy_connection_inner(client_stream, &mut backend_addr, guard, config, &rule_name).await;
+    if let Err(e) = &res {
+        // Throttled per rule/backend so a backend outage doesn't flood the logs
+        let key = format!("{}/{}", rule_name, backend_addr);
+        crate::throttled_error!(key, "[{}] [c{}] Proxy error (backend {}): {}", rule_name, id, backend_addr, e);
+    }
+    res
+}
+
+async fn proxy_connection_inner<I>(
+    client_stream: I,
+    backend_addr: &mut String,
+    guard: ConnectionGuard,
+    config: ProxyConfig,
+    rule_name: &str,
+) -> Result<()>
+where
+    I: ClientStream,
 {
     let start_time = std::time::Instant::now();
+    let id = config.connection_id;
     
     // Metrics: Increment Active & Total
-    crate::metrics::ACTIVE_CONNECTIONS.with_label_values(&[&rule_name]).inc();
-    crate::metrics::TOTAL_CONNECTIONS.with_label_values(&[&rule_name]).inc();
+    crate::metrics::ACTIVE_CONNECTIONS.with_label_values(&[rule_name]).inc();
+    crate::metrics::TOTAL_CONNECTIONS.with_label_values(&[rule_name]).inc();
 
     // Guard to decrement active connections on drop (ensure it runs even on error)
     struct ConnectionMetricGuard {
@@ -49,76 +199,238 @@ where
         }
     }
     
-    let _metric_guard = ConnectionMetricGuard { rule_name: rule_name.clone() };
+    let _metric_guard = ConnectionMetricGuard { rule_name: rule_name.to_string() };
+    // protocol_sniff: counted on the client's first read, or as `none` if the connection ends first
+    let client_stream = SniffingStream::new(client_stream, config.protocol_sniff, rule_name, id);
 
-    // Connect to backend (TCP)
-    let mut backend_stream = TcpStream::connect(&backend_addr).await?;
-    if let Err(e) = backend_stream.set_nodelay(true) {
-        debug!("Failed to set nodelay on backend stream: {}", e);
-    }
+    // Connect to backend (TCP). A failed connect moves on to another backend,
+    // never one already tried; nothing was sent yet, so the client can't tell.
+    let mut _guard = guard;
+    let mut failed: Vec<String> = Vec::new();
+    let mut backend_stream = loop {
+        if let Some(stream) = config.backend_pool.as_ref().and_then(|pool| pool.take(backend_addr)) {
+            debug!("[{}] [c{}] Using a pooled connection to {}", rule_name, id, backend_addr);
+            break stream;
+        }
+        match config.dial.connect(backend_addr, rule_name).await {
+            Ok(stream) => {
+                config.lb.report_backend_success(backend_addr);
+                break stream;
+            }
+            Err(e) => {
+                config.lb.report_backend_failure(backend_addr);
+                crate::metrics::ERRORS.with_label_values(&[rule_name, "backend_connect"]).inc();
+                if failed.len() as u32 >= config.max_retries {
+                    return Err(e.into());
+                }
+                failed.push(backend_addr.clone());
+                drop(_guard); // Release the failed backend's connection slot before picking another
+                let Some((next_addr, next_guard)) = config.lb.next_backend_excluding(config.client_addr.ip(), &failed) else {
+                    return Err(e.into());
+                };
+                debug!("[{}] [c{}] Connect to {} failed ({}), retrying with {}", rule_name, id, backend_addr, e, next_addr);
+                crate::metrics::BACKEND_RETRIES.with_label_values(&[rule_name]).inc();
+                *backend_addr = next_addr;
+                _guard = next_guard;
+            }
+        }
+    };
+    let backend_addr: &str = backend_addr;
+    // Listed in GET /api/connections until this function returns
+    let registration = crate::networking::connections::register(id, rule_name, config.client_addr, backend_addr);
+    let counters = registration.bytes().clone();
 
-    // Send Proxy Protocol Header if enabled
-    if config.proxy_protocol {
-        let header = crate::networking::proxy_protocol::create_v2_header(config.client_addr, config.local_addr);
-        backend_stream.write_all(&header).await?;
-        debug!("Sent Proxy Protocol v2 header to {}", backend_addr);
-    }
+    // Keyed by the backend actually connected (after any retries), so every
+    // connection to it shares the backend bandwidth budget
+    let backend_read_limiter = config.bandwidth.get_backend_download_limiter(backend_addr.to_string());
+    let backend_write_limiter = config.bandwidth.get_backend_upload_limiter(backend_addr.to_string());
+
+    // Everything past the connect; failures here are I/O errors on an established connection
+    let relay_fut = async move {
+        // Socket options, then the Proxy Protocol header if enabled (for this backend)
+        let tlvs = client_stream.proxy_tlvs();
+        let header = || crate::networking::proxy_protocol::create_v2_header(config.client_addr, config.local_addr, &tlvs);
+        config.dial.prepare(&mut backend_stream, &config.lb, backend_addr, header, &format!("[{}] [c{}]", rule_name, id)).await?;
+
+        // Sampled debug capture of the client's first bytes (passthrough otherwise)
+        let capture = config.capture.filter(|store| store.should_sample());
+
+        // Plain TCP on both sides with nothing to inspect or throttle: splice in the kernel
+        #[cfg(target_os = "linux")]
+        if let Some(client_tcp) = client_stream.as_tcp()
+            && capture.is_none()
+            && !config.protocol_sniff
+            && config.dial.tls().is_none()
+            && config.client_read_limiter.is_none() && config.client_write_limiter.is_none()
+            && config.rule_upload_limiter.is_none() && config.rule_download_limiter.is_none()
+            && backend_read_limiter.is_none() && backend_write_limiter.is_none() {
+            let (c2b, b2c) = splice_relay(client_tcp, &backend_stream, config.copy_buffer_size, config.idle_timeout, &counters, rule_name).await?;
+            record_traffic(rule_name, &config.lb, backend_addr, c2b, b2c, start_time);
+            debug!("[{}] [c{}] Connection closed (spliced) after {:?}. Client sent: {} bytes, Backend sent: {} bytes", rule_name, id, start_time.elapsed(), c2b, b2c);
+            return Ok(());
+        }
+
+        let client_stream = CapturingStream::new(client_stream, capture, config.client_addr, config.local_addr, backend_addr);
+        let client_stream = CountingStream::new(client_stream, counters);
+        // Rule-wide caps; the per-IP limiters below are applied on top
+        let client_stream = RateLimitedStream::new(client_stream, config.rule_upload_limiter, config.rule_download_limiter, config.bandwidth_chunk_size);
     
-    // ... TLS handling logic ... (simplified for brevity match structure in original)
-    // We need to match the original structure. I'll paste the full updated function body.
+        // ... TLS handling logic ... (simplified for brevity match structure in original)
+        // We need to match the original structure. I'll paste the full updated function body.
     
-    // Handle Backend TLS if enabled
-    if let Some(tls_cfg) = config.backend_tls {
-        if tls_cfg.enabled {
-             // ... TLS logic ...
-             // Replicating internal logic for TLS path to include metrics at end
-             debug!("Starting TLS handshake with backend {}", backend_addr);
+        // Handle Backend TLS if enabled
+        if let Some(tls_cfg) = config.dial.tls() {
+                 // ... TLS logic ...
+                 // Replicating internal logic for TLS path to include metrics at end
+                 debug!("[{}] [c{}] Starting TLS handshake with backend {}", rule_name, id, backend_addr);
              
-             let mut root_store = RootCertStore::empty();
-             root_store.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
-             let mut client_config = ClientConfig::builder()
-                .with_root_certificates(root_store)
-                .with_no_client_auth();
-             if tls_cfg.ignore_verify {
-                client_config.dangerous().set_certificate_verifier(Arc::new(NoVerify));
-             }
-             let connector = TlsConnector::from(Arc::new(client_config));
-             let domain = ServerName::try_from("localhost").unwrap().to_owned(); 
-             let tls_stream = connector.connect(domain, backend_stream).await?;
-
-             let mut backend_stream_limited = RateLimitedStream::new(tls_stream, config.backend_read_limiter, config.backend_write_limiter);
-             let mut client_stream_limited = RateLimitedStream::new(client_stream, config.client_read_limiter, config.client_write_limiter);
-
-             let (c2b, b2c) = tokio::io::copy_bidirectional(&mut client_stream_limited, &mut backend_stream_limited).await?;
-
-             // Record Traffic & Duration
-             crate::metrics::TRAFFIC_BYTES.with_label_values(&[&rule_name, "client_in"]).inc_by(c2b);
-             crate::metrics::TRAFFIC_BYTES.with_label_values(&[&rule_name, "backend_out"]).inc_by(c2b); // sent to backend
-             crate::metrics::TRAFFIC_BYTES.with_label_values(&[&rule_name, "backend_in"]).inc_by(b2c);
-             crate::metrics::TRAFFIC_BYTES.with_label_values(&[&rule_name, "client_out"]).inc_by(b2c); // sent to client
-             crate::metrics::CONNECTION_DURATION.with_label_values(&[&rule_name]).observe(start_time.elapsed().as_secs_f64());
-
-             debug!("TLS Connection closed. Client sent: {} bytes, Backend sent: {} bytes", c2b, b2c);
-             return Ok(());
+                 let tls_stream = connect_tls(tls_cfg, backend_addr, backend_stream).await?;
+
+                 let backend_stream_limited = RateLimitedStream::new(BackendStream::new(tls_stream), backend_read_limiter, backend_write_limiter, config.bandwidth_chunk_size);
+                 let client_stream_limited = RateLimitedStream::new(client_stream, config.client_read_limiter, config.client_write_limiter, config.bandwidth_chunk_size);
+
+                 let (c2b, b2c) = relay(client_stream_limited, backend_stream_limited, config.copy_buffer_size, config.idle_timeout, rule_name).await?;
+
+                 record_traffic(rule_name, &config.lb, backend_addr, c2b, b2c, start_time);
+
+                 debug!("[{}] [c{}] TLS Connection closed after {:?}. Client sent: {} bytes, Backend sent: {} bytes", rule_name, id, start_time.elapsed(), c2b, b2c);
+                 return Ok(());
         }
-    }
     
-    // Plain TCP
-    let mut backend_stream_limited = RateLimitedStream::new(backend_stream, config.backend_read_limiter, config.backend_write_limiter);
-    let mut client_stream_limited = RateLimitedStream::new(client_stream, config.client_read_limiter, config.client_write_limiter);
+        // Plain TCP
+        let backend_stream_limited = RateLimitedStream::new(BackendStream::new(backend_stream), backend_read_limiter, backend_write_limiter, config.bandwidth_chunk_size);
+        let client_stream_limited = RateLimitedStream::new(client_stream, config.client_read_limiter, config.client_write_limiter, config.bandwidth_chunk_size);
 
-    let (c2b, b2c) = tokio::io::copy_bidirectional(&mut client_stream_limited, &mut backend_stream_limited).await?;
+        let (c2b, b2c) = relay(client_stream_limited, backend_stream_limited, config.copy_buffer_size, config.idle_timeout, rule_name).await?;
     
-    // Record Traffic & Duration
-    crate::metrics::TRAFFIC_BYTES.with_label_values(&[&rule_name, "client_in"]).inc_by(c2b);
-    crate::metrics::TRAFFIC_BYTES.with_label_values(&[&rule_name, "backend_out"]).inc_by(c2b);
-    crate::metrics::TRAFFIC_BYTES.with_label_values(&[&rule_name, "backend_in"]).inc_by(b2c);
-    crate::metrics::TRAFFIC_BYTES.with_label_values(&[&rule_name, "client_out"]).inc_by(b2c);
-    crate::metrics::CONNECTION_DURATION.with_label_values(&[&rule_name]).observe(start_time.elapsed().as_secs_f64());
+        record_traffic(rule_name, &config.lb, backend_addr, c2b, b2c, start_time);
+
+        debug!("[{}] [c{}] Connection closed after {:?}. Client sent: {} bytes, Backend sent: {} bytes", rule_name, id, start_time.elapsed(), c2b, b2c);
+
+        Ok(())
+    };
+    let res: Result<()> = tokio::select! {
+        res = relay_fut => res,
+        // Backend removed by a reload and its grace period is over
+        _ = _guard.retired() => {
+            debug!("[{}] [c{}] Closing connection to removed backend {}", rule_name, id, backend_addr);
+            Ok(())
+        }
+    };
+    match res {
+        // The backend hanging up mid-stream is its call, not a proxy fault
+        Err(e) if e.downcast_ref::<std::io::Error>().is_some_and(is_backend_closed) => {
+            debug!("[{}] [c{}] Backend {} closed the connection mid-stream after {:?}: {}", rule_name, id, backend_addr, start_time.elapsed(), e);
+            crate::metrics::ERRORS.with_label_values(&[rule_name, "backend_eof"]).inc();
+            Ok(())
+        }
+        Err(e) => {
+            crate::metrics::ERRORS.with_label_values(&[rule_name, "proxy_io"]).inc();
+            Err(e)
+        }
+        Ok(()) => Ok(()),
+    }
+}
 
-    debug!("Connection closed. Client sent: {} bytes, Backend sent: {} bytes", c2b, b2c);
+// Traffic & duration of a finished connection (c2b: client -> backend bytes)
+fn record_traffic(rule_name: &str, lb: &LoadBalancer, backend_addr: &str, c2b: u64, b2c: u64, start_time: std::time::Instant) {
+    crate::metrics::TRAFFIC_BYTES.with_label_values(&[rule_name, "client_in"]).inc_by(c2b);
+    crate::metrics::TRAFFIC_BYTES.with_label_values(&[rule_name, "backend_out"]).inc_by(c2b); // sent to backend
+    crate::metrics::TRAFFIC_BYTES.with_label_values(&[rule_name, "backend_in"]).inc_by(b2c);
+    crate::metrics::TRAFFIC_BYTES.with_label_values(&[rule_name, "client_out"]).inc_by(b2c); // sent to client
+    let duration = start_time.elapsed();
+    crate::metrics::CONNECTION_DURATION.with_label_values(&[rule_name]).observe(duration.as_secs_f64());
+    lb.report_connection_duration(backend_addr, duration);
+}
 
-    Ok(())
+async fn connect_backend(backend_addr: &str, source: Option<IpAddr>, connect_timeout: Option<Duration>, rule_name: &str) -> std::io::Result<TcpStream> {
+    let Some(timeout) = connect_timeout else {
+        return happy_eyeballs::connect(backend_addr, source).await;
+    };
+    match tokio::time::timeout(timeout, happy_eyeballs::connect(backend_addr, source)).await {
+        Ok(res) => res,
+        Err(_) => {
+            crate::metrics::TIMEOUTS.with_label_values(&[rule_name, "connect"]).inc();
+            Err(std::io::Error::new(std::io::ErrorKind::TimedOut, format!("connect timed out after {:?}", timeout)))
+        }
+    }
+}
+
+// relay() for two plain sockets, without the bytes ever leaving the kernel
+#[cfg(target_os = "linux")]
+async fn splice_relay(client: &TcpStream, backend: &TcpStream, pipe_size: usize, idle_timeout: Option<Duration>, counters: &ByteCounters, rule_name: &str) -> std::io::Result<(u64, u64)> {
+    let Some(idle_timeout) = idle_timeout else {
+        return crate::networking::splice::splice_bidirectional(client, backend, pipe_size, None, counters).await;
+    };
+
+    let activity = Activity::new();
+    tokio::select! {
+        res = crate::networking::splice::splice_bidirectional(client, backend, pipe_size, Some(&*activity), counters) => res,
+        _ = activity.idle_expired(idle_timeout) => {
+            crate::metrics::TIMEOUTS.with_label_values(&[rule_name, "idle"]).inc();
+            Err(std::io::Error::new(std::io::ErrorKind::TimedOut, format!("idle for {:?}", idle_timeout)))
+        }
+    }
+}
+
+// copy_bidirectional with `buffer_size` buffers (half-closes passed on per
+// direction), but gives up once neither side has moved a byte for `idle_timeout`;
+// both streams are dropped (closed) on return
+async fn relay<C, B>(client: C, backend: B, buffer_size: usize, idle_timeout: Option<Duration>, rule_name: &str) -> std::io::Result<(u64, u64)>
+where
+    C: AsyncRead + AsyncWrite + Unpin,
+    B: AsyncRead + AsyncWrite + Unpin,
+{
+    let Some(idle_timeout) = idle_timeout else {
+        return copy_bidirectional(client, backend, buffer_size).await;
+    };
+
+    let activity = Activity::new();
+    let client = ActivityStream::new(client, activity.clone());
+    let backend = ActivityStream::new(backend, activity.clone());
+    tokio::select! {
+        res = copy_bidirectional(client, backend, buffer_size) => res,
+        _ = activity.idle_expired(idle_timeout) => {
+            crate::metrics::TIMEOUTS.with_label_values(&[rule_name, "idle"]).inc();
+            Err(std::io::Error::new(std::io::ErrorKind::TimedOut, format!("idle for {:?}", idle_timeout)))
+        }
+    }
+}
+
+/// Client side of TLS to a backend (proxied traffic and HTTPS health checks),
+/// verifying against the webpki roots unless `ignore_verify`. Built once per mode.
+pub fn backend_connector(ignore_verify: bool) -> TlsConnector {
+    static VERIFIED: OnceLock<TlsConnector> = OnceLock::new();
+    static UNVERIFIED: OnceLock<TlsConnector> = OnceLock::new();
+    let cell = if ignore_verify { &UNVERIFIED } else { &VERIFIED };
+    cell.get_or_init(|| {
+        let mut root_store = RootCertStore::empty();
+        root_store.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+        let mut client_config = ClientConfig::builder()
+            .with_root_certificates(root_store)
+            .with_no_client_auth();
+        if ignore_verify {
+            client_config.dangerous().set_certificate_verifier(Arc::new(NoVerify));
+        }
+        TlsConnector::from(Arc::new(client_config))
+    }).clone()
+}
+
+// TLS handshake with a backend per the rule's `backend_tls`
+async fn connect_tls(tls_cfg: &BackendTlsConfig, backend_addr: &str, stream: TcpStream) -> Result<tokio_rustls::client::TlsStream<TcpStream>> {
+    let domain = backend_server_name(tls_cfg.server_name.as_deref(), backend_addr)?;
+    Ok(backend_connector(tls_cfg.ignore_verify).connect(domain, stream).await?)
+}
+
+/// SNI / cert verification name: explicit `server_name`, else the host part of "host:port"
+pub fn backend_server_name(server_name: Option<&str>, backend_addr: &str) -> Result<ServerName<'static>> {
+    let host = match server_name {
+        Some(name) => name,
+        None => backend_addr.rsplit_once(':').map_or(backend_addr, |(host, _)| host),
+    };
+    let host = host.trim_start_matches('[').trim_end_matches(']'); // [::1]:443
+    ServerName::try_from(host.to_string())
+        .map_err(|e| anyhow::anyhow!("Invalid backend TLS server name '{}': {}", host, e))
 }
 
 #[derive(Debug)]
@@ -172,3 +484,66 @@ impl rustls::client::danger::ServerCertVerifier for NoVerify {
         ]
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_relay_idle_timeout() {
+        let (client, mut client_peer) = tokio::io::duplex(64);
+        let (backend, mut backend_peer) = tokio::io::duplex(64);
+        let relay = tokio::spawn(relay(client, backend, DEFAULT_COPY_BUFFER_SIZE, Some(Duration::from_millis(50)), "test"));
+
+        // Traffic keeps it alive past the timeout
+        for _ in 0..3 {
+            tokio::time::sleep(Duration::from_millis(30)).await;
+            client_peer.write_all(b"x").await.unwrap();
+            let mut buf = [0u8; 1];
+            tokio::io::AsyncReadExt::read_exact(&mut backend_peer, &mut buf).await.unwrap();
+        }
+        assert!(!relay.is_finished());
+        let err = relay.await.unwrap().unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::TimedOut);
+    }
+
+    #[tokio::test]
+    async fn test_open_backend_sends_proxy_header() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let backend_addr = listener.local_addr().unwrap().to_string();
+        let lb = LoadBalancer::new("open_backend_test".to_string(), vec![crate::config::BackendConfig::Simple(backend_addr.clone())], None, Default::default());
+        let dial = BackendDial {
+            backend_tls: None,
+            proxy_protocol: true,
+            backend_bind_addr: None,
+            connect_timeout: Some(Duration::from_secs(1)),
+            nodelay: true,
+            recv_buffer_bytes: None,
+            send_buffer_bytes: None,
+        };
+        let client_addr: SocketAddr = "10.0.0.1:5000".parse().unwrap();
+        let local_addr: SocketAddr = "10.0.0.2:443".parse().unwrap();
+
+        let mut stream = open_backend(&dial, &lb, &backend_addr, client_addr, local_addr, "open_backend_test").await.unwrap();
+        stream.write_all(b"ping").await.unwrap();
+        drop(stream);
+
+        // The replayed bytes follow the same PROXY header a proxied client gets
+        let (mut accepted, _) = listener.accept().await.unwrap();
+        let mut received = Vec::new();
+        tokio::io::AsyncReadExt::read_to_end(&mut accepted, &mut received).await.unwrap();
+        let mut expected = crate::networking::proxy_protocol::create_v2_header(client_addr, local_addr, &[]);
+        expected.extend_from_slice(b"ping");
+        assert_eq!(received, expected);
+    }
+
+    #[test]
+    fn test_backend_server_name() {
+        assert_eq!(backend_server_name(None, "api.internal:443").unwrap(), ServerName::try_from("api.internal").unwrap());
+        assert_eq!(backend_server_name(Some("api.example.com"), "10.0.0.5:443").unwrap(), ServerName::try_from("api.example.com").unwrap());
+        // IP backends verify against IP SANs
+        assert!(matches!(backend_server_name(None, "10.0.0.5:443").unwrap(), ServerName::IpAddress(_)));
+        assert!(matches!(backend_server_name(None, "[::1]:443").unwrap(), ServerName::IpAddress(_)));
+        assert!(backend_server_name(Some("bad name!"), "10.0.0.5:443").is_err());
+    }
+}
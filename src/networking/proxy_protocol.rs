@@ -1,12 +1,67 @@
-use std::net::SocketAddr;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
 use bytes::{BufMut, BytesMut};
+use tokio::io::AsyncReadExt;
+use tokio::net::TcpStream;
+use crate::common::error::{LbError, Result};
 
 // Proxy Protocol V2 signature
 const V2_SIG: [u8; 12] = [
     0x0D, 0x0A, 0x0D, 0x0A, 0x00, 0x0D, 0x0A, 0x51, 0x55, 0x49, 0x54, 0x0A,
 ];
 
-pub fn create_v2_header(src_addr: SocketAddr, dst_addr: SocketAddr) -> Vec<u8> {
+// v2 TLV types (PROXY protocol spec, section 2.2)
+pub const PP2_TYPE_ALPN: u8 = 0x01;
+pub const PP2_TYPE_AUTHORITY: u8 = 0x02; // Host name the client asked for (TLS SNI)
+const PP2_TYPE_SSL: u8 = 0x20;
+const PP2_SUBTYPE_SSL_VERSION: u8 = 0x21;
+const PP2_SUBTYPE_SSL_CN: u8 = 0x22;
+const PP2_SUBTYPE_SSL_CIPHER: u8 = 0x23;
+
+// PP2_TYPE_SSL `client` flags
+const PP2_CLIENT_SSL: u8 = 0x01;
+const PP2_CLIENT_CERT_CONN: u8 = 0x02;
+const PP2_CLIENT_CERT_SESS: u8 = 0x04;
+
+/// v2 type-length-value extension, sent after the address block
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tlv {
+    pub kind: u8,
+    pub value: Vec<u8>,
+}
+
+impl Tlv {
+    pub fn new(kind: u8, value: impl Into<Vec<u8>>) -> Self {
+        Tlv { kind, value: value.into() }
+    }
+
+    /// PP2_TYPE_SSL for a TLS client connection. `verify` is always 0 (success):
+    /// a client certificate that fails verification never gets past the handshake.
+    pub fn ssl(client_cert: bool, version: Option<&str>, cipher: Option<&str>, cn: Option<&str>) -> Self {
+        let mut client = PP2_CLIENT_SSL;
+        if client_cert {
+            client |= PP2_CLIENT_CERT_CONN | PP2_CLIENT_CERT_SESS;
+        }
+        let mut value = BytesMut::new();
+        value.put_u8(client);
+        value.put_u32(0);
+        for (kind, sub) in [(PP2_SUBTYPE_SSL_VERSION, version), (PP2_SUBTYPE_SSL_CIPHER, cipher), (PP2_SUBTYPE_SSL_CN, cn)] {
+            if let Some(sub) = sub {
+                put_tlv(&mut value, kind, sub.as_bytes());
+            }
+        }
+        Tlv::new(PP2_TYPE_SSL, value.to_vec())
+    }
+}
+
+fn put_tlv(buf: &mut BytesMut, kind: u8, value: &[u8]) {
+    buf.put_u8(kind);
+    buf.put_u16(value.len() as u16);
+    buf.put_slice(value);
+}
+
+/// v2 PROXY header for `src_addr` -> `dst_addr`, followed by `tlvs`
+pub fn create_v2_header(src_addr: SocketAddr, dst_addr: SocketAddr, tlvs: &[Tlv]) -> Vec<u8> {
     let mut buf = BytesMut::with_capacity(128);
 
     // 1. Signature
@@ -15,24 +70,16 @@ pub fn create_v2_header(src_addr: SocketAddr, dst_addr: SocketAddr) -> Vec<u8> {
     // 2. Version (2) | Command (PROXY = 1) -> 0x21
     buf.put_u8(0x21);
 
+    // Bytes after the address block, counted in the length field
+    let tlv_len: usize = tlvs.iter().map(|tlv| 3 + tlv.value.len()).sum();
+
     // 3. Address Family & Transport Protocol
     match (src_addr, dst_addr) {
         (SocketAddr::V4(src), SocketAddr::V4(dst)) => {
             // AF_INET (1) | STREAM (1) -> 0x11
             buf.put_u8(0x11);
-            // Length: 4 (src IP) + 4 (dst IP) + 2 (src port) + 2 (dst port) = 12 bytes
-            buf.put_u16(12);
-            
-            buf.put_slice(&src.ip().octets());
-            buf.put_slice(&dst.ip().octets());
-            buf.put_u16(src.port());
-            buf.put_u16(dst.port());
-        }
-        (SocketAddr::V6(src), SocketAddr::V6(dst)) => {
-            // AF_INET6 (2) | STREAM (1) -> 0x21
-            buf.put_u8(0x21);
-            // Length: 16 (src IP) + 16 (dst IP) + 2 (src port) + 2 (dst port) = 36 bytes
-            buf.put_u16(36);
+            // Length: 4 (src IP) + 4 (dst IP) + 2 (src port) + 2 (dst port) = 12 bytes, plus TLVs
+            buf.put_u16((12 + tlv_len) as u16);
             
             buf.put_slice(&src.ip().octets());
             buf.put_slice(&dst.ip().octets());
@@ -40,17 +87,177 @@ pub fn create_v2_header(src_addr: SocketAddr, dst_addr: SocketAddr) -> Vec<u8> {
             buf.put_u16(dst.port());
         }
         _ => {
-            // Mismatched families or UNIX socket (not supported here) -> Send "Unspec" (0x00)
-            // Version 2 | Local (0) / Unspec (0) -> 0x20
-            buf.put_u8(0x20); // LOCAL command
-            buf.put_u8(0x00); // UNSPEC family / UNSPEC proto
-            buf.put_u16(0);   // Length 0
+            // AF_INET6 (2) | STREAM (1) -> 0x21. Mixed families (an IPv6 client relayed
+            // through an IPv4 listener, or the reverse) are sent as IPv4-mapped IPv6.
+            buf.put_u8(0x21);
+            // Length: 16 (src IP) + 16 (dst IP) + 2 (src port) + 2 (dst port) = 36 bytes, plus TLVs
+            buf.put_u16((36 + tlv_len) as u16);
+
+            buf.put_slice(&to_ipv6(src_addr.ip()).octets());
+            buf.put_slice(&to_ipv6(dst_addr.ip()).octets());
+            buf.put_u16(src_addr.port());
+            buf.put_u16(dst_addr.port());
         }
     }
 
+    // 4. TLVs
+    for tlv in tlvs {
+        put_tlv(&mut buf, tlv.kind, &tlv.value);
+    }
+
     buf.to_vec()
 }
 
+fn to_ipv6(ip: IpAddr) -> Ipv6Addr {
+    match ip {
+        IpAddr::V4(v4) => v4.to_ipv6_mapped(),
+        IpAddr::V6(v6) => v6,
+    }
+}
+
+// Longest possible v1 line: "PROXY TCP6 <ffff:...> <ffff:...> 65535 65535\r\n"
+const V1_MAX_LEN: usize = 107;
+const V1_PREFIX: &[u8] = b"PROXY ";
+
+/// Addresses carried by an inbound PROXY header. Both are `None` for LOCAL
+/// (health checks from the upstream proxy) and UNKNOWN/unsupported families.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProxyHeader {
+    pub source: Option<SocketAddr>,
+    pub destination: Option<SocketAddr>,
+}
+
+fn invalid(msg: impl Into<String>) -> LbError {
+    LbError::ProxyProtocol(msg.into())
+}
+
+/// Parses a v1 or v2 header at the start of `buf`. Returns the header and its
+/// length in bytes, or `None` if more bytes are needed to decide.
+pub fn parse_header(buf: &[u8]) -> Result<Option<(ProxyHeader, usize)>> {
+    match buf.first() {
+        None => Ok(None),
+        Some(b'P') => parse_v1(buf),
+        Some(0x0D) => parse_v2(buf),
+        Some(_) => Err(invalid("missing PROXY protocol header")),
+    }
+}
+
+fn parse_v1(buf: &[u8]) -> Result<Option<(ProxyHeader, usize)>> {
+    let prefix_len = buf.len().min(V1_PREFIX.len());
+    if buf[..prefix_len] != V1_PREFIX[..prefix_len] {
+        return Err(invalid("bad v1 signature"));
+    }
+    let window = &buf[..buf.len().min(V1_MAX_LEN)];
+    let Some(end) = window.windows(2).position(|w| w == b"\r\n") else {
+        if buf.len() >= V1_MAX_LEN {
+            return Err(invalid("v1 header exceeds 107 bytes"));
+        }
+        return Ok(None);
+    };
+
+    let line = std::str::from_utf8(&buf[..end]).map_err(|_| invalid("v1 header is not ASCII"))?;
+    let parts: Vec<&str> = line.split(' ').collect();
+    let header = match parts.as_slice() {
+        ["PROXY", "UNKNOWN", ..] => ProxyHeader { source: None, destination: None },
+        ["PROXY", proto @ ("TCP4" | "TCP6"), src, dst, sport, dport] => {
+            let ip = |s: &str| -> Result<IpAddr> {
+                let ip: IpAddr = s.parse().map_err(|_| invalid(format!("bad v1 address '{}'", s)))?;
+                if ip.is_ipv4() != (*proto == "TCP4") {
+                    return Err(invalid(format!("v1 address '{}' does not match {}", s, proto)));
+                }
+                Ok(ip)
+            };
+            let port = |s: &str| -> Result<u16> { s.parse().map_err(|_| invalid(format!("bad v1 port '{}'", s))) };
+            ProxyHeader {
+                source: Some(SocketAddr::new(ip(src)?, port(sport)?)),
+                destination: Some(SocketAddr::new(ip(dst)?, port(dport)?)),
+            }
+        }
+        _ => return Err(invalid(format!("malformed v1 header '{}'", line))),
+    };
+    Ok(Some((header, end + 2)))
+}
+
+fn parse_v2(buf: &[u8]) -> Result<Option<(ProxyHeader, usize)>> {
+    let sig_len = buf.len().min(V2_SIG.len());
+    if buf[..sig_len] != V2_SIG[..sig_len] {
+        return Err(invalid("bad v2 signature"));
+    }
+    if buf.len() < 16 {
+        return Ok(None);
+    }
+
+    let (version, command) = (buf[12] >> 4, buf[12] & 0x0F);
+    if version != 2 {
+        return Err(invalid(format!("unsupported version {}", version)));
+    }
+    let len = u16::from_be_bytes([buf[14], buf[15]]) as usize;
+    let total = 16 + len;
+    if buf.len() < total {
+        return Ok(None);
+    }
+    let body = &buf[16..total];
+
+    let header = match (command, buf[13] >> 4) {
+        (0x0, _) => ProxyHeader { source: None, destination: None }, // LOCAL
+        (0x1, 0x1) => {
+            if body.len() < 12 {
+                return Err(invalid("short v2 IPv4 address block"));
+            }
+            let src = Ipv4Addr::new(body[0], body[1], body[2], body[3]);
+            let dst = Ipv4Addr::new(body[4], body[5], body[6], body[7]);
+            ProxyHeader {
+                source: Some(SocketAddr::new(src.into(), u16::from_be_bytes([body[8], body[9]]))),
+                destination: Some(SocketAddr::new(dst.into(), u16::from_be_bytes([body[10], body[11]]))),
+            }
+        }
+        (0x1, 0x2) => {
+            if body.len() < 36 {
+                return Err(invalid("short v2 IPv6 address block"));
+            }
+            let src: [u8; 16] = body[0..16].try_into().unwrap();
+            let dst: [u8; 16] = body[16..32].try_into().unwrap();
+            ProxyHeader {
+                source: Some(SocketAddr::new(Ipv6Addr::from(src).into(), u16::from_be_bytes([body[32], body[33]]))),
+                destination: Some(SocketAddr::new(Ipv6Addr::from(dst).into(), u16::from_be_bytes([body[34], body[35]]))),
+            }
+        }
+        (0x1, _) => ProxyHeader { source: None, destination: None }, // UNSPEC / UNIX
+        (cmd, _) => return Err(invalid(format!("unsupported v2 command {}", cmd))),
+    };
+    Ok(Some((header, total)))
+}
+
+/// Reads and consumes the PROXY header at the start of `stream`, leaving the
+/// application bytes that follow it unread.
+pub async fn read_header(stream: &mut TcpStream, timeout: Duration) -> Result<ProxyHeader> {
+    let read = async {
+        let mut header = Vec::new();
+        let mut peeked = [0u8; 512];
+        loop {
+            let n = stream.peek(&mut peeked).await?;
+            if n == 0 {
+                return Err(invalid("connection closed before PROXY header"));
+            }
+            let start = header.len();
+            header.extend_from_slice(&peeked[..n]);
+            match parse_header(&header)? {
+                Some((parsed, len)) => {
+                    // Consume the header only; the rest is application data
+                    stream.read_exact(&mut header[start..len]).await?;
+                    return Ok(parsed);
+                }
+                None => {
+                    // Everything peeked belongs to the (still incomplete) header
+                    stream.read_exact(&mut header[start..]).await?;
+                }
+            }
+        }
+    };
+    tokio::time::timeout(timeout, read).await
+        .map_err(|_| invalid("timed out waiting for PROXY header"))?
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -61,7 +268,7 @@ mod tests {
         let src = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(192, 168, 1, 1)), 12345);
         let dst = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)), 80);
 
-        let header = create_v2_header(src, dst);
+        let header = create_v2_header(src, dst, &[]);
 
         // Sig (12) + Ver/Cmd (1) + Fam/Proto (1) + Len (2) + Addrs (12) = 28 bytes
         assert_eq!(header.len(), 28);
@@ -80,4 +287,96 @@ mod tests {
         // Dst Port (80 = 0x0050)
         assert_eq!(&header[26..28], &[0x00, 0x50]);
     }
+
+    #[test]
+    fn test_parse_v2_roundtrip() {
+        let src: SocketAddr = "192.168.1.1:12345".parse().unwrap();
+        let dst: SocketAddr = "10.0.0.1:80".parse().unwrap();
+        let mut buf = create_v2_header(src, dst, &[]);
+        let header_len = buf.len();
+        buf.extend_from_slice(b"GET / HTTP/1.1\r\n");
+
+        assert_eq!(parse_header(&buf[..10]).unwrap(), None);
+        assert_eq!(parse_header(&buf[..20]).unwrap(), None);
+        let (header, len) = parse_header(&buf).unwrap().unwrap();
+        assert_eq!(len, header_len);
+        assert_eq!(header.source, Some(src));
+        assert_eq!(header.destination, Some(dst));
+
+        let src6: SocketAddr = "[2001:db8::1]:4000".parse().unwrap();
+        let dst6: SocketAddr = "[2001:db8::2]:443".parse().unwrap();
+        let (header, _) = parse_header(&create_v2_header(src6, dst6, &[])).unwrap().unwrap();
+        assert_eq!(header.source, Some(src6));
+
+        // IPv6 client decoded upstream, relayed through an IPv4 listener
+        let (header, _) = parse_header(&create_v2_header(src6, dst, &[])).unwrap().unwrap();
+        assert_eq!(header.source, Some(src6));
+        assert_eq!(header.destination, Some("[::ffff:10.0.0.1]:80".parse().unwrap()));
+    }
+
+    // (type, value) pairs of a TLV block
+    fn parse_tlvs(mut buf: &[u8]) -> Vec<(u8, Vec<u8>)> {
+        let mut out = Vec::new();
+        while buf.len() >= 3 {
+            let len = u16::from_be_bytes([buf[1], buf[2]]) as usize;
+            out.push((buf[0], buf[3..3 + len].to_vec()));
+            buf = &buf[3 + len..];
+        }
+        assert!(buf.is_empty(), "trailing bytes after TLVs");
+        out
+    }
+
+    #[test]
+    fn test_v2_tlvs_roundtrip() {
+        let src: SocketAddr = "192.168.1.1:12345".parse().unwrap();
+        let dst: SocketAddr = "10.0.0.1:443".parse().unwrap();
+        let tlvs = [
+            Tlv::new(PP2_TYPE_AUTHORITY, "api.example.com"),
+            Tlv::new(PP2_TYPE_ALPN, "h2"),
+            Tlv::ssl(true, Some("TLSv1.3"), Some("TLS13_AES_128_GCM_SHA256"), Some("client-1")),
+        ];
+        let mut buf = create_v2_header(src, dst, &tlvs);
+        let header_len = buf.len();
+        buf.extend_from_slice(b"payload");
+
+        // The length field covers the TLVs, so parsing stops right before the payload
+        let (header, len) = parse_header(&buf).unwrap().unwrap();
+        assert_eq!(len, header_len);
+        assert_eq!(header.source, Some(src));
+        assert_eq!(u16::from_be_bytes([buf[14], buf[15]]) as usize, header_len - 16);
+
+        let parsed = parse_tlvs(&buf[28..header_len]);
+        assert_eq!(parsed[0], (PP2_TYPE_AUTHORITY, b"api.example.com".to_vec()));
+        assert_eq!(parsed[1], (PP2_TYPE_ALPN, b"h2".to_vec()));
+        let (kind, ssl) = &parsed[2];
+        assert_eq!(*kind, PP2_TYPE_SSL);
+        assert_eq!(ssl[0], PP2_CLIENT_SSL | PP2_CLIENT_CERT_CONN | PP2_CLIENT_CERT_SESS);
+        assert_eq!(&ssl[1..5], &[0, 0, 0, 0]); // verify: success
+        assert_eq!(parse_tlvs(&ssl[5..]), vec![
+            (PP2_SUBTYPE_SSL_VERSION, b"TLSv1.3".to_vec()),
+            (PP2_SUBTYPE_SSL_CIPHER, b"TLS13_AES_128_GCM_SHA256".to_vec()),
+            (PP2_SUBTYPE_SSL_CN, b"client-1".to_vec()),
+        ]);
+    }
+
+    #[test]
+    fn test_parse_v1() {
+        let buf = b"PROXY TCP4 203.0.113.7 10.0.0.1 56324 443\r\nhello";
+        assert_eq!(parse_header(&buf[..12]).unwrap(), None);
+        let (header, len) = parse_header(buf).unwrap().unwrap();
+        assert_eq!(&buf[len..], b"hello");
+        assert_eq!(header.source, Some("203.0.113.7:56324".parse().unwrap()));
+
+        let (header, len) = parse_header(b"PROXY UNKNOWN\r\n").unwrap().unwrap();
+        assert_eq!((header.source, len), (None, 15));
+    }
+
+    #[test]
+    fn test_parse_rejects_garbage() {
+        assert!(parse_header(b"GET / HTTP/1.1\r\n").is_err());
+        assert!(parse_header(b"PROXY TCP4 1.2.3.4 5.6.7.8 x 80\r\n").is_err());
+        assert!(parse_header(b"PROXY TCP6 1.2.3.4 5.6.7.8 1 80\r\n").is_err());
+        assert!(parse_header(&[b'P'; 120]).is_err());
+        assert!(parse_header(&[0x0D, 0x0A, 0x0D, 0x0A, 0x00, 0x0D, 0x0A, 0x51, 0x55, 0x49, 0x54, 0x0A, 0x11, 0x11, 0, 0]).is_err());
+    }
 }
@@ -0,0 +1,150 @@
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use log::debug;
+
+// protocol_sniff: bytes of the client's first read looked at
+const SNIFF_BYTES: usize = 16;
+
+// Request line of HTTP/1.x ("GET /path HTTP/1.1")
+pub fn looks_like_http(data: &[u8]) -> bool {
+    const METHODS: [&[u8]; 9] = [b"GET ", b"POST ", b"PUT ", b"HEAD ", b"DELETE ", b"OPTIONS ", b"PATCH ", b"CONNECT ", b"TRACE "];
+    METHODS.iter().any(|method| data.starts_with(method))
+}
+
+// Coarse protocol of a connection from its first bytes, for metrics only
+fn sniff_protocol(data: &[u8]) -> &'static str {
+    match data {
+        [] => "none", // Client closed (or was closed) without sending anything
+        [0x16, 0x03, ..] => "tls",
+        _ if data.starts_with(b"PRI * HTTP/2") => "http2",
+        _ if data.starts_with(b"CONNECT ") => "http_connect",
+        _ if looks_like_http(data) => "http",
+        _ if data.starts_with(b"SSH-") => "ssh",
+        [b'*', b'0'..=b'9', ..] => "redis",
+        _ => "unknown",
+    }
+}
+
+/// Counts the connection under `proto` in `l4lb_connections_by_protocol_total`
+pub fn record_protocol(rule_name: &str, id: u64, proto: &str) {
+    debug!("[{}] [c{}] Sniffed protocol: {}", rule_name, id, proto);
+    crate::metrics::CONNECTIONS_BY_PROTOCOL.with_label_values(&[rule_name, proto]).inc();
+}
+
+/// Passthrough stream that guesses the protocol from the client's first read,
+/// as the relay does it, so the connection never waits on the sniff. A stream
+/// dropped before any read (or after EOF) counts as `none`.
+pub struct SniffingStream<S> {
+    inner: S,
+    rule_name: Option<String>, // Taken once the protocol is recorded
+    id: u64,
+}
+
+impl<S> SniffingStream<S> {
+    pub fn new(inner: S, enabled: bool, rule_name: &str, id: u64) -> Self {
+        SniffingStream { inner, rule_name: enabled.then(|| rule_name.to_string()), id }
+    }
+
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    fn record(&mut self, data: &[u8]) {
+        if let Some(rule_name) = self.rule_name.take() {
+            record_protocol(&rule_name, self.id, sniff_protocol(&data[..data.len().min(SNIFF_BYTES)]));
+        }
+    }
+}
+
+impl<S> Drop for SniffingStream<S> {
+    fn drop(&mut self) {
+        self.record(&[]);
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for SniffingStream<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let this = self.get_mut();
+        let before = buf.filled().len();
+        let res = Pin::new(&mut this.inner).poll_read(cx, buf);
+
+        if this.rule_name.is_some() && matches!(res, Poll::Ready(Ok(()))) {
+            let read = buf.filled()[before..].to_vec();
+            this.record(&read);
+        }
+        res
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for SniffingStream<S> {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<std::io::Result<usize>> {
+        Pin::new(&mut self.get_mut().inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[test]
+    fn test_sniff_protocol() {
+        assert_eq!(sniff_protocol(b"\x16\x03\x01\x02\x00\x01"), "tls");
+        assert_eq!(sniff_protocol(b"GET /chat HTTP/1.1"), "http");
+        assert_eq!(sniff_protocol(b"CONNECT db:5432 HT"), "http_connect");
+        assert_eq!(sniff_protocol(b"PRI * HTTP/2.0\r\n"), "http2");
+        assert_eq!(sniff_protocol(b"SSH-2.0-OpenSSH_9"), "ssh");
+        assert_eq!(sniff_protocol(b"*1\r\n$4\r\nPING"), "redis");
+        assert_eq!(sniff_protocol(b"\x00\x00\x00\x08"), "unknown");
+        assert_eq!(sniff_protocol(b""), "none");
+    }
+
+    #[test]
+    fn test_looks_like_http() {
+        assert!(looks_like_http(b"GET / HTTP/1.1\r\nHost: x\r\n\r\n"));
+        assert!(looks_like_http(b"POST /api HTTP/1.1\r\n"));
+        assert!(!looks_like_http(b"\x16\x03\x01\x02\x00")); // TLS ClientHello
+        assert!(!looks_like_http(b"GETX"));
+        assert!(!looks_like_http(b""));
+    }
+
+    #[tokio::test]
+    async fn test_sniffing_stream_records_first_read_once() {
+        let counter = |proto: &str| crate::metrics::CONNECTIONS_BY_PROTOCOL.with_label_values(&["sniff_test", proto]).get();
+        let (mut client, server) = tokio::io::duplex(64);
+        let mut stream = SniffingStream::new(server, true, "sniff_test", 1);
+
+        client.write_all(b"SSH-2.0-OpenSSH_9.6\r\n").await.unwrap();
+        let mut buf = [0u8; 64];
+        assert_eq!(stream.read(&mut buf).await.unwrap(), 21);
+        assert_eq!(counter("ssh"), 1);
+
+        // Later reads and the drop don't count it again
+        client.write_all(b"GET / HTTP/1.1\r\n").await.unwrap();
+        stream.read(&mut buf).await.unwrap();
+        drop(stream);
+        assert_eq!(counter("ssh"), 1);
+        assert_eq!(counter("http"), 0);
+        assert_eq!(counter("none"), 0);
+
+        // Gone before sending anything
+        drop(SniffingStream::new(tokio::io::duplex(64).1, true, "sniff_test", 2));
+        assert_eq!(counter("none"), 1);
+        // Disabled: nothing recorded
+        drop(SniffingStream::new(tokio::io::duplex(64).1, false, "sniff_test", 3));
+        assert_eq!(counter("none"), 1);
+    }
+}
@@ -0,0 +1,26 @@
+use socket2::SockRef;
+use log::debug;
+
+// Sanity bounds for per-rule SO_RCVBUF/SO_SNDBUF. The kernel clamps to
+// net.core.rmem_max/wmem_max (and Linux doubles the requested value).
+pub const MIN_SOCKET_BUFFER: usize = 4096;
+pub const MAX_SOCKET_BUFFER: usize = 256 * 1024 * 1024;
+
+/// Applies optional SO_RCVBUF/SO_SNDBUF sizes and returns the effective (kernel-reported) values.
+pub fn set_buffer_sizes(sock: SockRef<'_>, recv: Option<usize>, send: Option<usize>, context: &str) -> (Option<usize>, Option<usize>) {
+    if let Some(size) = recv
+        && let Err(e) = sock.set_recv_buffer_size(size) {
+            debug!("Failed to set SO_RCVBUF={} on {}: {}", size, context, e);
+    }
+    if let Some(size) = send
+        && let Err(e) = sock.set_send_buffer_size(size) {
+            debug!("Failed to set SO_SNDBUF={} on {}: {}", size, context, e);
+    }
+
+    let effective_recv = recv.and_then(|_| sock.recv_buffer_size().ok());
+    let effective_send = send.and_then(|_| sock.send_buffer_size().ok());
+    if recv.is_some() || send.is_some() {
+        debug!("Socket buffers on {}: SO_RCVBUF={:?} SO_SNDBUF={:?}", context, effective_recv, effective_send);
+    }
+    (effective_recv, effective_send)
+}
@@ -0,0 +1,138 @@
+use std::io;
+use std::net::Shutdown;
+use std::os::fd::{AsRawFd, RawFd};
+use tokio::io::Interest;
+use tokio::net::TcpStream;
+use socket2::SockRef;
+use std::sync::atomic::{AtomicU64, Ordering};
+use crate::common::io::{from_backend, Activity, ByteCounters};
+
+// Kernel pipe used as the in-kernel buffer between two sockets
+struct Pipe {
+    read: RawFd,
+    write: RawFd,
+}
+
+impl Pipe {
+    fn new(size: usize) -> io::Result<Self> {
+        let mut fds = [0 as RawFd; 2];
+        if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_NONBLOCK | libc::O_CLOEXEC) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let pipe = Pipe { read: fds[0], write: fds[1] };
+        // Best effort: the default (64KB) still works if the kernel refuses
+        unsafe { libc::fcntl(pipe.write, libc::F_SETPIPE_SZ, size as libc::c_int) };
+        Ok(pipe)
+    }
+}
+
+impl Drop for Pipe {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.read);
+            libc::close(self.write);
+        }
+    }
+}
+
+fn splice(from: RawFd, to: RawFd, len: usize) -> io::Result<usize> {
+    let n = unsafe {
+        libc::splice(from, std::ptr::null_mut(), to, std::ptr::null_mut(), len, libc::SPLICE_F_MOVE | libc::SPLICE_F_NONBLOCK)
+    };
+    if n < 0 { Err(io::Error::last_os_error()) } else { Ok(n as usize) }
+}
+
+/// copy_bidirectional for two plain TCP sockets, moving bytes through kernel
+/// pipes with splice(2) so they never reach userspace. Returns the bytes moved
+/// (client -> backend, backend -> client) once both directions have hit EOF.
+/// Errors on the backend socket are tagged like `BackendStream` does; bytes
+/// are added to `counters` as they go.
+pub async fn splice_bidirectional(client: &TcpStream, backend: &TcpStream, pipe_size: usize, activity: Option<&Activity>, counters: &ByteCounters) -> io::Result<(u64, u64)> {
+    let to_backend = Pipe::new(pipe_size)?;
+    let to_client = Pipe::new(pipe_size)?;
+    tokio::try_join!(
+        splice_one_way(client, backend, &to_backend, pipe_size, activity, &counters.client_to_backend, true),
+        splice_one_way(backend, client, &to_client, pipe_size, activity, &counters.backend_to_client, false),
+    )
+}
+
+async fn splice_one_way(from: &TcpStream, to: &TcpStream, pipe: &Pipe, chunk: usize, activity: Option<&Activity>, counter: &AtomicU64, to_backend: bool) -> io::Result<u64> {
+    let from_err = |e: io::Error| if to_backend { e } else { from_backend(e) };
+    let to_err = |e: io::Error| if to_backend { from_backend(e) } else { e };
+    let mut total = 0u64;
+    loop {
+        // Socket -> pipe; the pipe is always drained below, so only the socket can block
+        let n = loop {
+            from.readable().await.map_err(from_err)?;
+            match from.try_io(Interest::READABLE, || splice(from.as_raw_fd(), pipe.write, chunk)) {
+                Ok(n) => break n,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => continue,
+                Err(e) => return Err(from_err(e)),
+            }
+        };
+        if n == 0 {
+            // EOF: pass the half-close on, like copy_bidirectional does
+            let _ = SockRef::from(to).shutdown(Shutdown::Write);
+            return Ok(total);
+        }
+
+        // Pipe -> socket
+        let mut pending = n;
+        while pending > 0 {
+            to.writable().await.map_err(to_err)?;
+            match to.try_io(Interest::WRITABLE, || splice(pipe.read, to.as_raw_fd(), pending)) {
+                Ok(m) => pending -= m,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => continue,
+                Err(e) => return Err(to_err(e)),
+            }
+        }
+        total += n as u64;
+        counter.fetch_add(n as u64, Ordering::Relaxed);
+        if let Some(activity) = activity {
+            activity.touch();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    async fn tcp_pair() -> (TcpStream, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let connect = TcpStream::connect(listener.local_addr().unwrap());
+        let (accepted, connected) = tokio::join!(listener.accept(), connect);
+        (accepted.unwrap().0, connected.unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_splice_relays_both_ways() {
+        let (mut client, client_side) = tcp_pair().await;
+        let (backend_side, mut backend) = tcp_pair().await;
+        let relay = tokio::spawn(async move {
+            splice_bidirectional(&client_side, &backend_side, 64 * 1024, None, &ByteCounters::default()).await
+        });
+
+        let payload: Vec<u8> = (0..300_000u32).map(|i| i as u8).collect();
+        let expected = payload.clone();
+        let writer = tokio::spawn(async move {
+            client.write_all(&payload).await.unwrap();
+            client.shutdown().await.unwrap();
+            client
+        });
+        let mut received = Vec::new();
+        backend.read_to_end(&mut received).await.unwrap();
+        assert_eq!(received, expected);
+
+        backend.write_all(b"done").await.unwrap();
+        backend.shutdown().await.unwrap();
+        let mut client = writer.await.unwrap();
+        let mut reply = Vec::new();
+        client.read_to_end(&mut reply).await.unwrap();
+        assert_eq!(reply, b"done");
+
+        assert_eq!(relay.await.unwrap().unwrap(), (300_000, 4));
+    }
+}
@@ -1,35 +1,533 @@
-use rustls::pki_types::PrivateKeyDer;
-use rustls::ServerConfig;
-use rustls_pemfile::{certs, pkcs8_private_keys};
+use arc_swap::ArcSwap;
+use log::{error, info};
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+use rustls::crypto::aws_lc_rs;
+use rustls::server::{NoServerSessionStorage, ProducesTickets, ServerSessionMemoryCache, StoresServerSessions, WebPkiClientVerifier};
+use rustls::{RootCertStore, ServerConfig, SupportedCipherSuite, SupportedProtocolVersion};
+use rustls_pemfile::{certs, private_key};
+use std::collections::hash_map::DefaultHasher;
 use std::fs::File;
+use std::hash::{Hash, Hasher};
 use std::io::BufReader;
-use std::sync::Arc;
+use std::net::IpAddr;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
 use tokio_rustls::TlsAcceptor;
 use crate::common::error::{LbError, Result};
+use crate::config::{ClientAuth, TlsConfig};
+use crate::networking::proxy_protocol::{self, Tlv};
 
-pub fn load_tls_config(cert_path: &str, key_path: &str) -> Result<TlsAcceptor> {
-    let cert_file = File::open(cert_path).map_err(LbError::Io)?;
+/// Default number of sessions kept for resumption by session ID (TLS 1.2 clients
+/// without ticket support); ticket-based resumption needs no server-side state.
+pub const DEFAULT_SESSION_CACHE_SIZE: usize = 1024;
+
+// Resumption state, shared by successive configs of a rule so a cert reload
+// doesn't force every client back to a full handshake.
+#[derive(Clone)]
+struct SessionResumption {
+    cache: Arc<dyn StoresServerSessions>,
+    ticketer: Arc<dyn ProducesTickets>,
+}
+
+impl SessionResumption {
+    // None when `session_cache_size: 0` disables resumption
+    fn new(tls_config: &TlsConfig) -> Result<Option<Self>> {
+        let size = tls_config.session_cache_size.unwrap_or(DEFAULT_SESSION_CACHE_SIZE);
+        if size == 0 {
+            return Ok(None);
+        }
+        Ok(Some(Self {
+            cache: ServerSessionMemoryCache::new(size),
+            ticketer: aws_lc_rs::Ticketer::new().map_err(|e| LbError::Tls(e.to_string()))?,
+        }))
+    }
+}
+
+fn build_server_config(tls_config: &TlsConfig, sessions: Option<&SessionResumption>) -> Result<ServerConfig> {
+    let cert_file = File::open(&tls_config.cert).map_err(LbError::Io)?;
     let mut cert_reader = BufReader::new(cert_file);
     let certs = certs(&mut cert_reader)
         .collect::<std::result::Result<Vec<_>, _>>()
         .map_err(LbError::Io)?;
 
-    let key_file = File::open(key_path).map_err(LbError::Io)?;
+    let key_file = File::open(&tls_config.key).map_err(LbError::Io)?;
     let mut key_reader = BufReader::new(key_file);
-    let mut keys = pkcs8_private_keys(&mut key_reader)
-        .collect::<std::result::Result<Vec<_>, _>>()
-        .map_err(LbError::Io)?;
+    // First key in the file: PKCS#8, PKCS#1 (RSA) or SEC1 (EC)
+    let key = private_key(&mut key_reader)
+        .map_err(LbError::Io)?
+        .ok_or_else(|| LbError::Tls(format!(
+            "No private key found in {} (tried PKCS#8 \"PRIVATE KEY\", PKCS#1 \"RSA PRIVATE KEY\", SEC1 \"EC PRIVATE KEY\")",
+            tls_config.key
+        )))?;
 
-    if keys.is_empty() {
-        return Err(LbError::Tls("No private keys found".to_string()));
+    let mut provider = aws_lc_rs::default_provider();
+    if let Some(names) = &tls_config.cipher_suites {
+        provider.cipher_suites = cipher_suites(names)?;
     }
+    // Fails when none of the selected suites belongs to an allowed version
+    let builder = ServerConfig::builder_with_provider(Arc::new(provider))
+        .with_protocol_versions(protocol_versions(tls_config.min_version.as_deref())?)
+        .map_err(|e| LbError::Tls(format!("cipher_suites/min_version: {}", e)))?;
 
-    let key = PrivateKeyDer::Pkcs8(keys.remove(0));
-
-    let config = ServerConfig::builder()
-        .with_no_client_auth()
+    let builder = match &tls_config.client_ca {
+        Some(ca_path) => builder.with_client_cert_verifier(load_client_verifier(ca_path, tls_config.client_auth)?),
+        None => builder.with_no_client_auth(),
+    };
+    // Also rejects a key that doesn't belong to the cert (e.g. mid-rotation)
+    let mut config = builder
         .with_single_cert(certs, key)
         .map_err(|e| LbError::Tls(e.to_string()))?;
 
-    Ok(TlsAcceptor::from(Arc::new(config)))
+    // Only advertised here; the negotiated protocol is proxied opaquely
+    if let Some(protocols) = &tls_config.alpn {
+        if protocols.iter().any(|p| p.is_empty()) {
+            return Err(LbError::Tls("ALPN protocol names must not be empty".to_string()));
+        }
+        config.alpn_protocols = protocols.iter().map(|p| p.as_bytes().to_vec()).collect();
+    }
+
+    match sessions {
+        Some(sessions) => {
+            config.session_storage = sessions.cache.clone();
+            config.ticketer = sessions.ticketer.clone();
+        }
+        None => {
+            config.session_storage = Arc::new(NoServerSessionStorage {});
+            config.send_tls13_tickets = 0;
+        }
+    }
+    Ok(config)
+}
+
+static TLS13_ONLY: &[&SupportedProtocolVersion] = &[&rustls::version::TLS13];
+
+fn protocol_versions(min_version: Option<&str>) -> Result<&'static [&'static SupportedProtocolVersion]> {
+    match min_version {
+        None | Some("1.2") => Ok(rustls::ALL_VERSIONS),
+        Some("1.3") => Ok(TLS13_ONLY),
+        Some(other) => Err(LbError::Tls(format!("Unknown TLS min_version '{}' (expected 1.2 or 1.3)", other))),
+    }
+}
+
+// Names as rustls spells them (e.g. TLS13_AES_256_GCM_SHA384), case-insensitive
+fn cipher_suites(names: &[String]) -> Result<Vec<SupportedCipherSuite>> {
+    names.iter()
+        .map(|name| {
+            aws_lc_rs::ALL_CIPHER_SUITES.iter()
+                .find(|s| s.suite().as_str().is_some_and(|n| n.eq_ignore_ascii_case(name)))
+                .copied()
+                .ok_or_else(|| LbError::Tls(format!("Unknown cipher suite '{}'", name)))
+        })
+        .collect()
+}
+
+// Client cert verifier trusting only the CAs in `ca_path`
+fn load_client_verifier(ca_path: &str, client_auth: ClientAuth) -> Result<Arc<dyn rustls::server::danger::ClientCertVerifier>> {
+    let ca_file = File::open(ca_path).map_err(LbError::Io)?;
+    let mut ca_reader = BufReader::new(ca_file);
+    let mut roots = RootCertStore::empty();
+    for cert in certs(&mut ca_reader) {
+        roots.add(cert.map_err(LbError::Io)?).map_err(|e| LbError::Tls(e.to_string()))?;
+    }
+    if roots.is_empty() {
+        return Err(LbError::Tls(format!("No CA certificates found in {}", ca_path)));
+    }
+
+    let builder = WebPkiClientVerifier::builder(Arc::new(roots));
+    let builder = match client_auth {
+        ClientAuth::Required => builder,
+        ClientAuth::Optional => builder.allow_unauthenticated(),
+    };
+    builder.build().map_err(|e| LbError::Tls(e.to_string()))
+}
+
+/// TLS server config that can be swapped while the listener keeps running.
+/// Handshakes in flight keep the config they started with; new ones pick up
+/// the latest successfully loaded cert.
+pub struct ReloadableTls {
+    rule_name: String,
+    tls_config: TlsConfig,
+    current: ArcSwap<ServerConfig>,
+    fingerprint: AtomicU64, // Contents of the files `current` was built from
+    sessions: Mutex<Option<SessionResumption>>,
+}
+
+impl ReloadableTls {
+    pub fn load(rule_name: String, tls_config: TlsConfig) -> Result<Self> {
+        let fingerprint = files_fingerprint(&tls_config);
+        let sessions = SessionResumption::new(&tls_config)?;
+        let current = ArcSwap::from_pointee(build_server_config(&tls_config, sessions.as_ref())?);
+        Ok(Self { rule_name, tls_config, current, fingerprint: AtomicU64::new(fingerprint), sessions: Mutex::new(sessions) })
+    }
+
+    pub fn acceptor(&self) -> TlsAcceptor {
+        TlsAcceptor::from(self.current.load_full())
+    }
+
+    /// Applies `client_cert_allow` to a completed handshake: the client cert's
+    /// CN or one of its SANs must match an entry. Clients without a cert are
+    /// rejected too. Err describes the refused identity.
+    pub fn check_client_identity(&self, conn: &rustls::ServerConnection) -> std::result::Result<(), String> {
+        let Some(allow) = &self.tls_config.client_cert_allow else {
+            return Ok(());
+        };
+        let Some(cert) = conn.peer_certificates().and_then(|certs| certs.first()) else {
+            return Err("no client certificate".to_string());
+        };
+        let identities: Vec<String> = subject_cn(cert).into_iter().chain(subject_alt_names(cert)).collect();
+        if identities.iter().any(|id| allow.iter().any(|pattern| identity_matches(pattern, id))) {
+            Ok(())
+        } else {
+            Err(format!("certificate names {:?} not allowed", identities))
+        }
+    }
+
+    /// Rebuilds the config if the cert/key/CA files changed. An invalid or
+    /// half-written file leaves the previously loaded config in place.
+    ///
+    /// Sessions survive a cert rotation. With mTLS they are dropped instead, as
+    /// resuming skips client cert verification against a possibly changed CA.
+    pub fn reload(&self) -> Result<bool> {
+        let fingerprint = files_fingerprint(&self.tls_config);
+        if fingerprint == self.fingerprint.load(Ordering::Relaxed) {
+            return Ok(false);
+        }
+        let mut sessions = self.sessions.lock().unwrap();
+        let next_sessions = if self.tls_config.client_ca.is_some() {
+            SessionResumption::new(&self.tls_config)?
+        } else {
+            sessions.clone()
+        };
+        let config = build_server_config(&self.tls_config, next_sessions.as_ref())?;
+        self.current.store(Arc::new(config));
+        self.fingerprint.store(fingerprint, Ordering::Relaxed);
+        *sessions = next_sessions;
+        Ok(true)
+    }
+
+    /// Watches the directories holding the cert files (so symlink swaps done by
+    /// cert-manager / Kubernetes secret mounts are seen) and reloads on change.
+    /// Watching stops when the returned watcher is dropped.
+    pub fn watch(self: &Arc<Self>) -> notify::Result<RecommendedWatcher> {
+        let tls = self.clone();
+        let mut watcher = RecommendedWatcher::new(move |res: std::result::Result<Event, notify::Error>| {
+            match res {
+                Ok(event) if event.kind.is_create() || event.kind.is_modify() || event.kind.is_remove() => {
+                    match tls.reload() {
+                        Ok(true) => info!("[{}] Reloaded TLS certificate from {}", tls.rule_name, tls.tls_config.cert),
+                        Ok(false) => {}
+                        Err(e) => error!("[{}] TLS reload failed, keeping current certificate: {}", tls.rule_name, e),
+                    }
+                }
+                Ok(_) => {}
+                Err(e) => error!("[{}] TLS watch error: {:?}", tls.rule_name, e),
+            }
+        }, notify::Config::default())?;
+
+        let mut dirs: Vec<&Path> = watched_files(&self.tls_config).into_iter()
+            .map(|f| Path::new(f).parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or(Path::new(".")))
+            .collect();
+        dirs.sort();
+        dirs.dedup();
+        for dir in dirs {
+            watcher.watch(dir, RecursiveMode::NonRecursive)?;
+        }
+        Ok(watcher)
+    }
+}
+
+/// PROXY v2 TLVs describing a terminated client session: SNI (authority),
+/// ALPN, and SSL with the version, cipher and client certificate CN
+pub fn session_tlvs(conn: &rustls::ServerConnection) -> Vec<Tlv> {
+    let mut tlvs = Vec::new();
+    if let Some(sni) = conn.server_name() {
+        tlvs.push(Tlv::new(proxy_protocol::PP2_TYPE_AUTHORITY, sni));
+    }
+    if let Some(alpn) = conn.alpn_protocol() {
+        tlvs.push(Tlv::new(proxy_protocol::PP2_TYPE_ALPN, alpn));
+    }
+    let (version, cipher) = negotiated(conn);
+    let client_cert = conn.peer_certificates().and_then(|certs| certs.first());
+    let cn = client_cert.and_then(|cert| subject_cn(cert));
+    tlvs.push(Tlv::ssl(client_cert.is_some(), version, cipher, cn.as_deref()));
+    tlvs
+}
+
+/// Protocol version ("TLSv1.3") and cipher suite name of a completed handshake
+pub fn negotiated(conn: &rustls::ServerConnection) -> (Option<&'static str>, Option<&'static str>) {
+    let version = match conn.protocol_version() {
+        Some(rustls::ProtocolVersion::TLSv1_2) => Some("TLSv1.2"),
+        Some(rustls::ProtocolVersion::TLSv1_3) => Some("TLSv1.3"),
+        _ => None,
+    };
+    let cipher = conn.negotiated_cipher_suite().and_then(|suite| suite.suite().as_str());
+    (version, cipher)
+}
+
+// (tag, contents, rest) of the DER element at the start of `der`
+fn der_next(der: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, rest) = der.split_first()?;
+    let (&first, rest) = rest.split_first()?;
+    let (len, rest) = if first < 0x80 {
+        (first as usize, rest)
+    } else {
+        let n = (first & 0x7f) as usize;
+        if n == 0 || n > 4 || rest.len() < n {
+            return None;
+        }
+        (rest[..n].iter().fold(0usize, |len, b| len << 8 | *b as usize), &rest[n..])
+    };
+    (rest.len() >= len).then(|| (tag, &rest[..len], &rest[len..]))
+}
+
+// (subject, extensions) of a DER X.509 certificate
+fn subject_and_extensions(cert: &[u8]) -> Option<(&[u8], Option<&[u8]>)> {
+    let (_, cert, _) = der_next(cert)?;
+    let (_, tbs, _) = der_next(cert)?;
+    let mut fields = tbs;
+    let (tag, _, after_version) = der_next(fields)?;
+    if tag == 0xa0 {
+        fields = after_version; // Explicit [0] version
+    }
+    // Serial, signature algorithm, issuer, validity
+    for _ in 0..4 {
+        fields = der_next(fields)?.2;
+    }
+    let (_, subject, after_subject) = der_next(fields)?;
+    // Public key info, optional unique IDs, then the explicit [3] extensions
+    let mut rest = der_next(after_subject).map_or(&[][..], |(_, _, rest)| rest);
+    let mut extensions = None;
+    while let Some((tag, contents, next)) = der_next(rest) {
+        if tag == 0xa3 {
+            extensions = der_next(contents).map(|(_, extensions, _)| extensions);
+        }
+        rest = next;
+    }
+    Some((subject, extensions))
+}
+
+// Subject common name (OID 2.5.4.3) of a DER X.509 certificate
+fn subject_cn(cert: &[u8]) -> Option<String> {
+    let (mut rdns, _) = subject_and_extensions(cert)?;
+    // SEQUENCE OF SET OF (OID, value)
+    while let Some((_, set, next)) = der_next(rdns) {
+        let (_, attribute, _) = der_next(set)?;
+        let (_, oid, value) = der_next(attribute)?;
+        if oid == [0x55, 0x04, 0x03] {
+            let (_, cn, _) = der_next(value)?;
+            return String::from_utf8(cn.to_vec()).ok();
+        }
+        rdns = next;
+    }
+    None
+}
+
+// DNS, email, URI and IP entries of the subjectAltName extension (OID 2.5.29.17)
+fn subject_alt_names(cert: &[u8]) -> Vec<String> {
+    let mut names = Vec::new();
+    let Some((_, Some(mut extensions))) = subject_and_extensions(cert) else {
+        return names;
+    };
+    while let Some((_, extension, next)) = der_next(extensions) {
+        extensions = next;
+        // SEQUENCE { OID, critical BOOLEAN (optional), OCTET STRING }
+        let Some((_, oid, mut rest)) = der_next(extension) else { continue };
+        if oid != [0x55, 0x1d, 0x11] {
+            continue;
+        }
+        let mut value = None;
+        while let Some((tag, contents, next)) = der_next(rest) {
+            if tag == 0x04 {
+                value = Some(contents);
+            }
+            rest = next;
+        }
+        let Some((_, mut general_names, _)) = value.and_then(der_next) else { continue };
+        while let Some((tag, contents, next)) = der_next(general_names) {
+            match tag {
+                // [1] rfc822Name, [2] dNSName, [6] URI (IA5String)
+                0x81 | 0x82 | 0x86 => names.extend(std::str::from_utf8(contents).ok().map(str::to_string)),
+                // [7] iPAddress
+                0x87 => names.extend(<[u8; 4]>::try_from(contents).map(IpAddr::from)
+                    .or_else(|_| <[u8; 16]>::try_from(contents).map(IpAddr::from))
+                    .ok()
+                    .map(|ip| ip.to_string())),
+                _ => {}
+            }
+            general_names = next;
+        }
+    }
+    names
+}
+
+// `client_cert_allow` entry against a cert name: exact, or "*.example.com" for
+// any name under example.com (at any depth). Case-insensitive.
+fn identity_matches(pattern: &str, identity: &str) -> bool {
+    match pattern.strip_prefix('*') {
+        Some(suffix) => identity.len() > suffix.len()
+            && identity.get(identity.len() - suffix.len()..).is_some_and(|tail| tail.eq_ignore_ascii_case(suffix)),
+        None => pattern.eq_ignore_ascii_case(identity),
+    }
+}
+
+fn watched_files(tls_config: &TlsConfig) -> Vec<&String> {
+    [Some(&tls_config.cert), Some(&tls_config.key), tls_config.client_ca.as_ref()]
+        .into_iter()
+        .flatten()
+        .collect()
+}
+
+// Unreadable files hash as empty, so they change the fingerprint and the
+// rebuild reports the actual error.
+fn files_fingerprint(tls_config: &TlsConfig) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    for path in watched_files(tls_config) {
+        std::fs::read(path).unwrap_or_default().hash(&mut hasher);
+    }
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cipher_suites_by_name() {
+        let suites = cipher_suites(&["TLS13_AES_256_GCM_SHA384".to_string(), "tls_ecdhe_rsa_with_aes_128_gcm_sha256".to_string()]).unwrap();
+        assert_eq!(suites.len(), 2);
+        assert_eq!(suites[0].version(), &rustls::version::TLS13);
+        assert_eq!(suites[1].version(), &rustls::version::TLS12);
+
+        let err = cipher_suites(&["TLS_RSA_WITH_RC4_128_MD5".to_string()]).unwrap_err();
+        assert!(err.to_string().contains("TLS_RSA_WITH_RC4_128_MD5"));
+    }
+
+    fn der(tag: u8, parts: &[&[u8]]) -> Vec<u8> {
+        let content = parts.concat();
+        let mut out = vec![tag];
+        if content.len() < 0x80 {
+            out.push(content.len() as u8);
+        } else {
+            out.extend([0x82, (content.len() >> 8) as u8, content.len() as u8]);
+        }
+        out.extend(content);
+        out
+    }
+
+    fn name(cn: &str) -> Vec<u8> {
+        let org = der(0x31, &[&der(0x30, &[&der(0x06, &[&[0x55, 0x04, 0x0a]]), &der(0x0c, &[b"Example"])])]);
+        let common = der(0x31, &[&der(0x30, &[&der(0x06, &[&[0x55, 0x04, 0x03]]), &der(0x0c, &[cn.as_bytes()])])]);
+        der(0x30, &[&org, &common])
+    }
+
+    #[test]
+    fn test_subject_cn() {
+        // Skeleton certificate: only the fields in front of the subject need to be well-formed
+        let version = der(0xa0, &[&der(0x02, &[&[2]])]);
+        let tbs = der(0x30, &[
+            &version,
+            &der(0x02, &[&[1]]),
+            &der(0x30, &[]),
+            &name("Issuing CA"),
+            &der(0x30, &[&[0u8; 200]]), // Long-form length
+            &name("client-1"),
+        ]);
+        let cert = der(0x30, &[&tbs, &der(0x30, &[]), &der(0x03, &[&[0]])]);
+        assert_eq!(subject_cn(&cert).as_deref(), Some("client-1"));
+        assert_eq!(subject_cn(&cert[..20]), None);
+    }
+
+    #[test]
+    fn test_subject_alt_names() {
+        let general_names = der(0x30, &[&der(0x82, &[b"api.example.com"]), &der(0x87, &[&[10, 0, 0, 1]]), &der(0x81, &[b"ops@example.com"])]);
+        let san = der(0x30, &[&der(0x06, &[&[0x55, 0x1d, 0x11]]), &der(0x01, &[&[0xff]]), &der(0x04, &[&general_names])]);
+        let key_usage = der(0x30, &[&der(0x06, &[&[0x55, 0x1d, 0x0f]]), &der(0x04, &[&der(0x03, &[&[0x05, 0xa0]])])]);
+        let tbs = der(0x30, &[
+            &der(0xa0, &[&der(0x02, &[&[2]])]),
+            &der(0x02, &[&[1]]),
+            &der(0x30, &[]),
+            &name("Issuing CA"),
+            &der(0x30, &[]),
+            &name("client-1"),
+            &der(0x30, &[]), // Public key info
+            &der(0xa3, &[&der(0x30, &[&key_usage, &san])]),
+        ]);
+        let cert = der(0x30, &[&tbs, &der(0x30, &[]), &der(0x03, &[&[0]])]);
+        assert_eq!(subject_cn(&cert).as_deref(), Some("client-1"));
+        assert_eq!(subject_alt_names(&cert), ["api.example.com", "10.0.0.1", "ops@example.com"]);
+    }
+
+    #[test]
+    fn test_identity_matches() {
+        assert!(identity_matches("client-1", "CLIENT-1"));
+        assert!(identity_matches("*.example.com", "api.example.com"));
+        assert!(identity_matches("*.example.com", "a.b.example.com"));
+        assert!(!identity_matches("*.example.com", "example.com"));
+        assert!(!identity_matches("*.example.com", "api.example.com.evil"));
+        assert!(!identity_matches("client-1", "client-10"));
+    }
+
+    #[test]
+    fn test_protocol_versions() {
+        assert_eq!(protocol_versions(None).unwrap().len(), 2);
+        assert_eq!(protocol_versions(Some("1.3")).unwrap(), &[&rustls::version::TLS13]);
+        assert!(protocol_versions(Some("1.1")).is_err());
+    }
+
+    #[test]
+    fn test_missing_key_names_formats() {
+        let dir = std::env::temp_dir().join(format!("l4lb-tls-key-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let key_path = dir.join("server.key");
+        // A cert where the key should be: no key block of any format
+        std::fs::write(&key_path, "-----BEGIN CERTIFICATE-----\nAAAA\n-----END CERTIFICATE-----\n").unwrap();
+        let tls_config = TlsConfig {
+            enabled: true,
+            cert: key_path.to_string_lossy().into_owned(),
+            key: key_path.to_string_lossy().into_owned(),
+            client_ca: None,
+            client_auth: ClientAuth::default(),
+            client_cert_allow: None,
+            min_version: None,
+            cipher_suites: None,
+            alpn: None,
+            session_cache_size: None,
+        };
+
+        let err = build_server_config(&tls_config, None).err().unwrap().to_string();
+        assert!(err.contains("PKCS#8") && err.contains("PKCS#1") && err.contains("SEC1"), "{}", err);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_reload_rejects_invalid_files() {
+        let dir = std::env::temp_dir().join(format!("l4lb-tls-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let tls_config = TlsConfig {
+            enabled: true,
+            cert: dir.join("server.crt").to_string_lossy().into_owned(),
+            key: dir.join("server.key").to_string_lossy().into_owned(),
+            client_ca: None,
+            client_auth: ClientAuth::default(),
+            client_cert_allow: None,
+            min_version: None,
+            cipher_suites: None,
+            alpn: None,
+            session_cache_size: None,
+        };
+
+        std::fs::write(&tls_config.cert, "not a cert").unwrap();
+        std::fs::write(&tls_config.key, "not a key").unwrap();
+        assert!(ReloadableTls::load("r".to_string(), tls_config.clone()).is_err());
+
+        // Any content change is picked up by the fingerprint
+        let before = files_fingerprint(&tls_config);
+        std::fs::write(&tls_config.key, "still not a key").unwrap();
+        assert_ne!(before, files_fingerprint(&tls_config));
+        assert_eq!(files_fingerprint(&tls_config), files_fingerprint(&tls_config));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
 }
@@ -0,0 +1,325 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
+use dashmap::DashMap;
+use tokio::net::UdpSocket;
+use log::{debug, info};
+use crate::core::balancer::{ConnectionGuard, LoadBalancer};
+use crate::networking::acl::AccessControl;
+use crate::traffic::limiter::RateLimiter;
+
+pub const DEFAULT_UDP_IDLE_TIMEOUT_MS: u64 = 30_000;
+
+/// Sessions per listening socket before new client addresses are dropped
+pub const DEFAULT_UDP_MAX_SESSIONS: usize = 10_000;
+
+// Max datagram size we forward (max UDP payload)
+const MAX_DATAGRAM: usize = 65535;
+
+/// One client "flow": a connected socket towards the chosen backend. Replies
+/// arriving on it are relayed back to the client through the listener socket.
+/// The socket is set once the backend is resolved; datagrams arriving before
+/// that are dropped.
+struct UdpSession {
+    backend_socket: OnceLock<UdpSocket>,
+    backend_addr: String,
+    client_addr: SocketAddr,
+    last_seen_ms: AtomicU64, // Millis since `epoch`
+    _guard: ConnectionGuard, // Counts towards backend active connections
+}
+
+pub struct UdpProxy {
+    rule_name: String,
+    socket: Arc<UdpSocket>,
+    lb: Arc<LoadBalancer>,
+    rate_limiter: Arc<RateLimiter>,
+    acl: Arc<AccessControl>,
+    idle_timeout: Duration,
+    max_sessions: usize,
+    sessions: Arc<DashMap<SocketAddr, Arc<UdpSession>>>,
+    epoch: Instant,
+}
+
+impl UdpProxy {
+    pub fn new(
+        rule_name: String,
+        socket: UdpSocket,
+        lb: Arc<LoadBalancer>,
+        rate_limiter: Arc<RateLimiter>,
+        acl: Arc<AccessControl>,
+        idle_timeout: Duration,
+        max_sessions: usize,
+    ) -> Self {
+        UdpProxy {
+            rule_name,
+            socket: Arc::new(socket),
+            lb,
+            rate_limiter,
+            acl,
+            idle_timeout,
+            max_sessions,
+            sessions: Arc::new(DashMap::new()),
+            epoch: Instant::now(),
+        }
+    }
+
+    pub async fn run(self) {
+        let mut buf = vec![0u8; MAX_DATAGRAM];
+        loop {
+            let (len, client_addr) = match self.socket.recv_from(&mut buf).await {
+                Ok(r) => r,
+                Err(e) => {
+                    crate::throttled_error!(self.rule_name, "[{}] UDP receive error: {}", self.rule_name, e);
+                    continue;
+                }
+            };
+
+            let session = match self.sessions.get(&client_addr) {
+                Some(s) => s.clone(),
+                None => {
+                    self.open_session(client_addr, &buf[..len]);
+                    continue;
+                }
+            };
+
+            session.last_seen_ms.store(self.now_ms(), Ordering::Relaxed);
+            let Some(backend_socket) = session.backend_socket.get() else {
+                debug!("[{}] UDP datagram from {} dropped: backend {} not connected yet", self.rule_name, client_addr, session.backend_addr);
+                continue;
+            };
+            match backend_socket.send(&buf[..len]).await {
+                Ok(n) => {
+                    crate::metrics::TRAFFIC_BYTES.with_label_values(&[&self.rule_name, "client_in"]).inc_by(n as u64);
+                    crate::metrics::TRAFFIC_BYTES.with_label_values(&[&self.rule_name, "backend_out"]).inc_by(n as u64);
+                }
+                Err(e) => {
+                    let key = format!("{}/{}", self.rule_name, session.backend_addr);
+                    crate::throttled_error!(key, "[{}] UDP send to backend {} failed: {}", self.rule_name, session.backend_addr, e);
+                }
+            }
+        }
+    }
+
+    // Admission and backend selection happen here; resolving and connecting the
+    // backend socket runs in the session's own task, so a slow DNS lookup only
+    // holds up this client. `first` is sent once the socket is connected.
+    fn open_session(&self, client_addr: SocketAddr, first: &[u8]) {
+        if !self.acl.is_allowed(client_addr.ip()) {
+            debug!("[{}] UDP datagram from {} denied by ACL", self.rule_name, client_addr);
+            crate::metrics::REJECTED_CONNECTIONS.with_label_values(&[&self.rule_name, "acl"]).inc();
+            crate::metrics::ERRORS.with_label_values(&[&self.rule_name, "acl_denied"]).inc();
+            return;
+        }
+        if self.sessions.len() >= self.max_sessions {
+            crate::throttled_error!(self.rule_name, "[{}] UDP session limit ({}) reached, dropping datagram from {}", self.rule_name, self.max_sessions, client_addr);
+            crate::metrics::REJECTED_CONNECTIONS.with_label_values(&[&self.rule_name, "max_sessions"]).inc();
+            return;
+        }
+        if !self.rate_limiter.check(client_addr.ip()) {
+            crate::metrics::REJECTED_CONNECTIONS.with_label_values(&[&self.rule_name, "rate_limit"]).inc();
+            crate::metrics::ERRORS.with_label_values(&[&self.rule_name, "rate_limited"]).inc();
+            return;
+        }
+
+        let Some((backend_addr, guard)) = self.lb.next_backend_for(client_addr.ip()) else {
+            crate::throttled_error!(self.rule_name, "[{}] No available backends", self.rule_name);
+            return;
+        };
+
+        let session = Arc::new(UdpSession {
+            backend_socket: OnceLock::new(),
+            backend_addr,
+            client_addr,
+            last_seen_ms: AtomicU64::new(self.now_ms()),
+            _guard: guard,
+        });
+        self.sessions.insert(client_addr, session.clone());
+        debug!("[{}] New UDP session {} -> {}", self.rule_name, client_addr, session.backend_addr);
+
+        crate::metrics::ACTIVE_CONNECTIONS.with_label_values(&[&self.rule_name]).inc();
+        crate::metrics::TOTAL_CONNECTIONS.with_label_values(&[&self.rule_name]).inc();
+
+        tokio::spawn(run_session(
+            self.rule_name.clone(),
+            self.socket.clone(),
+            self.sessions.clone(),
+            session,
+            first.to_vec(),
+            self.idle_timeout,
+            self.epoch,
+        ));
+    }
+
+    fn now_ms(&self) -> u64 {
+        self.epoch.elapsed().as_millis() as u64
+    }
+}
+
+// Connects the session's backend socket, forwards the client's first datagram,
+// then relays replies until the session ends
+async fn run_session(
+    rule_name: String,
+    listener: Arc<UdpSocket>,
+    sessions: Arc<DashMap<SocketAddr, Arc<UdpSession>>>,
+    session: Arc<UdpSession>,
+    first: Vec<u8>,
+    idle_timeout: Duration,
+    epoch: Instant,
+) {
+    let start = Instant::now();
+    let client_addr = session.client_addr;
+    match connect_backend(&session.backend_addr).await {
+        Ok(socket) => {
+            match socket.send(&first).await {
+                Ok(n) => {
+                    crate::metrics::TRAFFIC_BYTES.with_label_values(&[&rule_name, "client_in"]).inc_by(n as u64);
+                    crate::metrics::TRAFFIC_BYTES.with_label_values(&[&rule_name, "backend_out"]).inc_by(n as u64);
+                }
+                Err(e) => debug!("[{}] UDP send to backend {} failed: {}", rule_name, session.backend_addr, e),
+            }
+            let _ = session.backend_socket.set(socket);
+            relay_replies(&rule_name, &listener, &session, client_addr, idle_timeout, epoch).await;
+        }
+        Err(e) => {
+            let key = format!("{}/{}", rule_name, session.backend_addr);
+            crate::throttled_error!(key, "[{}] UDP connect to backend {} failed: {}", rule_name, session.backend_addr, e);
+            crate::metrics::ERRORS.with_label_values(&[&rule_name, "backend_connect"]).inc();
+        }
+    }
+
+    sessions.remove(&client_addr);
+    crate::metrics::ACTIVE_CONNECTIONS.with_label_values(&[&rule_name]).dec();
+    crate::metrics::CONNECTION_DURATION.with_label_values(&[&rule_name]).observe(start.elapsed().as_secs_f64());
+    debug!("[{}] UDP session {} -> {} closed", rule_name, client_addr, session.backend_addr);
+}
+
+// Backend -> client direction. Ends once neither side has sent anything for `idle_timeout`.
+async fn relay_replies(
+    rule_name: &str,
+    listener: &UdpSocket,
+    session: &UdpSession,
+    client_addr: SocketAddr,
+    idle_timeout: Duration,
+    epoch: Instant,
+) {
+    let Some(backend_socket) = session.backend_socket.get() else { return };
+    let mut buf = vec![0u8; MAX_DATAGRAM];
+    loop {
+        match tokio::time::timeout(idle_timeout, backend_socket.recv(&mut buf)).await {
+            Ok(Ok(len)) => {
+                session.last_seen_ms.store(epoch.elapsed().as_millis() as u64, Ordering::Relaxed);
+                match listener.send_to(&buf[..len], client_addr).await {
+                    Ok(n) => {
+                        crate::metrics::TRAFFIC_BYTES.with_label_values(&[rule_name, "backend_in"]).inc_by(n as u64);
+                        crate::metrics::TRAFFIC_BYTES.with_label_values(&[rule_name, "client_out"]).inc_by(n as u64);
+                    }
+                    Err(e) => debug!("[{}] UDP send to client {} failed: {}", rule_name, client_addr, e),
+                }
+            }
+            Ok(Err(e)) => {
+                // e.g. ICMP port unreachable surfaced as ECONNREFUSED on a connected socket
+                debug!("[{}] UDP receive from backend {} failed: {}", rule_name, session.backend_addr, e);
+                break;
+            }
+            Err(_) => {
+                let idle_ms = (epoch.elapsed().as_millis() as u64).saturating_sub(session.last_seen_ms.load(Ordering::Relaxed));
+                if idle_ms >= idle_timeout.as_millis() as u64 {
+                    break;
+                }
+            }
+        }
+    }
+}
+
+// Socket connected to the backend, so only its datagrams are received
+pub async fn connect_backend(backend_addr: &str) -> std::io::Result<UdpSocket> {
+    let target = tokio::net::lookup_host(backend_addr).await?
+        .next()
+        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, format!("{} did not resolve", backend_addr)))?;
+    let local: IpAddr = if target.is_ipv4() { Ipv4Addr::UNSPECIFIED.into() } else { Ipv6Addr::UNSPECIFIED.into() };
+    let socket = UdpSocket::bind(SocketAddr::new(local, 0)).await?;
+    socket.connect(target).await?;
+    Ok(socket)
+}
+
+pub async fn bind(rule_name: &str, listen: SocketAddr) -> std::io::Result<UdpSocket> {
+    let socket = UdpSocket::bind(listen).await?;
+    info!("UDP listener for rule '{}' on {}", rule_name, socket.local_addr()?);
+    Ok(socket)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{BackendConfig, RateLimitConfig, Strategy};
+
+    #[tokio::test]
+    async fn test_udp_session_round_trip_and_expiry() {
+        // Echo backend
+        let backend = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let backend_addr = backend.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut buf = [0u8; 1500];
+            while let Ok((n, peer)) = backend.recv_from(&mut buf).await {
+                let _ = backend.send_to(&buf[..n], peer).await;
+            }
+        });
+
+        let lb = Arc::new(LoadBalancer::new("udp_test".to_string(), vec![BackendConfig::Simple(backend_addr.to_string())], None, Strategy::RoundRobin));
+        let limiter = Arc::new(RateLimiter::new(RateLimitConfig::default()));
+        let acl = Arc::new(AccessControl::new(None, None));
+        let socket = bind("udp_test", "127.0.0.1:0".parse().unwrap()).await.unwrap();
+        let listen_addr = socket.local_addr().unwrap();
+        let proxy = UdpProxy::new("udp_test".to_string(), socket, lb, limiter, acl, Duration::from_millis(200), DEFAULT_UDP_MAX_SESSIONS);
+        let sessions = proxy.sessions.clone();
+        tokio::spawn(proxy.run());
+
+        let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        client.connect(listen_addr).await.unwrap();
+        let mut buf = [0u8; 1500];
+        for msg in [&b"ping"[..], &b"pong"[..]] {
+            client.send(msg).await.unwrap();
+            let n = tokio::time::timeout(Duration::from_secs(2), client.recv(&mut buf)).await.unwrap().unwrap();
+            assert_eq!(&buf[..n], msg);
+        }
+        assert_eq!(sessions.len(), 1);
+
+        tokio::time::sleep(Duration::from_millis(600)).await;
+        assert!(sessions.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_udp_session_limit_drops_new_clients() {
+        let backend = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let backend_addr = backend.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut buf = [0u8; 1500];
+            while let Ok((n, peer)) = backend.recv_from(&mut buf).await {
+                let _ = backend.send_to(&buf[..n], peer).await;
+            }
+        });
+
+        let lb = Arc::new(LoadBalancer::new("udp_cap".to_string(), vec![BackendConfig::Simple(backend_addr.to_string())], None, Strategy::RoundRobin));
+        let limiter = Arc::new(RateLimiter::new(RateLimitConfig::default()));
+        let acl = Arc::new(AccessControl::new(None, None));
+        let socket = bind("udp_cap", "127.0.0.1:0".parse().unwrap()).await.unwrap();
+        let listen_addr = socket.local_addr().unwrap();
+        let proxy = UdpProxy::new("udp_cap".to_string(), socket, lb, limiter, acl, Duration::from_secs(30), 1);
+        let sessions = proxy.sessions.clone();
+        tokio::spawn(proxy.run());
+
+        let mut buf = [0u8; 1500];
+        let first = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        first.connect(listen_addr).await.unwrap();
+        first.send(b"one").await.unwrap();
+        let n = tokio::time::timeout(Duration::from_secs(2), first.recv(&mut buf)).await.unwrap().unwrap();
+        assert_eq!(&buf[..n], b"one");
+
+        let second = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        second.connect(listen_addr).await.unwrap();
+        second.send(b"two").await.unwrap();
+        assert!(tokio::time::timeout(Duration::from_millis(200), second.recv(&mut buf)).await.is_err());
+        assert_eq!(sessions.len(), 1);
+    }
+}
@@ -6,6 +6,9 @@ use crate::traffic::limiter::RateLimiterType;
 use futures::future::BoxFuture;
 use futures::FutureExt;
 
+// Default bytes paid for / transferred per poll when a limiter is attached
+pub const DEFAULT_CHUNK_SIZE: usize = 16384;
+
 pub struct RateLimitedStream<S> {
     inner: S,
     read_limiter: Option<Arc<RateLimiterType>>,
@@ -14,17 +17,19 @@ pub struct RateLimitedStream<S> {
     read_permit_fut: Option<BoxFuture<'static, ()>>,
     // State for pending write permission
     write_permit_fut: Option<BoxFuture<'static, ()>>,
+    chunk_size: usize,
 }
 
 impl<S> RateLimitedStream<S> {
-    pub fn new(inner: S, read_limiter: Option<Arc<RateLimiterType>>, write_limiter: Option<Arc<RateLimiterType>>) -> Self {
-        log::info!("New RateLimitedStream. ReadLimiter: {}, WriteLimiter: {}", read_limiter.is_some(), write_limiter.is_some());
+    pub fn new(inner: S, read_limiter: Option<Arc<RateLimiterType>>, write_limiter: Option<Arc<RateLimiterType>>, chunk_size: usize) -> Self {
+        log::debug!("New RateLimitedStream. ReadLimiter: {}, WriteLimiter: {}", read_limiter.is_some(), write_limiter.is_some());
         RateLimitedStream {
             inner,
             read_limiter,
             write_limiter,
             read_permit_fut: None,
             write_permit_fut: None,
+            chunk_size: chunk_size.max(1),
         }
     }
 }
@@ -62,8 +67,7 @@ impl<S: AsyncRead + Unpin + Send> AsyncRead for RateLimitedStream<S> {
                         return Poll::Ready(Ok(()));
                     }
                     
-                    let chunk_size = 16384;
-                    let to_read = std::cmp::min(remaining, chunk_size);
+                    let to_read = std::cmp::min(remaining, this.chunk_size);
                     let n_req = std::num::NonZeroU32::new(to_read as u32).unwrap();
 
                     // Try to acquire tokens immediately
@@ -92,8 +96,7 @@ impl<S: AsyncRead + Unpin + Send> AsyncRead for RateLimitedStream<S> {
                 // Re-calculate chunk size to be safe, though it should be same as permit if we just fell through.
                 // NOTE: If we waited, `buf` "could" have changed theoretically if caller is naughty, 
                 // but we assume it's stable per AsyncRead contract for Pending.
-                let chunk_size = 16384;
-                let to_read = std::cmp::min(remaining, chunk_size);
+                let to_read = std::cmp::min(remaining, this.chunk_size);
                 
                 let mut small_buf = buf.take(to_read);
                 
@@ -146,8 +149,7 @@ impl<S: AsyncWrite + Unpin + Send> AsyncWrite for RateLimitedStream<S> {
                     let len = buf.len();
                     if len == 0 { return Poll::Ready(Ok(0)); }
 
-                    let chunk_size = 16384;
-                    let to_write = std::cmp::min(len, chunk_size);
+                    let to_write = std::cmp::min(len, this.chunk_size);
                     let n_req = std::num::NonZeroU32::new(to_write as u32).unwrap();
 
                     // Try check_n
@@ -169,8 +171,7 @@ impl<S: AsyncWrite + Unpin + Send> AsyncWrite for RateLimitedStream<S> {
                 // 3. Perform the write
                 let len = buf.len();
                 if len == 0 { return Poll::Ready(Ok(0)); }
-                let chunk_size = 16384;
-                let to_write = std::cmp::min(len, chunk_size);
+                let to_write = std::cmp::min(len, this.chunk_size);
                 
                 let truncated_buf = &buf[0..to_write];
                 match Pin::new(&mut this.inner).poll_write(cx, truncated_buf) {
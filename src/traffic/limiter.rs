@@ -1,12 +1,15 @@
 // Custom Simple Limiter to debug Governor issues
-use std::sync::Mutex;
+use std::sync::{Mutex, OnceLock};
 use std::time::{Instant, Duration};
 use tokio::time::sleep;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use dashmap::DashMap;
 use std::net::IpAddr;
-use crate::config::RateLimitConfig;
+use ipnet::IpNet;
+use crate::config::{RateLimitConfig, RateLimitScope};
 use crate::config::BandwidthLimitConfig;
+use crate::cluster::usage::UsageSync;
 
 #[derive(Debug)]
 pub struct SimpleLimiter {
@@ -17,10 +20,30 @@ pub struct SimpleLimiter {
 
 #[derive(Debug)]
 struct SimpleLimiterState {
+    // Negative while `until_n_ready` waiters hold reservations on future refill
     tokens: f64,
     last_update: Instant,
 }
 
+// Tokens taken ahead of time by `until_n_ready`; handed back if the waiter is
+// dropped before its reservation comes due
+struct Reservation<'a> {
+    limiter: &'a SimpleLimiter,
+    n: u32,
+    armed: bool,
+}
+
+impl Drop for Reservation<'_> {
+    fn drop(&mut self) {
+        if !self.armed {
+            return;
+        }
+        let mut state = self.limiter.state.lock().unwrap();
+        self.limiter.refill(&mut state);
+        state.tokens = (state.tokens + self.n as f64).min(self.limiter.burst_size as f64);
+    }
+}
+
 impl SimpleLimiter {
     pub fn new(rate_per_sec: u32, burst_size: u32) -> Self {
         SimpleLimiter {
@@ -33,7 +56,7 @@ impl SimpleLimiter {
         }
     }
 
-    // Returns Ok if tokens consumed, Err if not enough
+    // Returns Ok if tokens consumed, Err if not enough (or reserved by waiters)
     pub fn check_n(&self, n: u32) -> Result<(), ()> {
         let mut state = self.state.lock().unwrap();
         self.refill(&mut state);
@@ -46,27 +69,41 @@ impl SimpleLimiter {
         }
     }
 
-    // Async wait for tokens
+    // Tokens spent elsewhere (another cluster node). May go into debt, but at
+    // most one burst so a stale backlog can't lock the key out for long (and
+    // never cancelling debt already reserved by local waiters).
+    pub fn consume(&self, n: u32) {
+        let mut state = self.state.lock().unwrap();
+        self.refill(&mut state);
+        let floor = (-(self.burst_size as f64)).min(state.tokens);
+        state.tokens = (state.tokens - n as f64).max(floor);
+    }
+
+    // Async wait for tokens, served first come first served. The tokens are
+    // reserved on arrival (the bucket may go into debt) and the caller sleeps
+    // exactly until the refill has paid for them, so waiters sharing a limiter
+    // queue up in arrival order without polling or waking each other, and the
+    // achieved rate doesn't drift with sleep granularity.
     pub async fn until_n_ready(&self, n: u32) -> Result<(), ()> {
-        loop {
-            let wait_duration = {
-                let mut state = self.state.lock().unwrap();
-                self.refill(&mut state);
-                if state.tokens >= n as f64 {
-                    state.tokens -= n as f64;
-                    return Ok(());
-                }
-                
-                // Calculate time needed to get enough tokens
-                let missing = (n as f64) - state.tokens;
-                let seconds_needed = missing / (self.rate_per_sec as f64);
-                Duration::from_secs_f64(seconds_needed)
-            };
+        let wait_duration = {
+            let mut state = self.state.lock().unwrap();
+            self.refill(&mut state);
+            state.tokens -= n as f64;
+            if state.tokens >= 0.0 {
+                return Ok(());
+            }
+            Duration::from_secs_f64(-state.tokens / (self.rate_per_sec as f64))
+        };
 
-            // Sleep for the calculated duration (plus a tiny buffer to be safe?)
-            // We just sleep and retry.
-            sleep(wait_duration).await;
-        }
+        let mut reservation = Reservation { limiter: self, n, armed: true };
+        sleep(wait_duration).await;
+        reservation.armed = false;
+        Ok(())
+    }
+
+    // Time since the bucket was last checked or drawn from
+    pub fn idle_for(&self) -> Duration {
+        self.state.lock().unwrap().last_update.elapsed()
     }
 
     fn refill(&self, state: &mut SimpleLimiterState) {
@@ -83,94 +120,443 @@ impl SimpleLimiter {
 
 pub type RateLimiterType = SimpleLimiter;
 
+/// How long a per-client (or per-backend) limiter may sit unused before it is dropped
+pub const DEFAULT_LIMITER_IDLE_TTL_MS: u64 = 300_000;
+
+// Full sweep of idle limiters every N newly created ones
+const LIMITER_SWEEP_EVERY: usize = 1024;
+
+// Keyed limiters, pruned of idle entries as new keys arrive so that a stream of
+// distinct client IPs can't grow the map forever. An evicted key starts over
+// with a full burst, which is harmless once the bucket has refilled anyway.
+struct LimiterMap<K: std::hash::Hash + Eq> {
+    entries: DashMap<K, Arc<RateLimiterType>>,
+    inserts: AtomicUsize,
+    idle_ttl: Duration,
+    active_keys: OnceLock<prometheus::Gauge>, // l4lb_rate_limiter_active_keys, once the rule is known
+}
+
+impl<K: std::hash::Hash + Eq> LimiterMap<K> {
+    fn new(idle_ttl_ms: Option<u64>) -> Self {
+        LimiterMap {
+            entries: DashMap::new(),
+            inserts: AtomicUsize::new(0),
+            idle_ttl: Duration::from_millis(idle_ttl_ms.unwrap_or(DEFAULT_LIMITER_IDLE_TTL_MS)),
+            active_keys: OnceLock::new(),
+        }
+    }
+
+    fn report_to(&self, rule_name: &str, kind: &str) {
+        let gauge = crate::metrics::RATE_LIMITER_ACTIVE_KEYS.with_label_values(&[rule_name, kind]);
+        gauge.add(self.entries.len() as f64);
+        let _ = self.active_keys.set(gauge);
+    }
+
+    fn adjust_active_keys(&self, delta: f64) {
+        if let Some(gauge) = self.active_keys.get() {
+            gauge.add(delta);
+        }
+    }
+
+    fn get_or_insert_with(&self, key: K, create: impl FnOnce() -> RateLimiterType) -> Arc<RateLimiterType> {
+        if let Some(limiter) = self.entries.get(&key) {
+            return limiter.clone();
+        }
+        let (limiter, created) = match self.entries.entry(key) {
+            dashmap::mapref::entry::Entry::Occupied(e) => (e.get().clone(), false),
+            dashmap::mapref::entry::Entry::Vacant(e) => (e.insert(Arc::new(create())).value().clone(), true),
+        };
+        if created {
+            self.adjust_active_keys(1.0);
+        }
+        // Entry guard is released above; retain locks every shard
+        if created && self.inserts.fetch_add(1, Ordering::Relaxed) % LIMITER_SWEEP_EVERY == LIMITER_SWEEP_EVERY - 1 {
+            self.sweep();
+        }
+        limiter
+    }
+
+    // Drops limiters idle for longer than the TTL. Ones still held by a live
+    // stream are kept, or a new connection from the same key would get a
+    // second, independent budget.
+    fn sweep(&self) {
+        let ttl = self.idle_ttl;
+        let mut evicted = 0;
+        self.entries.retain(|_, limiter| {
+            let keep = Arc::strong_count(limiter) > 1 || limiter.idle_for() < ttl;
+            evicted += usize::from(!keep);
+            keep
+        });
+        self.adjust_active_keys(-(evicted as f64));
+    }
+
+    fn len(&self) -> usize {
+        self.entries.len()
+    }
+}
+
+// The gauge is adjusted rather than set, since a reloaded rule's new limiters
+// share it with the old ones until those are dropped
+impl<K: std::hash::Hash + Eq> Drop for LimiterMap<K> {
+    fn drop(&mut self) {
+        self.adjust_active_keys(-(self.entries.len() as f64));
+    }
+}
+
+// Cluster usage key of the `scope: global` bucket
+const GLOBAL_KEY: &str = "global";
+
 #[derive(Clone)]
 pub struct RateLimiter {
-    limiters: Arc<DashMap<IpAddr, Arc<RateLimiterType>>>,
+    limiters: Arc<LimiterMap<IpAddr>>,
+    global: Option<Arc<RateLimiterType>>, // Set for `scope: global`
     config: RateLimitConfig,
+    cluster: Option<(String, Arc<UsageSync>)>, // Rule name + cluster-wide usage view
 }
 
 impl RateLimiter {
     pub fn new(config: RateLimitConfig) -> Self {
+        let global = (config.scope == RateLimitScope::Global).then(|| {
+            Arc::new(SimpleLimiter::new(config.requests_per_second.max(1), config.burst.max(1)))
+        });
         RateLimiter {
-            limiters: Arc::new(DashMap::new()),
+            limiters: Arc::new(LimiterMap::new(config.idle_ttl_ms)),
+            global,
             config,
+            cluster: None,
         }
     }
 
+    // Shares this rule's buckets with the rest of the cluster
+    pub fn with_cluster(mut self, rule_name: String, sync: Arc<UsageSync>) -> Self {
+        self.cluster = Some((rule_name.clone(), sync.clone()));
+        sync.register(&rule_name, self.clone());
+        self
+    }
+
+    // How long a per-client bucket may sit unused before it is dropped
+    pub fn idle_ttl(&self) -> Duration {
+        self.limiters.idle_ttl
+    }
+
+    // Reports the number of per-key buckets as l4lb_rate_limiter_active_keys
+    pub fn with_metrics(self, rule_name: &str) -> Self {
+        self.limiters.report_to(rule_name, "rate_limit");
+        self
+    }
+
     pub fn check(&self, ip: IpAddr) -> bool {
         if !self.config.enabled {
             return true;
         }
+        if let Some(global) = &self.global {
+            let allowed = global.check_n(1).is_ok();
+            if allowed {
+                self.record_cluster_usage(GLOBAL_KEY);
+            }
+            return allowed;
+        }
         
-        let limiter = self.limiters.entry(ip).or_insert_with(|| {
-            Arc::new(SimpleLimiter::new(
+        let key = self.key_for(ip);
+        let allowed = self.limiter_for(key).check_n(1).is_ok();
+        if allowed && self.cluster.is_some() {
+            self.record_cluster_usage(&key.to_string());
+        }
+        allowed
+    }
+
+    /// Applies `n` tokens consumed for `key` on another node
+    pub fn consume_remote(&self, key: &str, n: u32) {
+        if !self.config.enabled {
+            return;
+        }
+        match (&self.global, key) {
+            (Some(global), GLOBAL_KEY) => global.consume(n),
+            (None, key) => {
+                if let Ok(ip) = key.parse::<IpAddr>() {
+                    self.limiter_for(ip).consume(n);
+                }
+            }
+            _ => {} // Scope differs between nodes' configs
+        }
+    }
+
+    fn limiter_for(&self, key: IpAddr) -> Arc<RateLimiterType> {
+        self.limiters.get_or_insert_with(key, || {
+            SimpleLimiter::new(
                 self.config.requests_per_second.max(1),
                 self.config.burst.max(1)
-            ))
-        }).value().clone();
+            )
+        })
+    }
 
-        limiter.check_n(1).is_ok()
+    fn record_cluster_usage(&self, key: &str) {
+        if let Some((rule_name, sync)) = &self.cluster {
+            sync.record(rule_name, key);
+        }
     }
+
+    // Client IP masked down to the configured subnet prefix, if any
+    fn key_for(&self, ip: IpAddr) -> IpAddr {
+        let prefix = match ip {
+            IpAddr::V4(_) => self.config.key_prefix_v4,
+            IpAddr::V6(_) => self.config.key_prefix_v6,
+        };
+        prefix.and_then(|p| IpNet::new(ip, p).ok()).map(|net| net.network()).unwrap_or(ip)
+    }
+}
+
+/// Bandwidth limiter bucket size when none is configured
+pub const DEFAULT_BANDWIDTH_BURST: u32 = 65536;
+
+// 64KB buffer for smooth throttling by default, and never less than one chunk (or it could never be paid for)
+fn bandwidth_burst(burst_bytes: Option<u32>, chunk_size: usize) -> u32 {
+    burst_bytes.unwrap_or(DEFAULT_BANDWIDTH_BURST).max(chunk_size as u32)
 }
 
 #[derive(Clone)]
 pub struct BandwidthManager {
     config: BandwidthLimitConfig,
-    client_upload: Arc<DashMap<IpAddr, Arc<RateLimiterType>>>,
-    client_download: Arc<DashMap<IpAddr, Arc<RateLimiterType>>>,
-    backend_upload: Arc<DashMap<String, Arc<RateLimiterType>>>,
-    backend_download: Arc<DashMap<String, Arc<RateLimiterType>>>,
+    client_upload: Arc<LimiterMap<IpAddr>>,
+    client_download: Arc<LimiterMap<IpAddr>>,
+    backend_upload: Arc<LimiterMap<String>>,
+    backend_download: Arc<LimiterMap<String>>,
+    rule_upload: Option<Arc<RateLimiterType>>, // Shared by all connections of the rule
+    rule_download: Option<Arc<RateLimiterType>>,
 }
 
 impl BandwidthManager {
     pub fn new(config: BandwidthLimitConfig) -> Self {
+        let ttl = config.idle_ttl_ms;
+        let chunk_size = config.chunk_size.max(1);
+        let aggregate = |rate: Option<u32>| {
+            rate.filter(|_| config.enabled).map(|rate| Arc::new(SimpleLimiter::new(rate.max(1024), bandwidth_burst(None, chunk_size))))
+        };
+        let (rule_upload, rule_download) = (aggregate(config.rule_upload_per_sec), aggregate(config.rule_download_per_sec));
         BandwidthManager {
             config,
-            client_upload: Arc::new(DashMap::new()),
-            client_download: Arc::new(DashMap::new()),
-            backend_upload: Arc::new(DashMap::new()),
-            backend_download: Arc::new(DashMap::new()),
+            client_upload: Arc::new(LimiterMap::new(ttl)),
+            client_download: Arc::new(LimiterMap::new(ttl)),
+            backend_upload: Arc::new(LimiterMap::new(ttl)),
+            backend_download: Arc::new(LimiterMap::new(ttl)),
+            rule_upload,
+            rule_download,
         }
     }
 
+    // Reports the number of limiters per direction as l4lb_rate_limiter_active_keys
+    pub fn with_metrics(self, rule_name: &str) -> Self {
+        self.client_upload.report_to(rule_name, "client_upload");
+        self.client_download.report_to(rule_name, "client_download");
+        self.backend_upload.report_to(rule_name, "backend_upload");
+        self.backend_download.report_to(rule_name, "backend_download");
+        self
+    }
+
     fn get_or_create_limiter<K: std::hash::Hash + Eq + Clone + std::fmt::Display>(
-        map: &Arc<DashMap<K, Arc<RateLimiterType>>>, 
+        &self,
+        map: &LimiterMap<K>,
         key: K, 
         rate_per_sec: u32,
+        burst_bytes: Option<u32>,
         context: &str
     ) -> Arc<RateLimiterType> {
-        if let Some(limiter) = map.get(&key) {
-            return limiter.clone();
-        }
+        map.get_or_insert_with(key.clone(), || {
+            let burst = bandwidth_burst(burst_bytes, self.chunk_size());
+            log::info!("Creating new SimpleLimiter for {} {} with rate {} B/s, burst {} B", context, key, rate_per_sec, burst);
+            SimpleLimiter::new(rate_per_sec.max(1024), burst)
+        })
+    }
 
-        map.entry(key.clone()).or_insert_with(|| {
-            let burst = 65536; // 64KB buffer for smooth throttling 
-            log::info!("Creating new SimpleLimiter for {} {} with rate {} B/s", context, key, rate_per_sec);
-            Arc::new(SimpleLimiter::new(rate_per_sec.max(1024), burst))
-        }).value().clone()
+    pub fn chunk_size(&self) -> usize {
+        self.config.chunk_size.max(1)
+    }
+
+    // Rule-wide client -> backend cap, if configured
+    pub fn rule_upload_limiter(&self) -> Option<Arc<RateLimiterType>> {
+        self.rule_upload.clone()
+    }
+
+    // Rule-wide backend -> client cap, if configured
+    pub fn rule_download_limiter(&self) -> Option<Arc<RateLimiterType>> {
+        self.rule_download.clone()
     }
 
     pub fn get_client_upload_limiter(&self, ip: IpAddr) -> Option<Arc<RateLimiterType>> {
         if !self.config.enabled { return None; }
         let limits = self.config.client.as_ref()?;
-        Some(Self::get_or_create_limiter(&self.client_upload, ip, limits.upload_per_sec, "Client Upload"))
+        Some(self.get_or_create_limiter(&self.client_upload, ip, limits.upload_per_sec, limits.burst_bytes, "Client Upload"))
     }
 
     pub fn get_client_download_limiter(&self, ip: IpAddr) -> Option<Arc<RateLimiterType>> {
          if !self.config.enabled { return None; }
         let limits = self.config.client.as_ref()?;
-        Some(Self::get_or_create_limiter(&self.client_download, ip, limits.download_per_sec, "Client Download"))
+        Some(self.get_or_create_limiter(&self.client_download, ip, limits.download_per_sec, limits.burst_bytes, "Client Download"))
     }
 
     pub fn get_backend_upload_limiter(&self, key: String) -> Option<Arc<RateLimiterType>> {
         if !self.config.enabled { return None; }
         let limits = self.config.backend.as_ref()?;
-        Some(Self::get_or_create_limiter(&self.backend_upload, key, limits.upload_per_sec, "Backend Upload"))
+        Some(self.get_or_create_limiter(&self.backend_upload, key, limits.upload_per_sec, limits.burst_bytes, "Backend Upload"))
     }
 
     pub fn get_backend_download_limiter(&self, key: String) -> Option<Arc<RateLimiterType>> {
         if !self.config.enabled { return None; }
         let limits = self.config.backend.as_ref()?;
-        Some(Self::get_or_create_limiter(&self.backend_download, key, limits.download_per_sec, "Backend Download"))
+        Some(self.get_or_create_limiter(&self.backend_download, key, limits.download_per_sec, limits.burst_bytes, "Backend Download"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    fn config(scope: RateLimitScope) -> RateLimitConfig {
+        RateLimitConfig { enabled: true, requests_per_second: 1, burst: 2, scope, ..Default::default() }
+    }
+
+    fn ip(last: u8) -> IpAddr {
+        IpAddr::V4(Ipv4Addr::new(10, 0, 0, last))
+    }
+
+    #[test]
+    fn test_per_ip_scope() {
+        let limiter = RateLimiter::new(config(RateLimitScope::PerIp));
+        assert!(limiter.check(ip(1)) && limiter.check(ip(1)));
+        assert!(!limiter.check(ip(1)));
+        assert!(limiter.check(ip(2)));
+    }
+
+    #[test]
+    fn test_global_scope_shared_across_ips() {
+        let limiter = RateLimiter::new(config(RateLimitScope::Global));
+        assert!(limiter.check(ip(1)));
+        assert!(limiter.check(ip(2)));
+        assert!(!limiter.check(ip(3)));
+        assert_eq!(limiter.limiters.len(), 0);
+    }
+
+    #[test]
+    fn test_subnet_keys() {
+        let limiter = RateLimiter::new(RateLimitConfig { key_prefix_v4: Some(24), key_prefix_v6: Some(64), ..config(RateLimitScope::PerIp) });
+        assert_eq!(limiter.key_for(ip(77)), ip(0));
+        assert_eq!(limiter.key_for("10.0.1.5".parse().unwrap()), "10.0.1.0".parse::<IpAddr>().unwrap());
+        assert_eq!(limiter.key_for("2001:db8:1:2:aaaa::1".parse().unwrap()), "2001:db8:1:2::".parse::<IpAddr>().unwrap());
+
+        // Same /24 shares the bucket
+        assert!(limiter.check(ip(1)) && limiter.check(ip(2)));
+        assert!(!limiter.check(ip(3)));
+        assert!(limiter.check("10.0.1.1".parse().unwrap()));
+
+        let exact = RateLimiter::new(config(RateLimitScope::PerIp));
+        assert_eq!(exact.key_for(ip(77)), ip(77));
+    }
+
+    #[test]
+    fn test_idle_limiters_swept() {
+        let limiter = RateLimiter::new(RateLimitConfig { idle_ttl_ms: Some(30), ..config(RateLimitScope::PerIp) });
+        assert!(limiter.check(ip(1)) && limiter.check(ip(1)));
+        let held = limiter.limiter_for(ip(2));
+        std::thread::sleep(Duration::from_millis(50));
+        assert!(limiter.check(ip(3)));
+
+        limiter.limiters.sweep();
+        // ip(1) idled out; ip(2) is still referenced; ip(3) was just used
+        assert_eq!(limiter.limiters.len(), 2);
+        assert!(!limiter.limiters.entries.contains_key(&ip(1)));
+        assert!(limiter.limiters.entries.contains_key(&ip(2)));
+        drop(held);
+
+        // Evicted keys come back with a full burst
+        assert!(limiter.check(ip(1)) && limiter.check(ip(1)));
+        assert!(!limiter.check(ip(1)));
+    }
+
+    #[test]
+    fn test_active_keys_gauge() {
+        let gauge = crate::metrics::RATE_LIMITER_ACTIVE_KEYS.with_label_values(&["limiter-gauge-test", "rate_limit"]);
+        let limiter = RateLimiter::new(RateLimitConfig { idle_ttl_ms: Some(30), ..config(RateLimitScope::PerIp) }).with_metrics("limiter-gauge-test");
+        limiter.check(ip(1));
+        limiter.check(ip(1));
+        limiter.check(ip(2));
+        assert_eq!(gauge.get(), 2.0);
+
+        std::thread::sleep(Duration::from_millis(50));
+        limiter.check(ip(3));
+        limiter.limiters.sweep();
+        assert_eq!(gauge.get(), 1.0);
+
+        drop(limiter);
+        assert_eq!(gauge.get(), 0.0);
+    }
+
+    #[test]
+    fn test_rule_bandwidth_limiter_is_shared() {
+        let config: BandwidthLimitConfig = serde_yaml::from_str("{ enabled: true, rule_upload_per_sec: 1048576 }").unwrap();
+        let bw = BandwidthManager::new(config);
+        let (a, b) = (bw.rule_upload_limiter().unwrap(), bw.rule_upload_limiter().unwrap());
+        assert!(Arc::ptr_eq(&a, &b));
+        assert!(bw.rule_download_limiter().is_none());
+        assert!(bw.get_client_upload_limiter(ip(1)).is_none());
+
+        let disabled: BandwidthLimitConfig = serde_yaml::from_str("{ enabled: false, rule_upload_per_sec: 1048576 }").unwrap();
+        assert!(BandwidthManager::new(disabled).rule_upload_limiter().is_none());
+    }
+
+    #[test]
+    fn test_bandwidth_burst() {
+        assert_eq!(bandwidth_burst(None, 16384), DEFAULT_BANDWIDTH_BURST);
+        assert_eq!(bandwidth_burst(Some(1_048_576), 16384), 1_048_576);
+        // Below one chunk a read could never be paid for
+        assert_eq!(bandwidth_burst(Some(4096), 16384), 16384);
+    }
+
+    #[tokio::test]
+    async fn test_until_n_ready_shares_fairly() {
+        // 400 grants of 1000 bytes per second, split across 4 readers
+        let limiter = Arc::new(SimpleLimiter::new(400_000, 1000));
+        let counts: Arc<Vec<std::sync::atomic::AtomicUsize>> = Arc::new((0..4).map(|_| Default::default()).collect());
+        let deadline = tokio::time::Instant::now() + Duration::from_millis(300);
+        let readers: Vec<_> = (0..4).map(|i| {
+            let (limiter, counts) = (limiter.clone(), counts.clone());
+            tokio::spawn(async move {
+                while tokio::time::timeout_at(deadline, limiter.until_n_ready(1000)).await.is_ok() {
+                    counts[i].fetch_add(1, Ordering::Relaxed);
+                }
+            })
+        }).collect();
+        for reader in readers {
+            reader.await.unwrap();
+        }
+
+        let counts: Vec<usize> = counts.iter().map(|c| c.load(Ordering::Relaxed)).collect();
+        let (min, max) = (*counts.iter().min().unwrap(), *counts.iter().max().unwrap());
+        assert!(min >= 10, "counts {:?}", counts);
+        assert!(max - min <= 2, "counts {:?}", counts);
+
+        // Timed-out waiters gave their reservations back: the limiter is usable again
+        sleep(Duration::from_millis(10)).await;
+        assert!(limiter.check_n(1000).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_until_n_ready_holds_rate() {
+        // 100 grants of 1000 bytes per second, plus the initial burst of one
+        let limiter = SimpleLimiter::new(100_000, 1000);
+        let start = Instant::now();
+        for _ in 0..21 {
+            limiter.until_n_ready(1000).await.unwrap();
+        }
+        // Sleep overshoot isn't added on top of each wait; the upper bound leaves
+        // room for a loaded test machine
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(195), "{:?}", elapsed);
+        assert!(elapsed < Duration::from_millis(400), "{:?}", elapsed);
+
+        // A waiter cancelled before its turn hands its reservation back
+        assert!(tokio::time::timeout(Duration::from_millis(1), limiter.until_n_ready(50_000)).await.is_err());
+        sleep(Duration::from_millis(15)).await;
+        assert!(limiter.check_n(1000).is_ok());
     }
 }